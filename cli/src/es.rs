@@ -0,0 +1,78 @@
+//! Drop-in stand-in for Everything's `es.exe`, answering from the UltraSearch index.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    process::ExitCode,
+};
+use ultrasearch_core::{
+    everything::{self, EsAction, EsArgs},
+    indexer,
+};
+
+/// es.exe's "failed to create export file" exit code.
+const EXIT_EXPORT_FAILED: u8 = 5;
+/// es.exe's "Everything is not running" exit code; here, there is no index to search.
+const EXIT_NO_INDEX: u8 = 8;
+
+fn run(args: &EsArgs) -> Result<(), u8> {
+    if args.action == EsAction::Help {
+        print!("{}", everything::HELP);
+        return Ok(());
+    }
+
+    let index_path = indexer::default_index_path().ok_or_else(|| {
+        eprintln!("Error: no data directory to find the index in; set ULTRASEARCH_INDEX");
+        EXIT_NO_INDEX
+    })?;
+    let files = indexer::load_index(&index_path).map_err(|e| {
+        eprintln!("Error: no usable index at {}: {}", index_path.display(), e);
+        EXIT_NO_INDEX
+    })?;
+    let results = args.run(&files).map_err(|e| {
+        eprintln!("Error: {}", e);
+        e.exit_code()
+    })?;
+
+    let stdout = io::stdout();
+    let written = match args.action {
+        EsAction::ResultCount => writeln!(stdout.lock(), "{}", results.len()),
+        EsAction::TotalSize => writeln!(stdout.lock(), "{}", results.iter().map(|file| file.size).sum::<u64>()),
+        _ => match &args.export {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| {
+                    eprintln!("Error: failed to create {}: {}", path.display(), e);
+                    EXIT_EXPORT_FAILED
+                })?;
+                let mut out = BufWriter::new(file);
+                everything::write_results(&mut out, &results, args).and_then(|_| out.flush())
+            }
+            None => {
+                let mut out = BufWriter::new(stdout.lock());
+                everything::write_results(&mut out, &results, args).and_then(|_| out.flush())
+            }
+        },
+    };
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("Error: {}", e);
+            Err(EXIT_EXPORT_FAILED)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match EsArgs::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(e.exit_code());
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
@@ -0,0 +1,622 @@
+//! Command line front end over the same index the UltraSearch app builds and searches.
+
+use std::{
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread,
+    time::Instant,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, Parser, Subcommand};
+use ultrasearch_core::{
+    efu,
+    export::{self, ExportColumn, ExportFormat, ExportOptions},
+    extractor::ExtractorRegistry,
+    indexer::{self, FileMetadata, IndexError},
+    preview::FilePreview,
+    query::{AdvancedFilterOptions, FileQuery, SortKey},
+    rename::{self, RenameJournal, RenameOptions, RenameRule},
+    scanner::{self, ScanEvent, ScanOptions},
+    server::{self, HttpServer, ScanRequest, ServerConfig, ServerContext},
+    volumes::{self, VolumeCatalog, VolumeChange},
+};
+#[cfg(unix)]
+use ultrasearch_core::{
+    desktop,
+    ipc::{self, IpcContext, IpcServer, OpenAction},
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "ultrasearch", version, about = "Search the UltraSearch file index from the command line")]
+struct Cli {
+    /// Index file to use instead of the app's (also settable with ULTRASEARCH_INDEX).
+    #[arg(long, global = true, value_name = "PATH")]
+    index: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Find files whose name or path contains QUERY (case-insensitive).
+    Search(SearchArgs),
+    /// Scan folders (every drive when none are given) and update the index.
+    Scan(ScanArgs),
+    /// Rename the files QUERY finds by applying each --rule in turn; shows the plan unless --apply is given.
+    Rename(RenameArgs),
+    /// Summarize what the index contains.
+    Stats {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Import, list, remove or export Everything file lists (.efu).
+    Efu {
+        #[command(subcommand)]
+        command: EfuCommand,
+    },
+    /// List, re-sync or forget the removable and external volumes in the catalog.
+    Volumes {
+        #[command(subcommand)]
+        command: VolumesCommand,
+    },
+    /// Answer `/search`, `/stats`, `/preview` and `/scan` requests over HTTP on 127.0.0.1,
+    /// and optionally search/open/reveal requests on a Unix socket.
+    Serve(ServeArgs),
+}
+
+#[derive(Debug, Args)]
+struct SearchArgs {
+    #[arg(default_value = "")]
+    query: String,
+    /// Only files with this extension.
+    #[arg(long, value_name = "EXT")]
+    ext: Option<String>,
+    #[arg(long, value_name = "BYTES")]
+    min_size: Option<u64>,
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<u64>,
+    /// name, path, size, modified or extension.
+    #[arg(long, value_name = "KEY")]
+    sort: Option<SortKey>,
+    /// Sort in descending order.
+    #[arg(long, requires = "sort")]
+    desc: bool,
+    /// Maximum number of results; 0 for no limit.
+    #[arg(long, default_value_t = 0)]
+    limit: usize,
+    #[command(flatten)]
+    format: FormatArgs,
+    #[command(flatten)]
+    export: ExportArgs,
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// Write every result to FILE (`-` for stdout) instead of listing them.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["json", "null", "table"])]
+    export: Option<PathBuf>,
+    /// csv, json, jsonl, paths, m3u or efu; guessed from the file name by default.
+    #[arg(long, value_name = "FORMAT", requires = "export")]
+    export_format: Option<ExportFormat>,
+    /// Comma-separated: path, name, directory, extension, size, modified, volume, offline.
+    #[arg(long, value_name = "LIST", value_delimiter = ',', requires = "export")]
+    columns: Vec<ExportColumn>,
+}
+
+#[derive(Debug, Args)]
+#[group(multiple = false)]
+struct FormatArgs {
+    /// One JSON object per line.
+    #[arg(long)]
+    json: bool,
+    /// Separate paths with NUL instead of newlines (for `xargs -0`).
+    #[arg(short = '0', long)]
+    null: bool,
+    /// Size, modification time and path in aligned columns.
+    #[arg(long)]
+    table: bool,
+}
+
+#[derive(Debug, Args)]
+struct ScanArgs {
+    roots: Vec<PathBuf>,
+    /// Also index the files inside zip, tar and 7z archives.
+    #[arg(long)]
+    archives: bool,
+}
+
+#[derive(Debug, Args)]
+struct RenameArgs {
+    #[arg(required_unless_present = "undo")]
+    query: Option<String>,
+    /// Only files with this extension.
+    #[arg(long, value_name = "EXT")]
+    ext: Option<String>,
+    /// `s/PATTERN/REPLACEMENT/[i]`, `lower`, `upper`, `title`, `number[:start=1,step=1,width=3,at=end,sep=_]`
+    /// or `date[:format=%Y%m%d,at=start,sep=_]`.
+    #[arg(long = "rule", value_name = "RULE", required_unless_present = "undo")]
+    rules: Vec<RenameRule>,
+    /// Let the rules change extensions too.
+    #[arg(long)]
+    include_extension: bool,
+    /// Order the files (and so their numbers) by name, path, size, modified or extension.
+    #[arg(long, value_name = "KEY")]
+    sort: Option<SortKey>,
+    #[arg(long, requires = "sort")]
+    desc: bool,
+    /// Rename the files instead of only showing what would change.
+    #[arg(long)]
+    apply: bool,
+    /// Print the plan as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Put back the names the last applied rename changed.
+    #[arg(long, conflicts_with_all = ["query", "rules", "apply", "json"])]
+    undo: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum EfuCommand {
+    /// Add file lists to the index as read-only sources (re-importing one replaces it).
+    Import { files: Vec<PathBuf> },
+    /// Show the imported file lists and how many entries each holds.
+    List,
+    /// Drop an imported file list from the index.
+    Remove { file: PathBuf },
+    /// Write the index, or everything under ROOT, as a file list.
+    Export {
+        file: PathBuf,
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum VolumesCommand {
+    /// Show every volume seen so far, whether it's plugged in and how many files it holds.
+    List {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Take volumes that went away offline and re-sync the ones that came back.
+    Sync {
+        /// Also index the files inside zip, tar and 7z archives.
+        #[arg(long)]
+        archives: bool,
+    },
+    /// Drop a volume and its files from the catalog and the index.
+    Forget { id: String },
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    #[arg(long, default_value_t = server::DEFAULT_PORT)]
+    port: u16,
+    /// Require this bearer token on every request.
+    #[arg(long, env = "ULTRASEARCH_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Also answer JSON-lines requests on a Unix socket (default: $XDG_RUNTIME_DIR/ultrasearch.sock).
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    socket: Option<Option<PathBuf>>,
+}
+
+fn index_path(cli: &Cli) -> Result<PathBuf> {
+    cli.index.clone().or_else(indexer::default_index_path).ok_or_else(|| anyhow!("No data directory; pass --index"))
+}
+
+fn load(index_path: &Path) -> Result<Vec<FileMetadata>> {
+    match indexer::load_index(index_path) {
+        Err(IndexError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Err(anyhow!(
+            "No index at {}; run `ultrasearch scan` or scan from the app first",
+            index_path.display()
+        )),
+        result => result.with_context(|| format!("Failed to read index {}", index_path.display())),
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", size) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn write_results(out: &mut impl Write, results: &[&FileMetadata], format: &FormatArgs) -> io::Result<()> {
+    if format.table && !results.is_empty() {
+        let size_width = results.iter().map(|file| format_size(file.size).len()).max().unwrap_or(0);
+        writeln!(out, "{:>size_width$}  {:<16}  Path", "Size", "Modified")?;
+        for file in results {
+            let offline = if file.source.is_some() { "  (offline)" } else { "" };
+            writeln!(out, "{:>size_width$}  {:<16}  {}{}", format_size(file.size), format_time(file.modified_time), file.path, offline)?;
+        }
+        return Ok(());
+    }
+    for file in results {
+        if format.json {
+            serde_json::to_writer(&mut *out, file)?;
+            out.write_all(b"\n")?;
+        } else {
+            out.write_all(file.path.as_bytes())?;
+            out.write_all(if format.null { b"\0" } else { b"\n" })?;
+        }
+    }
+    Ok(())
+}
+
+fn search(index_path: &Path, args: SearchArgs) -> Result<ExitCode> {
+    let files = load(index_path)?;
+    let filters = AdvancedFilterOptions { file_type: args.ext, min_size: args.min_size, max_size: args.max_size };
+    let mut query = FileQuery::new(&args.query).with_filters(filters);
+    if let Some(key) = args.sort {
+        query = query.with_sort(key, args.desc);
+    }
+    let limit = if args.limit == 0 { usize::MAX } else { args.limit };
+    let results = query.search(&files, 0, limit);
+    if let Some(export_path) = &args.export.export {
+        return export(export_path, &results, args.export.export_format, args.export.columns);
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    match write_results(&mut out, &results, &args.format).and_then(|()| out.flush()) {
+        // `ultrasearch search ... | head` closes the pipe early; that's not an error.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        result => result?,
+    }
+    Ok(if results.is_empty() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+fn export(export_path: &Path, results: &[&FileMetadata], format: Option<ExportFormat>, columns: Vec<ExportColumn>) -> Result<ExitCode> {
+    let format = match format.or_else(|| ExportFormat::from_path(export_path)) {
+        Some(format) => format,
+        None if export_path == Path::new("-") => ExportFormat::Paths,
+        None => return Err(anyhow!("Cannot tell the format of {}; pass --export-format", export_path.display())),
+    };
+    let options = ExportOptions { format, columns };
+    if export_path == Path::new("-") {
+        let mut out = BufWriter::new(io::stdout().lock());
+        match export::write_export(&mut out, results.iter().copied(), &options).and_then(|_| out.flush()) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    } else {
+        let count = export::export_to_file(export_path, results.iter().copied(), &options)
+            .with_context(|| format!("Failed to export to {}", export_path.display()))?;
+        eprintln!("Exported {} results to {}", count, export_path.display());
+    }
+    Ok(if results.is_empty() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Canonical scan roots; every drive when none are given.
+fn resolve_roots(roots: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    if roots.is_empty() {
+        return Ok(scanner::available_drives().map_err(|e| anyhow!(e))?.into_iter().map(PathBuf::from).collect());
+    }
+    roots
+        .iter()
+        .map(|root| root.canonicalize().with_context(|| format!("Cannot scan {}", root.display())))
+        .collect()
+}
+
+fn scan_roots(roots: &[PathBuf], options: &ScanOptions) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    for root in roots {
+        let scan_start = Instant::now();
+        let found = scanner::scan(root, options, |event| {
+            if let ScanEvent::File(file) = event {
+                files.push(file);
+            }
+            true
+        });
+        eprintln!("Scanned {}: {} files in {:.2?}", root.display(), found, scan_start.elapsed());
+    }
+    files
+}
+
+/// The saved index, or an empty one when there is none yet.
+fn load_or_empty(index_path: &Path) -> Vec<FileMetadata> {
+    match indexer::load_index(index_path) {
+        Ok(files) => files,
+        Err(IndexError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            eprintln!("Starting a new index; the existing one can't be read: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn scan(index_path: &Path, args: ScanArgs) -> Result<ExitCode> {
+    let roots = resolve_roots(&args.roots)?;
+    // Rescanning a folder replaces what the index held for it and keeps everything else.
+    let mut files = load_or_empty(index_path);
+    let options = ScanOptions { index_archives: args.archives };
+    let scanned = scan_roots(&roots, &options);
+    indexer::replace_roots(&mut files, &roots, scanned);
+    sync_volumes(index_path, &mut files, &options)?;
+
+    indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+    eprintln!("Index {} now holds {} files", index_path.display(), files.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn rename(index_path: &Path, args: RenameArgs) -> Result<ExitCode> {
+    let journal_path = RenameJournal::path_for(index_path);
+    let mut journal = RenameJournal::load(&journal_path).with_context(|| format!("Failed to read {}", journal_path.display()))?;
+    let mut files = load(index_path)?;
+    if args.undo {
+        let undone = journal.undo_last()?;
+        rename::update_index(&mut files, &undone);
+        indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+        journal.save(&journal_path).with_context(|| format!("Failed to write {}", journal_path.display()))?;
+        eprintln!("Restored {} names", undone.len());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let filters = AdvancedFilterOptions { file_type: args.ext, min_size: None, max_size: None };
+    let mut query = FileQuery::new(args.query.as_deref().unwrap_or_default()).with_filters(filters);
+    if let Some(key) = args.sort {
+        query = query.with_sort(key, args.desc);
+    }
+    let paths: Vec<String> = query.search(&files, 0, usize::MAX).into_iter().map(|file| file.path.clone()).collect();
+    let options = RenameOptions { rules: args.rules, include_extension: args.include_extension };
+    let plan = rename::plan(&files, &paths, &options)?;
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &plan)?;
+        writeln!(out)?;
+    } else {
+        for planned in plan.renames.iter().filter(|planned| planned.to != planned.from || planned.conflict.is_some()) {
+            let new_name = Path::new(&planned.to).file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            match &planned.conflict {
+                Some(conflict) => writeln!(out, "{} -> {}  [{}]", planned.from, new_name, conflict)?,
+                None => writeln!(out, "{} -> {}", planned.from, new_name)?,
+            }
+        }
+    }
+    let (changes, conflicts) = (plan.changes().count(), plan.conflicts());
+    if conflicts > 0 {
+        eprintln!("{} of {} files can't be renamed as planned; nothing was changed", conflicts, plan.renames.len());
+        return Ok(ExitCode::FAILURE);
+    }
+    if !args.apply {
+        eprintln!("{} files would be renamed; pass --apply to rename them", changes);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let renamed = rename::apply(&plan)?;
+    rename::update_index(&mut files, &renamed);
+    indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+    eprintln!("Renamed {} files; `ultrasearch rename --undo` puts them back", renamed.len());
+    journal.record(renamed);
+    journal.save(&journal_path).with_context(|| format!("Failed to write {}", journal_path.display()))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Brings the volume catalog next to the index up to date with what's mounted and reports what changed.
+fn sync_volumes(index_path: &Path, files: &mut Vec<FileMetadata>, options: &ScanOptions) -> Result<()> {
+    let catalog_path = VolumeCatalog::path_for(index_path);
+    let mut catalog = VolumeCatalog::load(&catalog_path).with_context(|| format!("Failed to read {}", catalog_path.display()))?;
+    let shared = Mutex::new(std::mem::take(files));
+    let changes = catalog.refresh(&shared, &volumes::mounted_volumes(), options);
+    *files = shared.into_inner().unwrap_or_else(|p| p.into_inner());
+    for change in &changes {
+        match change {
+            VolumeChange::Added { id } => eprintln!("New volume {}", id),
+            VolumeChange::Offline { id, files } => eprintln!("Volume {} went offline; its {} files stay searchable", id, files),
+            VolumeChange::Resynced { id, diff } => eprintln!(
+                "Volume {} re-synced: {} added, {} changed, {} removed",
+                id, diff.added, diff.changed, diff.removed
+            ),
+        }
+    }
+    catalog.save(&catalog_path).with_context(|| format!("Failed to write {}", catalog_path.display()))
+}
+
+fn volumes(index_path: &Path, command: VolumesCommand) -> Result<ExitCode> {
+    let catalog_path = VolumeCatalog::path_for(index_path);
+    match command {
+        VolumesCommand::List { json } => {
+            let catalog = VolumeCatalog::load(&catalog_path).with_context(|| format!("Failed to read {}", catalog_path.display()))?;
+            let mut out = io::stdout().lock();
+            if json {
+                serde_json::to_writer_pretty(&mut out, &catalog.volumes)?;
+                writeln!(out)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            let files = load_or_empty(index_path);
+            for known in &catalog.volumes {
+                let count = files.iter().filter(|file| file.volume.as_deref() == Some(known.volume.id.as_str())).count();
+                writeln!(
+                    out,
+                    "{:<7}  {:>8}  {:<16}  {}  {}",
+                    if known.online { "online" } else { "offline" },
+                    count,
+                    format_time(known.last_seen),
+                    known.volume.label.as_deref().unwrap_or(&known.volume.id),
+                    known.volume.mount_point.display()
+                )?;
+            }
+        }
+        VolumesCommand::Sync { archives } => {
+            let mut files = load_or_empty(index_path);
+            sync_volumes(index_path, &mut files, &ScanOptions { index_archives: archives })?;
+            indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+        }
+        VolumesCommand::Forget { id } => {
+            let mut catalog = VolumeCatalog::load(&catalog_path).with_context(|| format!("Failed to read {}", catalog_path.display()))?;
+            let mut files = load_or_empty(index_path);
+            let before = files.len();
+            if !catalog.forget(&mut files, &id) {
+                return Err(anyhow!("No volume {} in the catalog", id));
+            }
+            indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+            catalog.save(&catalog_path).with_context(|| format!("Failed to write {}", catalog_path.display()))?;
+            eprintln!("Forgot volume {} and its {} files", id, before - files.len());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn stats(index_path: &Path, json: bool) -> Result<ExitCode> {
+    let files = load(index_path)?;
+    let stats = indexer::index_stats(&files);
+    let mut out = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &stats)?;
+        writeln!(out)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let updated = std::fs::metadata(index_path).and_then(|meta| meta.modified()).ok().map(DateTime::<Utc>::from);
+    writeln!(out, "Index:           {}", index_path.display())?;
+    if let Some(updated) = updated {
+        writeln!(out, "Last saved:      {}", format_time(updated))?;
+    }
+    writeln!(out, "Files:           {}", stats.file_count)?;
+    writeln!(out, "Archive entries: {}", stats.archive_entries)?;
+    if stats.imported_entries > 0 {
+        writeln!(out, "Imported:        {}", stats.imported_entries)?;
+    }
+    writeln!(out, "Total size:      {}", format_size(stats.total_size))?;
+    if let Some(newest) = stats.newest {
+        writeln!(out, "Newest file:     {}", format_time(newest))?;
+    }
+    if !stats.top_extensions.is_empty() {
+        writeln!(out, "Top extensions:")?;
+        for (extension, count) in &stats.top_extensions {
+            writeln!(out, "  .{:<12} {}", extension, count)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn efu(index_path: &Path, command: EfuCommand) -> Result<ExitCode> {
+    match command {
+        EfuCommand::Import { files: lists } => {
+            let mut files = load_or_empty(index_path);
+            for list in &lists {
+                let (source, imported) = efu::import_efu(list).with_context(|| format!("Failed to import {}", list.display()))?;
+                eprintln!("Imported {} entries from {}", imported.len(), source);
+                indexer::replace_source(&mut files, &source, imported);
+            }
+            indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+        }
+        EfuCommand::List => {
+            let mut out = io::stdout().lock();
+            let sources = indexer::sources(&load(index_path)?);
+            for (source, count) in sources.iter().filter(|(source, _)| !source.starts_with(volumes::OFFLINE_SOURCE_PREFIX)) {
+                writeln!(out, "{:>10}  {}", count, source)?;
+            }
+        }
+        EfuCommand::Remove { file } => {
+            let source = file.canonicalize().unwrap_or(file).to_string_lossy().into_owned();
+            let mut files = load(index_path)?;
+            let before = files.len();
+            indexer::replace_source(&mut files, &source, Vec::new());
+            if files.len() == before {
+                return Err(anyhow!("{} is not an imported file list", source));
+            }
+            indexer::save_index(index_path, &files).with_context(|| format!("Failed to write index {}", index_path.display()))?;
+            eprintln!("Removed {} entries from {}", before - files.len(), source);
+        }
+        EfuCommand::Export { file, root } => {
+            let root = root.map(|root| root.canonicalize().with_context(|| format!("Cannot export {}", root.display()))).transpose()?;
+            let files = load(index_path)?;
+            let options = ExportOptions { format: ExportFormat::Efu, columns: Vec::new() };
+            let count = export::export_to_file(&file, efu::under_root(&files, root.as_deref()), &options)
+                .with_context(|| format!("Failed to export to {}", file.display()))?;
+            eprintln!("Exported {} entries to {}", count, file.display());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn serve(index_path: &Path, args: ServeArgs) -> Result<ExitCode> {
+    let files = Arc::new(Mutex::new(load_or_empty(index_path)));
+    let scanning = Arc::new(AtomicBool::new(false));
+
+    let on_scan = {
+        let (files, scanning, index_path) = (files.clone(), scanning.clone(), index_path.to_path_buf());
+        move |request: ScanRequest| {
+            let roots = resolve_roots(&request.roots).map_err(|e| format!("{:#}", e))?;
+            if scanning.swap(true, Ordering::SeqCst) {
+                return Err("a scan is already running".to_string());
+            }
+            let (files, scanning, index_path) = (files.clone(), scanning.clone(), index_path.clone());
+            thread::spawn(move || {
+                let scanned = scan_roots(&roots, &request.options);
+                let mut files = match files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+                indexer::replace_roots(&mut files, &roots, scanned);
+                if let Err(e) = indexer::save_index(&index_path, &files) {
+                    eprintln!("Failed to write index {}: {}", index_path.display(), e);
+                }
+                scanning.store(false, Ordering::SeqCst);
+            });
+            Ok(())
+        }
+    };
+    #[cfg(unix)]
+    let _ipc = match &args.socket {
+        Some(socket) => {
+            let socket = socket.clone().unwrap_or_else(ipc::default_socket_path);
+            let ctx = IpcContext {
+                files: files.clone(),
+                on_open: Box::new(|path, action| {
+                    match action {
+                        OpenAction::Open => desktop::open(path),
+                        OpenAction::Reveal => desktop::reveal(path),
+                    }
+                    .map_err(|e| e.to_string())
+                }),
+            };
+            let ipc = IpcServer::start(&socket, Arc::new(ctx)).with_context(|| format!("Cannot listen on {}", socket.display()))?;
+            eprintln!("Answering IPC requests on {}", socket.display());
+            Some(ipc)
+        }
+        None => None,
+    };
+    let ctx = ServerContext {
+        files,
+        preview: Arc::new(FilePreview::new().with_extractors(Arc::new(ExtractorRegistry::with_defaults()))),
+        scanning,
+        on_scan: Box::new(on_scan),
+    };
+
+    let server = HttpServer::start(ServerConfig { port: args.port, token: args.token }, Arc::new(ctx))
+        .with_context(|| format!("Cannot listen on port {}", args.port))?;
+    eprintln!("Serving {} on http://{} (Ctrl+C to stop)", index_path.display(), server.local_addr());
+    loop {
+        thread::park();
+    }
+}
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error")).init();
+
+    let cli = Cli::parse();
+    let result = index_path(&cli).and_then(|index_path| match cli.command {
+        Command::Search(args) => search(&index_path, args),
+        Command::Scan(args) => scan(&index_path, args),
+        Command::Rename(args) => rename(&index_path, args),
+        Command::Stats { json } => stats(&index_path, json),
+        Command::Efu { command } => efu(&index_path, command),
+        Command::Volumes { command } => volumes(&index_path, command),
+        Command::Serve(args) => serve(&index_path, args),
+    });
+    result.unwrap_or_else(|e| {
+        eprintln!("ultrasearch: {:#}", e);
+        ExitCode::from(2)
+    })
+}
@@ -0,0 +1,120 @@
+#!/usr/bin/env python3
+"""Reference client for UltraSearch's Unix socket IPC (protocol version 1).
+
+Each request is one JSON object per line, and so is each response:
+
+    {"v": 1, "id": 1, "method": "search", "params": {"query": "report", "limit": 20}}
+    {"v": 1, "id": 1, "ok": true, "result": {"total": 3, "offset": 0, "limit": 20, "results": [...]}}
+
+Usage:
+    ultrasearch-ipc.py search QUERY [--ext EXT] [--sort KEY] [--desc] [--limit N] [--json]
+    ultrasearch-ipc.py open PATH
+    ultrasearch-ipc.py reveal PATH
+    ultrasearch-ipc.py hello
+
+Only the standard library is needed, so launcher scripts (Rofi, Ulauncher, Albert...) can copy
+`Client` as is.
+"""
+
+import argparse
+import json
+import os
+import socket
+import sys
+
+PROTOCOL_VERSION = 1
+
+
+def default_socket_path():
+    if os.environ.get("ULTRASEARCH_SOCKET"):
+        return os.environ["ULTRASEARCH_SOCKET"]
+    runtime_dir = os.environ.get("XDG_RUNTIME_DIR")
+    if runtime_dir:
+        return os.path.join(runtime_dir, "ultrasearch.sock")
+    return os.path.join("/tmp", "ultrasearch-%s.sock" % os.environ.get("USER", ""))
+
+
+class IpcError(Exception):
+    def __init__(self, code, message):
+        super().__init__("%s (%s)" % (message, code))
+        self.code = code
+
+
+class Client:
+    def __init__(self, path=None):
+        self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        self.sock.connect(path or default_socket_path())
+        self.reader = self.sock.makefile("r", encoding="utf-8")
+        self.next_id = 1
+
+    def call(self, method, params=None):
+        request = {"v": PROTOCOL_VERSION, "id": self.next_id, "method": method, "params": params or {}}
+        self.next_id += 1
+        self.sock.sendall((json.dumps(request) + "\n").encode("utf-8"))
+        line = self.reader.readline()
+        if not line:
+            raise IpcError("closed", "connection closed by the server")
+        response = json.loads(line)
+        if response.get("ok"):
+            return response["result"]
+        error = response.get("error", {})
+        raise IpcError(error.get("code", "unknown"), error.get("message", ""))
+
+    def search(self, query, **params):
+        return self.call("search", dict(params, query=query))
+
+    def open(self, path):
+        self.call("open", {"path": path})
+
+    def reveal(self, path):
+        self.call("reveal", {"path": path})
+
+    def close(self):
+        self.reader.close()
+        self.sock.close()
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Talk to a running UltraSearch over its Unix socket.")
+    parser.add_argument("--socket", help="socket path (default: $ULTRASEARCH_SOCKET or $XDG_RUNTIME_DIR/ultrasearch.sock)")
+    commands = parser.add_subparsers(dest="command", required=True)
+    search = commands.add_parser("search", help="print matching paths")
+    search.add_argument("query", nargs="?", default="")
+    search.add_argument("--ext")
+    search.add_argument("--sort", choices=["name", "path", "size", "modified", "extension"])
+    search.add_argument("--desc", action="store_true")
+    search.add_argument("--limit", type=int, default=50)
+    search.add_argument("--json", action="store_true", help="one JSON object per result")
+    for name in ("open", "reveal"):
+        commands.add_parser(name, help="%s an indexed file" % name).add_argument("path")
+    commands.add_parser("hello", help="show the server version")
+    args = parser.parse_args()
+
+    try:
+        client = Client(args.socket)
+    except OSError as e:
+        print("ultrasearch-ipc: cannot connect: %s" % e, file=sys.stderr)
+        return 2
+    try:
+        if args.command == "search":
+            params = {"limit": args.limit, "descending": args.desc}
+            if args.ext:
+                params["ext"] = args.ext
+            if args.sort:
+                params["sort"] = args.sort
+            for result in client.search(args.query, **params)["results"]:
+                print(json.dumps(result) if args.json else result["path"])
+        elif args.command == "hello":
+            print(json.dumps(client.call("hello")))
+        else:
+            getattr(client, args.command)(os.path.abspath(args.path))
+    except IpcError as e:
+        print("ultrasearch-ipc: %s" % e, file=sys.stderr)
+        return 1
+    finally:
+        client.close()
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
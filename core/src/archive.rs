@@ -0,0 +1,263 @@
+//! Listing and reading zip, tar(.gz) and 7z archives, and the `archive!/entry` virtual paths for their contents.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use flate2::read::GzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use zip::ZipArchive;
+
+/// Separates the archive's own path from the entry path in a virtual path,
+/// e.g. `/data/backup.zip!/docs/readme.txt`.
+pub const ARCHIVE_SEPARATOR: &str = "!/";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    SevenZ,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    /// Unknown for tar entries, which are never compressed individually.
+    pub compressed_size: Option<u64>,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub modified: Option<DateTime<Utc>>,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("not a supported archive")]
+    Unsupported,
+    #[error("entry '{0}' not found in archive")]
+    EntryNotFound(String),
+    #[error("entry is {size} bytes, over the {limit} byte limit")]
+    TooLarge { size: u64, limit: u64 },
+    #[error("failed to read archive: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed archive: {0}")]
+    Malformed(String),
+}
+
+impl From<zip::result::ZipError> for ArchiveError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ArchiveError::Malformed(e.to_string())
+    }
+}
+
+impl From<sevenz_rust::Error> for ArchiveError {
+    fn from(e: sevenz_rust::Error) -> Self {
+        ArchiveError::Malformed(e.to_string())
+    }
+}
+
+pub fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".zip") || name.ends_with(".jar") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".7z") {
+        Some(ArchiveKind::SevenZ)
+    } else {
+        None
+    }
+}
+
+pub fn virtual_path(archive: &str, entry: &str) -> String {
+    format!("{}{}{}", archive, ARCHIVE_SEPARATOR, entry)
+}
+
+/// Splits `archive.zip!/dir/file.txt` into the archive path and the entry name.
+pub fn split_virtual_path(path: &str) -> Option<(&str, &str)> {
+    path.match_indices(ARCHIVE_SEPARATOR)
+        .map(|(index, _)| (&path[..index], &path[index + ARCHIVE_SEPARATOR.len()..]))
+        .find(|(archive, entry)| !entry.is_empty() && archive_kind(Path::new(archive)).is_some())
+}
+
+fn normalize_entry_name(name: &str) -> String {
+    name.trim_start_matches("./").replace('\\', "/")
+}
+
+fn tar_reader(path: &Path, kind: ArchiveKind) -> Result<tar::Archive<Box<dyn Read>>, ArchiveError> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn zip_datetime(time: zip::DateTime) -> Option<DateTime<Utc>> {
+    NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+        .and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())
+        .map(|naive| naive.and_utc())
+}
+
+/// Lists the entries of an archive without extracting anything.
+pub fn list_entries(path: &Path) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let kind = archive_kind(path).ok_or(ArchiveError::Unsupported)?;
+    let mut entries = Vec::new();
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+            for index in 0..archive.len() {
+                let entry = archive.by_index_raw(index)?;
+                entries.push(ArchiveEntry {
+                    name: normalize_entry_name(entry.name()),
+                    size: entry.size(),
+                    compressed_size: Some(entry.compressed_size()),
+                    modified: entry.last_modified().and_then(zip_datetime),
+                    is_dir: entry.is_dir(),
+                });
+            }
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let mut archive = tar_reader(path, kind)?;
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let header = entry.header();
+                entries.push(ArchiveEntry {
+                    name: normalize_entry_name(&entry.path()?.to_string_lossy()),
+                    size: header.size()?,
+                    compressed_size: None,
+                    modified: header.mtime().ok().and_then(|secs| DateTime::from_timestamp(secs as i64, 0)),
+                    is_dir: header.entry_type().is_dir(),
+                });
+            }
+        }
+        ArchiveKind::SevenZ => {
+            let reader = SevenZReader::open(path, Password::empty())?;
+            for entry in &reader.archive().files {
+                entries.push(ArchiveEntry {
+                    name: normalize_entry_name(entry.name()),
+                    size: entry.size(),
+                    // Files in a solid block share one compressed stream and report 0.
+                    compressed_size: Some(entry.compressed_size).filter(|&size| size > 0),
+                    modified: entry
+                        .has_last_modified_date
+                        .then(|| DateTime::from_timestamp(entry.last_modified_date().to_unix_time(), 0))
+                        .flatten(),
+                    is_dir: entry.is_directory(),
+                });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn read_limited(reader: &mut dyn Read, size: u64, limit: u64) -> Result<Vec<u8>, ArchiveError> {
+    if size > limit {
+        return Err(ArchiveError::TooLarge { size, limit });
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    reader.take(limit).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Decompresses a single entry into memory, refusing entries larger than `limit` bytes.
+pub fn read_entry(path: &Path, name: &str, limit: u64) -> Result<Vec<u8>, ArchiveError> {
+    let kind = archive_kind(path).ok_or(ArchiveError::Unsupported)?;
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+            let mut entry = match archive.by_name(name) {
+                Ok(entry) => entry,
+                Err(zip::result::ZipError::FileNotFound) => return Err(ArchiveError::EntryNotFound(name.to_string())),
+                Err(e) => return Err(e.into()),
+            };
+            let size = entry.size();
+            read_limited(&mut entry, size, limit)
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let mut archive = tar_reader(path, kind)?;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if normalize_entry_name(&entry.path()?.to_string_lossy()) == name {
+                    let size = entry.header().size()?;
+                    return read_limited(&mut entry, size, limit);
+                }
+            }
+            Err(ArchiveError::EntryNotFound(name.to_string()))
+        }
+        ArchiveKind::SevenZ => {
+            let mut reader = SevenZReader::open(path, Password::empty())?;
+            let mut found = None;
+            reader.for_each_entries(|entry, data| {
+                if normalize_entry_name(entry.name()) != name {
+                    // Solid archives must be decoded in order, so skipped entries are still drained.
+                    std::io::copy(data, &mut std::io::sink())?;
+                    return Ok(true);
+                }
+                found = Some(read_limited(data, entry.size(), limit));
+                Ok(false)
+            })?;
+            found.unwrap_or_else(|| Err(ArchiveError::EntryNotFound(name.to_string())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_split_virtual_path() {
+        assert_eq!(split_virtual_path("/a/b.zip!/dir/c.txt"), Some(("/a/b.zip", "dir/c.txt")));
+        assert_eq!(split_virtual_path("/odd!/name.tar.gz!/x"), Some(("/odd!/name.tar.gz", "x")));
+        assert_eq!(split_virtual_path("/a/plain!/file.txt"), None);
+    }
+
+    #[test]
+    fn test_zip_and_tar_gz_entries() {
+        let temp_dir = tempdir().unwrap();
+
+        let zip_path = temp_dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.add_directory("docs/", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.start_file("docs/readme.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"zipped readme").unwrap();
+        writer.finish().unwrap();
+
+        let entries = list_entries(&zip_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].name, "docs/readme.txt");
+        assert_eq!(entries[1].size, 13);
+        assert_eq!(read_entry(&zip_path, "docs/readme.txt", 1024).unwrap(), b"zipped readme");
+        assert!(matches!(read_entry(&zip_path, "docs/readme.txt", 4), Err(ArchiveError::TooLarge { .. })));
+        assert!(matches!(read_entry(&zip_path, "missing", 1024), Err(ArchiveError::EntryNotFound(_))));
+
+        let tgz_path = temp_dir.path().join("logs.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(File::create(&tgz_path).unwrap(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(9);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, "app/today.log", &b"log lines"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let entries = list_entries(&tgz_path).unwrap();
+        assert_eq!(entries[0].name, "app/today.log");
+        assert_eq!(entries[0].modified.unwrap().timestamp(), 1_700_000_000);
+        assert_eq!(read_entry(&tgz_path, "app/today.log", 1024).unwrap(), b"log lines");
+    }
+}
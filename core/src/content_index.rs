@@ -0,0 +1,783 @@
+//! Inverted index over file contents for instant full-text queries, kept fresh by a filesystem watcher.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use log::{debug, error, trace};
+use memmap2::Mmap;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+const SNIPPET_MAX_CHARS: usize = 200;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContentIndexConfig {
+    pub roots: Vec<PathBuf>,
+    pub extensions: Vec<String>,
+    pub max_file_size: u64,
+}
+
+impl Default for ContentIndexConfig {
+    fn default() -> Self {
+        let extensions = [
+            "txt", "md", "markdown", "log", "csv", "json", "xml", "yaml", "yml", "toml", "ini",
+            "cfg", "conf", "rs", "py", "js", "ts", "tsx", "jsx", "java", "c", "h", "cpp", "hpp",
+            "cs", "go", "rb", "php", "sh", "bat", "ps1", "sql", "html", "htm", "css", "vue",
+        ];
+        ContentIndexConfig {
+            roots: Vec::new(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+            max_file_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl ContentIndexConfig {
+    /// Whether a file should be in the index. An empty `roots` list accepts any location.
+    pub fn accepts(&self, path: &Path, size: u64) -> bool {
+        if size > self.max_file_size {
+            return false;
+        }
+        if !self.roots.is_empty() && !self.roots.iter().any(|root| path.starts_with(root)) {
+            return false;
+        }
+        match path.extension().and_then(|os| os.to_str()) {
+            Some(ext) => self.extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(ext)),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub term: String,
+    pub position: u32,
+    pub line: u32,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF |   // Hiragana, Katakana
+        0x3400..=0x4DBF |   // CJK Extension A
+        0x4E00..=0x9FFF |   // CJK Unified Ideographs
+        0xAC00..=0xD7AF |   // Hangul Syllables
+        0xF900..=0xFAFF |   // CJK Compatibility Ideographs
+        0x20000..=0x2A6DF)  // CJK Extension B
+}
+
+/// Splits text into lowercase words, and runs of CJK characters into overlapping bigrams
+/// (a lone CJK character becomes a unigram). Lines are 1-based.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position: u32 = 0;
+    let mut word = String::new();
+    let mut cjk_run: Vec<char> = Vec::new();
+
+    fn flush_word(word: &mut String, line: u32, position: &mut u32, tokens: &mut Vec<Token>) {
+        if !word.is_empty() {
+            tokens.push(Token { term: std::mem::take(word), position: *position, line });
+            *position += 1;
+        }
+    }
+
+    fn flush_cjk(run: &mut Vec<char>, line: u32, position: &mut u32, tokens: &mut Vec<Token>) {
+        if run.len() == 1 {
+            tokens.push(Token { term: run[0].to_string(), position: *position, line });
+            *position += 1;
+        } else {
+            for pair in run.windows(2) {
+                tokens.push(Token { term: pair.iter().collect(), position: *position, line });
+                *position += 1;
+            }
+        }
+        run.clear();
+    }
+
+    for (line_index, line_text) in text.lines().enumerate() {
+        let line = line_index as u32 + 1;
+        for c in line_text.chars() {
+            if is_cjk(c) {
+                flush_word(&mut word, line, &mut position, &mut tokens);
+                cjk_run.push(c);
+            } else if c.is_alphanumeric() || c == '_' {
+                flush_cjk(&mut cjk_run, line, &mut position, &mut tokens);
+                word.extend(c.to_lowercase());
+            } else {
+                flush_word(&mut word, line, &mut position, &mut tokens);
+                flush_cjk(&mut cjk_run, line, &mut position, &mut tokens);
+            }
+        }
+        flush_word(&mut word, line, &mut position, &mut tokens);
+        flush_cjk(&mut cjk_run, line, &mut position, &mut tokens);
+    }
+
+    tokens
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Consecutive terms; a plain word is a phrase of one (or, for CJK, several bigram) terms.
+    Phrase { text: String, terms: Vec<String> },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryToken {
+    Word(String),
+    Quoted(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn lex_query(input: &str) -> Result<Vec<QueryToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(QueryToken::Open);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(QueryToken::Close);
+        } else if c == '|' {
+            chars.next();
+            tokens.push(QueryToken::Or);
+        } else if c == '-' {
+            chars.next();
+            tokens.push(QueryToken::Not);
+        } else if c == '"' {
+            chars.next();
+            let mut phrase = String::new();
+            let mut closed = false;
+            for next in chars.by_ref() {
+                if next == '"' {
+                    closed = true;
+                    break;
+                }
+                phrase.push(next);
+            }
+            if !closed {
+                return Err("Unterminated phrase in query".to_string());
+            }
+            tokens.push(QueryToken::Quoted(phrase));
+        } else {
+            let mut word = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_whitespace() || matches!(next, '(' | ')' | '"' | '|') {
+                    break;
+                }
+                word.push(next);
+                chars.next();
+            }
+            tokens.push(match word.as_str() {
+                "AND" => QueryToken::And,
+                "OR" => QueryToken::Or,
+                "NOT" => QueryToken::Not,
+                _ => QueryToken::Word(word),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Query, String> {
+        let mut branches = vec![self.parse_and()?];
+        while self.peek() == Some(&QueryToken::Or) {
+            self.pos += 1;
+            branches.push(self.parse_and()?);
+        }
+        Ok(if branches.len() == 1 { branches.remove(0) } else { Query::Or(branches) })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, String> {
+        let mut parts = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                Some(QueryToken::And) => {
+                    self.pos += 1;
+                    parts.push(self.parse_unary()?);
+                }
+                None | Some(QueryToken::Or) | Some(QueryToken::Close) => break,
+                Some(_) => parts.push(self.parse_unary()?),
+            }
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { Query::And(parts) })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, String> {
+        if self.peek() == Some(&QueryToken::Not) {
+            self.pos += 1;
+            return Ok(Query::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(QueryToken::Open) => {
+                let inner = self.parse_or()?;
+                if self.peek() != Some(&QueryToken::Close) {
+                    return Err("Missing closing parenthesis in query".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(QueryToken::Word(text)) | Some(QueryToken::Quoted(text)) => {
+                let terms: Vec<String> = tokenize(&text).into_iter().map(|t| t.term).collect();
+                if terms.is_empty() {
+                    return Err(format!("Query term '{}' contains no searchable text", text));
+                }
+                Ok(Query::Phrase { text, terms })
+            }
+            Some(other) => Err(format!("Unexpected {:?} in query", other)),
+            None => Err("Unexpected end of query".to_string()),
+        }
+    }
+}
+
+/// Parses `foo bar` (AND), `foo OR bar` / `foo | bar`, `NOT foo` / `-foo`, `"exact phrase"`
+/// and parenthesised groups.
+pub fn parse_query(input: &str) -> Result<Query, String> {
+    let tokens = lex_query(input)?;
+    if tokens.is_empty() {
+        return Err("Empty query".to_string());
+    }
+    let mut parser = QueryParser { tokens, pos: 0 };
+    let query = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("Unexpected {:?} in query", parser.tokens[parser.pos]));
+    }
+    Ok(query)
+}
+
+type DocId = u32;
+
+#[derive(Debug, Clone, Copy)]
+struct Occurrence {
+    position: u32,
+    line: u32,
+}
+
+#[derive(Debug)]
+struct Document {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    terms: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentHit {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub line_number: u32,
+    pub snippet: String,
+    pub matched: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ContentIndex {
+    config: ContentIndexConfig,
+    docs: Vec<Option<Document>>,
+    free_ids: Vec<DocId>,
+    by_path: HashMap<PathBuf, DocId>,
+    postings: HashMap<String, HashMap<DocId, Vec<Occurrence>>>,
+    /// CJK bigram terms by each of their characters, so a one-character query finds them without a full scan.
+    cjk_bigrams: HashMap<char, HashSet<String>>,
+}
+
+/// The character of a one-character CJK term.
+fn single_cjk(term: &str) -> Option<char> {
+    let mut chars = term.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if is_cjk(c) => Some(c),
+        _ => None,
+    }
+}
+
+/// The characters of a CJK bigram term.
+fn cjk_pair(term: &str) -> Option<[char; 2]> {
+    let mut chars = term.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(a), Some(b), None) if is_cjk(a) && is_cjk(b) => Some([a, b]),
+        _ => None,
+    }
+}
+
+fn read_document(path: &Path, config: &ContentIndexConfig) -> Option<(Document, Vec<Token>)> {
+    let meta = std::fs::metadata(path).ok()?;
+    if !meta.is_file() || !config.accepts(path, meta.len()) {
+        return None;
+    }
+    let modified = meta.modified().ok()?;
+    let tokens = if meta.len() == 0 {
+        Vec::new()
+    } else {
+        let file = File::open(path).ok()?;
+        let mmap = unsafe { Mmap::map(&file) }.ok()?;
+        if crate::encoding::looks_binary(&mmap) {
+            return None;
+        }
+        tokenize(&crate::encoding::decode(&mmap, None).text)
+    };
+    let doc = Document { path: path.to_path_buf(), size: meta.len(), modified, terms: Vec::new() };
+    Some((doc, tokens))
+}
+
+impl ContentIndex {
+    pub fn new(config: ContentIndexConfig) -> Self {
+        ContentIndex { config, ..Default::default() }
+    }
+
+    pub fn config(&self) -> &ContentIndexConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+
+    /// Indexes `paths` in parallel, skipping anything the config rejects.
+    pub fn build(&mut self, paths: Vec<PathBuf>) -> usize {
+        let config = self.config.clone();
+        let documents: Vec<(Document, Vec<Token>)> = paths
+            .into_par_iter()
+            .filter_map(|path| read_document(&path, &config))
+            .collect();
+        let count = documents.len();
+        for (doc, tokens) in documents {
+            self.remove_file(&doc.path);
+            self.insert(doc, tokens);
+        }
+        debug!("Content index built with {} documents, {} terms", self.len(), self.postings.len());
+        count
+    }
+
+    /// Re-reads a single file after a change. Files that vanished or no longer qualify are dropped.
+    pub fn update_file(&mut self, path: &Path) {
+        if let Some(&id) = self.by_path.get(path) {
+            let unchanged = match (std::fs::metadata(path), self.docs[id as usize].as_ref()) {
+                (Ok(meta), Some(doc)) => meta.len() == doc.size && meta.modified().ok() == Some(doc.modified),
+                _ => false,
+            };
+            if unchanged {
+                return;
+            }
+        }
+        self.remove_file(path);
+        if let Some((doc, tokens)) = read_document(path, &self.config) {
+            trace!("Content index updated: {}", path.display());
+            self.insert(doc, tokens);
+        }
+    }
+
+    /// Handles a watcher event for `path`: a new folder has its files indexed, and a folder that's gone
+    /// takes its documents with it.
+    pub fn update_path(&mut self, path: &Path) {
+        if path.is_dir() {
+            let mut files = Vec::new();
+            crate::scanner::scan(path, &crate::scanner::ScanOptions::default(), |event| {
+                if let crate::scanner::ScanEvent::File(file) = event {
+                    files.push(PathBuf::from(file.path));
+                }
+                true
+            });
+            for file in files {
+                self.update_file(&file);
+            }
+        } else if !path.exists() && !self.by_path.contains_key(path) {
+            let inside: Vec<PathBuf> = self.by_path.keys().filter(|doc| doc.starts_with(path)).cloned().collect();
+            for doc in inside {
+                self.remove_file(&doc);
+            }
+        } else {
+            self.update_file(path);
+        }
+    }
+
+    pub fn remove_file(&mut self, path: &Path) {
+        let Some(id) = self.by_path.remove(path) else { return };
+        if let Some(doc) = self.docs[id as usize].take() {
+            for term in doc.terms {
+                if let Some(docs) = self.postings.get_mut(&term) {
+                    docs.remove(&id);
+                    if docs.is_empty() {
+                        self.postings.remove(&term);
+                        for c in cjk_pair(&term).into_iter().flatten() {
+                            if let Some(bigrams) = self.cjk_bigrams.get_mut(&c) {
+                                bigrams.remove(&term);
+                                if bigrams.is_empty() {
+                                    self.cjk_bigrams.remove(&c);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.free_ids.push(id);
+    }
+
+    fn insert(&mut self, mut doc: Document, tokens: Vec<Token>) {
+        let id = match self.free_ids.pop() {
+            Some(id) => id,
+            None => {
+                self.docs.push(None);
+                (self.docs.len() - 1) as DocId
+            }
+        };
+        let mut terms = HashSet::new();
+        for token in tokens {
+            if !self.postings.contains_key(&token.term) {
+                for c in cjk_pair(&token.term).into_iter().flatten() {
+                    self.cjk_bigrams.entry(c).or_default().insert(token.term.clone());
+                }
+            }
+            self.postings
+                .entry(token.term.clone())
+                .or_default()
+                .entry(id)
+                .or_default()
+                .push(Occurrence { position: token.position, line: token.line });
+            terms.insert(token.term);
+        }
+        doc.terms = terms.into_iter().collect();
+        self.by_path.insert(doc.path.clone(), id);
+        self.docs[id as usize] = Some(doc);
+    }
+
+    /// Postings that match `term`: its own, plus every bigram containing it when it's a single CJK character.
+    fn matching_postings<'a>(&'a self, term: &'a str) -> impl Iterator<Item = &'a HashMap<DocId, Vec<Occurrence>>> + 'a {
+        let bigrams = single_cjk(term).and_then(|c| self.cjk_bigrams.get(&c));
+        std::iter::once(term)
+            .chain(bigrams.into_iter().flatten().map(String::as_str))
+            .filter_map(|term| self.postings.get(term))
+    }
+
+    /// Occurrences of `term` in `doc`. A single CJK character also matches every bigram containing it.
+    fn occurrences(&self, term: &str, doc: DocId) -> Vec<Occurrence> {
+        let mut all: Vec<Occurrence> = self.matching_postings(term).filter_map(|docs| docs.get(&doc)).flatten().copied().collect();
+        all.sort_by_key(|occ| occ.position);
+        all
+    }
+
+    fn candidate_docs(&self, term: &str) -> HashSet<DocId> {
+        self.matching_postings(term).flat_map(|docs| docs.keys().copied()).collect()
+    }
+
+    /// Lines on which `terms` occur consecutively in `doc`.
+    fn phrase_lines(&self, terms: &[String], doc: DocId) -> Vec<u32> {
+        let first = self.occurrences(&terms[0], doc);
+        if terms.len() == 1 {
+            return first.iter().map(|occ| occ.line).collect();
+        }
+        let rest: Vec<Vec<Occurrence>> = terms[1..].iter().map(|term| self.occurrences(term, doc)).collect();
+        first
+            .iter()
+            .filter(|start| {
+                rest.iter().enumerate().all(|(offset, occs)| {
+                    let wanted = start.position + offset as u32 + 1;
+                    occs.binary_search_by_key(&wanted, |occ| occ.position).is_ok()
+                })
+            })
+            .map(|start| start.line)
+            .collect()
+    }
+
+    fn all_docs(&self) -> HashSet<DocId> {
+        self.by_path.values().copied().collect()
+    }
+
+    fn matching_docs(&self, query: &Query) -> HashSet<DocId> {
+        match query {
+            Query::Phrase { terms, .. } => {
+                let mut candidates = self.candidate_docs(&terms[0]);
+                for term in &terms[1..] {
+                    let docs = self.candidate_docs(term);
+                    candidates.retain(|doc| docs.contains(doc));
+                }
+                if terms.len() > 1 {
+                    candidates.retain(|&doc| !self.phrase_lines(terms, doc).is_empty());
+                }
+                candidates
+            }
+            Query::And(parts) => {
+                let mut parts_iter = parts.iter();
+                let mut result = match parts_iter.next() {
+                    Some(first) => self.matching_docs(first),
+                    None => return HashSet::new(),
+                };
+                for part in parts_iter {
+                    if result.is_empty() {
+                        break;
+                    }
+                    let docs = self.matching_docs(part);
+                    result.retain(|doc| docs.contains(doc));
+                }
+                result
+            }
+            Query::Or(branches) => branches.iter().flat_map(|branch| self.matching_docs(branch)).collect(),
+            Query::Not(inner) => {
+                let excluded = self.matching_docs(inner);
+                self.all_docs().into_iter().filter(|doc| !excluded.contains(doc)).collect()
+            }
+        }
+    }
+
+    fn positive_phrases<'q>(query: &'q Query, out: &mut Vec<(&'q str, &'q [String])>) {
+        match query {
+            Query::Phrase { text, terms } => out.push((text, terms)),
+            Query::And(parts) | Query::Or(parts) => {
+                parts.iter().for_each(|part| Self::positive_phrases(part, out))
+            }
+            Query::Not(_) => {}
+        }
+    }
+
+    /// Runs a boolean/phrase query and returns one hit per matching line, up to `limit` hits.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<ContentHit>, String> {
+        let parsed = parse_query(query)?;
+        let mut docs: Vec<&Document> = self
+            .matching_docs(&parsed)
+            .into_iter()
+            .filter_map(|id| self.docs[id as usize].as_ref())
+            .collect();
+        docs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut phrases = Vec::new();
+        Self::positive_phrases(&parsed, &mut phrases);
+
+        let mut hits = Vec::new();
+        for doc in docs {
+            if hits.len() >= limit {
+                break;
+            }
+            let id = self.by_path[&doc.path];
+
+            let mut lines: Vec<(u32, Vec<String>)> = Vec::new();
+            for (text, terms) in &phrases {
+                for line in self.phrase_lines(terms, id) {
+                    match lines.iter_mut().find(|(l, _)| *l == line) {
+                        Some((_, matched)) if !matched.iter().any(|m| m == text) => matched.push(text.to_string()),
+                        Some(_) => {}
+                        None => lines.push((line, vec![text.to_string()])),
+                    }
+                }
+            }
+            lines.sort_by_key(|(line, _)| *line);
+
+            if lines.is_empty() {
+                // Purely negative queries match whole documents rather than lines.
+                hits.push(ContentHit {
+                    path: doc.path.clone(), size: doc.size, modified: doc.modified,
+                    line_number: 0, snippet: String::new(), matched: Vec::new(),
+                });
+                continue;
+            }
+
+            let snippets = read_lines(&doc.path, lines.iter().map(|(line, _)| *line).collect());
+            for ((line, matched), snippet) in lines.into_iter().zip(snippets) {
+                if hits.len() >= limit {
+                    break;
+                }
+                hits.push(ContentHit {
+                    path: doc.path.clone(), size: doc.size, modified: doc.modified,
+                    line_number: line, snippet, matched,
+                });
+            }
+        }
+
+        Ok(hits)
+    }
+}
+
+/// Fetches the given (sorted, 1-based) lines of a file, each trimmed to a snippet.
+fn read_lines(path: &Path, wanted: Vec<u32>) -> Vec<String> {
+    let content = File::open(path)
+        .ok()
+        .and_then(|file| unsafe { Mmap::map(&file) }.ok())
+        .map(|mmap| crate::encoding::decode(&mmap, None).text.into_owned())
+        .unwrap_or_default();
+    let lines: Vec<&str> = content.lines().collect();
+    wanted
+        .into_iter()
+        .map(|line| {
+            let text = lines.get(line as usize - 1).map(|l| l.trim()).unwrap_or_default();
+            text.chars().take(SNIPPET_MAX_CHARS).collect()
+        })
+        .collect()
+}
+
+impl ContentIndex {
+    /// What to watch, recursively, for changes: the configured roots, or without any, the outermost folders
+    /// holding indexed documents, so new subfolders under them are picked up too.
+    pub fn watch_roots(&self) -> Vec<PathBuf> {
+        if !self.config.roots.is_empty() {
+            return self.config.roots.clone();
+        }
+        let mut folders: Vec<&Path> = self.by_path.keys().filter_map(|path| path.parent()).collect();
+        // Sorted by component, a folder comes right before the ones inside it.
+        folders.sort();
+        folders.dedup();
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for folder in folders {
+            if !roots.last().is_some_and(|root| folder.starts_with(root)) {
+                roots.push(folder.to_path_buf());
+            }
+        }
+        roots
+    }
+}
+
+/// Keeps the shared index in sync with changes under its roots (see [`ContentIndex::watch_roots`]).
+pub fn watch(index: Arc<Mutex<Option<ContentIndex>>>) -> notify::Result<RecommendedWatcher> {
+    let roots = match index.lock() {
+        Ok(guard) => guard.as_ref().map(ContentIndex::watch_roots),
+        Err(poisoned) => poisoned.into_inner().as_ref().map(ContentIndex::watch_roots),
+    }
+    .unwrap_or_default();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Content index watch error: {}", e);
+                return;
+            }
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            return;
+        }
+        let mut guard = match index.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(index) = guard.as_mut() {
+            for path in &event.paths {
+                index.update_path(path);
+            }
+        }
+    })?;
+    for root in &roots {
+        watcher.watch(root, RecursiveMode::Recursive)?;
+    }
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_tokenize_cjk_bigrams() {
+        let terms: Vec<String> = tokenize("Hello 搜索引擎 world").into_iter().map(|t| t.term).collect();
+        assert_eq!(terms, vec!["hello", "搜索", "索引", "引擎", "world"]);
+    }
+
+    #[test]
+    fn test_content_index_queries() {
+        let temp_dir = tempdir().unwrap();
+        let first = temp_dir.path().join("first.txt");
+        let second = temp_dir.path().join("second.md");
+        File::create(&first).unwrap().write_all(b"alpha beta\nquick brown fox\n").unwrap();
+        File::create(&second).unwrap().write_all("beta gamma\n全文搜索引擎\n".as_bytes()).unwrap();
+
+        let mut index = ContentIndex::new(ContentIndexConfig::default());
+        assert_eq!(index.build(vec![first.clone(), second.clone()]), 2);
+
+        let hits = index.search("\"quick brown\"", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, first);
+        assert_eq!(hits[0].line_number, 2);
+        assert_eq!(hits[0].snippet, "quick brown fox");
+
+        assert!(index.search("\"brown quick\"", 10).unwrap().is_empty());
+        assert_eq!(index.search("beta -gamma", 10).unwrap()[0].path, first);
+        assert_eq!(index.search("alpha OR gamma", 10).unwrap().len(), 2);
+        assert_eq!(index.search("搜索", 10).unwrap()[0].line_number, 2);
+        assert_eq!(index.search("索", 10).unwrap()[0].path, second);
+        assert_eq!(index.watch_roots(), vec![temp_dir.path().to_path_buf()]);
+
+        File::create(&first).unwrap().write_all(b"gamma only\n").unwrap();
+        index.update_file(&first);
+        assert!(index.search("quick", 10).unwrap().is_empty());
+        assert_eq!(index.search("gamma", 10).unwrap().len(), 2);
+        index.remove_file(&second);
+        assert!(index.search("索", 10).unwrap().is_empty());
+        assert!(index.cjk_bigrams.is_empty());
+    }
+
+    #[test]
+    fn test_watch_picks_up_new_subfolders() {
+        let temp_dir = tempdir().unwrap();
+        let notes = temp_dir.path().join("notes");
+        std::fs::create_dir_all(notes.join("old")).unwrap();
+        std::fs::write(notes.join("todo.txt"), "existing notes\n").unwrap();
+        std::fs::write(notes.join("old").join("done.txt"), "archived notes\n").unwrap();
+
+        let mut index = ContentIndex::new(ContentIndexConfig::default());
+        index.build(vec![notes.join("todo.txt"), notes.join("old").join("done.txt")]);
+        assert_eq!(index.watch_roots(), vec![notes.clone()]);
+
+        let shared = Arc::new(Mutex::new(Some(index)));
+        let _watcher = watch(shared.clone()).unwrap();
+        let project = notes.join("project").join("plans");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("roadmap.md"), "quarterly roadmap\n").unwrap();
+
+        let found = |term: &str| shared.lock().unwrap().as_ref().unwrap().search(term, 10).unwrap().len();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while found("roadmap") == 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(found("roadmap"), 1);
+
+        std::fs::remove_dir_all(notes.join("project")).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while found("roadmap") != 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(found("roadmap"), 0);
+        assert_eq!(found("notes"), 2);
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        assert!(parse_query("\"open phrase").is_err());
+        assert!(parse_query("(alpha").is_err());
+        assert!(parse_query("").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Handing files to the desktop: opening them with the default app, revealing them in the file manager,
+//! sending them to the trash and putting paths on the clipboard.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+use log::debug;
+
+/// Starts `command` without waiting for it; a thread reaps it so it doesn't linger as a zombie.
+fn spawn_detached(mut command: Command) -> io::Result<()> {
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Opens `path` with the application the desktop associates with it.
+pub fn open(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())));
+    }
+    let command = if cfg!(windows) {
+        // Not `cmd /C start`: cmd.exe would re-parse `&`, `^` and `%VAR%` in the file name as commands.
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler").arg(path);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg(path);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(path);
+        command
+    };
+    spawn_detached(command)
+}
+
+/// Shows `path` selected in the file manager. On Linux this goes through the freedesktop
+/// `FileManager1` D-Bus interface, falling back to opening the containing folder.
+pub fn reveal(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())));
+    }
+    if cfg!(windows) {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", path.display()));
+        return spawn_detached(command);
+    }
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        return spawn_detached(command);
+    }
+
+    if let Ok(uri) = url::Url::from_file_path(path) {
+        let shown = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--dest=org.freedesktop.FileManager1",
+                "--type=method_call",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:{}", uri))
+            .arg("string:")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match shown {
+            Ok(status) if status.success() => return Ok(()),
+            other => debug!("FileManager1.ShowItems unavailable ({:?}), opening the folder instead", other),
+        }
+    }
+    open(path.parent().unwrap_or(path))
+}
+
+/// Runs `command` to completion, turning a non-zero exit into an error.
+fn run(mut command: Command) -> io::Result<()> {
+    let output = command.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(io::Error::other(if message.is_empty() { format!("{:?} failed with {}", command.get_program(), output.status) } else { message }))
+}
+
+/// Moves `path` to the trash: the freedesktop.org trash on Linux and the BSDs, Finder's on macOS and
+/// the Recycle Bin on Windows.
+pub fn trash(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata().is_err() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())));
+    }
+    if cfg!(windows) {
+        // The path goes through the environment so it never needs quoting for PowerShell.
+        let mut command = Command::new("powershell");
+        command
+            .args(["-NoProfile", "-NonInteractive", "-Command"])
+            .arg("Add-Type -AssemblyName Microsoft.VisualBasic; [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile($env:ULTRASEARCH_PATH, 'OnlyErrorDialogs', 'SendToRecycleBin')")
+            .env("ULTRASEARCH_PATH", path);
+        return run(command);
+    }
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command
+            .args(["-e", "on run argv", "-e", "tell application \"Finder\" to delete POSIX file (item 1 of argv)", "-e", "end run"])
+            .arg(path);
+        return run(command);
+    }
+    #[cfg(unix)]
+    {
+        freedesktop::trash(path)
+    }
+    #[cfg(not(unix))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "no trash on this platform"))
+}
+
+/// The trash as the freedesktop.org Trash specification lays it out: `$XDG_DATA_HOME/Trash` for files on
+/// the same file system as the home directory, `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid` for the rest.
+#[cfg(unix)]
+mod freedesktop {
+    use std::{
+        fs::{self, DirBuilder, OpenOptions},
+        io::{self, Write},
+        os::unix::fs::{DirBuilderExt, MetadataExt},
+        path::{Path, PathBuf},
+    };
+    use chrono::Local;
+
+    pub fn trash(path: &Path) -> io::Result<()> {
+        let home_trash = dirs::data_dir()
+            .map(|data_dir| data_dir.join("Trash"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory for the trash"))?;
+        trash_into(path, &home_trash)
+    }
+
+    /// The device of `path`, or of its closest existing ancestor.
+    fn device(path: &Path) -> io::Result<u64> {
+        let mut current = Some(path);
+        while let Some(candidate) = current {
+            if let Ok(meta) = fs::metadata(candidate) {
+                return Ok(meta.dev());
+            }
+            current = candidate.parent();
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())))
+    }
+
+    /// The top directory of the mount `path` is on.
+    fn mount_root(path: &Path, dev: u64) -> PathBuf {
+        let mut root = path;
+        while let Some(parent) = root.parent() {
+            if fs::metadata(parent).map_or(true, |meta| meta.dev() != dev) {
+                break;
+            }
+            root = parent;
+        }
+        root.to_path_buf()
+    }
+
+    fn create_private_dir(dir: &Path) -> io::Result<()> {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)
+    }
+
+    fn trash_dir_for(path: &Path, dev: u64, home_trash: &Path) -> io::Result<PathBuf> {
+        if device(home_trash)? == dev {
+            return Ok(home_trash.to_path_buf());
+        }
+        let topdir = mount_root(path, dev);
+        let uid = fs::metadata("/proc/self").or_else(|_| fs::metadata(home_trash))?.uid();
+        // A shared `.Trash` only counts if it is a real directory with the sticky bit set.
+        let shared = topdir.join(".Trash");
+        if fs::symlink_metadata(&shared).is_ok_and(|meta| meta.is_dir() && meta.mode() & 0o1000 != 0) {
+            let dir = shared.join(uid.to_string());
+            if create_private_dir(&dir).is_ok() {
+                return Ok(dir);
+            }
+        }
+        Ok(topdir.join(format!(".Trash-{}", uid)))
+    }
+
+    pub(super) fn trash_into(path: &Path, home_trash: &Path) -> io::Result<()> {
+        let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no file name", path.display())))?;
+        let path = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?.join(name),
+            _ => path.to_path_buf(),
+        };
+        let trash_dir = trash_dir_for(&path, fs::symlink_metadata(&path)?.dev(), home_trash)?;
+        let (files_dir, info_dir) = (trash_dir.join("files"), trash_dir.join("info"));
+        create_private_dir(&files_dir)?;
+        create_private_dir(&info_dir)?;
+
+        // The spec reserves a name by creating its `.trashinfo` exclusively before moving the file.
+        let original = url::Url::from_file_path(&path).map_err(|()| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not absolute", path.display())))?;
+        let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", original.path(), Local::now().format("%Y-%m-%dT%H:%M:%S"));
+        let name = name.to_string_lossy();
+        let mut attempt = 1;
+        let (trashed_name, info_path) = loop {
+            let candidate = if attempt == 1 { name.to_string() } else { format!("{}.{}", name, attempt) };
+            let info_path = info_dir.join(format!("{}.trashinfo", candidate));
+            match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+                Ok(mut file) => {
+                    file.write_all(info.as_bytes())?;
+                    break (candidate, info_path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        fs::rename(&path, files_dir.join(trashed_name)).inspect_err(|_| {
+            let _ = fs::remove_file(&info_path);
+        })
+    }
+}
+
+/// Puts `text` on the clipboard, through `wl-copy`, `xclip` or `xsel` on Linux.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", "Set-Clipboard -Value $env:ULTRASEARCH_CLIPBOARD"]).env("ULTRASEARCH_CLIPBOARD", text);
+        return run(command);
+    }
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    };
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no clipboard tool found");
+    for (program, args) in candidates {
+        if *program == "wl-copy" && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            continue;
+        }
+        // These keep running to serve the selection, so they're reaped in the background like opened apps.
+        let child = Command::new(program).args(*args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                debug!("{} unavailable: {}", program, e);
+                last_error = e;
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        thread::spawn(move || {
+            let _ = child.wait();
+        });
+        return Ok(());
+    }
+    Err(last_error)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_trash_into_home_trash() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let home_trash = temp_dir.path().join("Trash");
+        let file = temp_dir.path().join("report 1.txt");
+        for contents in ["first", "second"] {
+            fs::write(&file, contents).unwrap();
+            freedesktop::trash_into(&file, &home_trash).unwrap();
+            assert!(!file.exists());
+        }
+
+        assert_eq!(fs::read_to_string(home_trash.join("files/report 1.txt")).unwrap(), "first");
+        assert_eq!(fs::read_to_string(home_trash.join("files/report 1.txt.2")).unwrap(), "second");
+        let info = fs::read_to_string(home_trash.join("info/report 1.txt.2.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+        assert!(info.contains("/report%201.txt\nDeletionDate="));
+        assert!(freedesktop::trash_into(&file, &home_trash).is_err());
+    }
+}
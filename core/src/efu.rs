@@ -0,0 +1,215 @@
+//! Everything file lists (`.efu`): CSV with `Filename,Size,Date Modified,Date Created,Attributes` and FILETIME dates.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use crate::indexer::FileMetadata;
+
+/// Seconds between 1601-01-01 (the FILETIME epoch) and 1970-01-01.
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+pub const HEADER: &str = "Filename,Size,Date Modified,Date Created,Attributes";
+
+#[derive(Debug, Error)]
+pub enum EfuError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not an EFU file list: no Filename column")]
+    MissingFilename,
+    #[error("Line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+/// 100-nanosecond ticks since 1601-01-01, as Windows and EFU files store times. `None` for times past
+/// the year 30828, which don't fit (a far-future mtime, say, or a crafted one in an archive).
+pub fn to_filetime(time: DateTime<Utc>) -> Option<i64> {
+    time.timestamp()
+        .checked_add(FILETIME_UNIX_OFFSET)?
+        .checked_mul(10_000_000)?
+        .checked_add(i64::from(time.timestamp_subsec_nanos() / 100))
+}
+
+pub fn from_filetime(ticks: i64) -> Option<DateTime<Utc>> {
+    let nanos = u32::try_from(ticks.rem_euclid(10_000_000) * 100).ok()?;
+    DateTime::from_timestamp(ticks.div_euclid(10_000_000) - FILETIME_UNIX_OFFSET, nanos)
+}
+
+/// Quotes a CSV field when it needs it (or always, as EFU does for file names).
+pub fn csv_field(value: &str, always_quote: bool) -> String {
+    if always_quote || value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads one CSV record, which may span lines inside quotes. `line` counts physical lines read so far.
+fn read_record(reader: &mut impl BufRead, line: &mut usize) -> io::Result<Option<Vec<String>>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut buffer = String::new();
+    loop {
+        buffer.clear();
+        if reader.read_line(&mut buffer)? == 0 {
+            if quoted || !fields.is_empty() || !field.is_empty() {
+                fields.push(field);
+                return Ok(Some(fields));
+            }
+            return Ok(None);
+        }
+        *line += 1;
+        let mut chars = buffer.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                '\r' | '\n' if !quoted => {}
+                c => field.push(c),
+            }
+        }
+        if !quoted {
+            fields.push(field);
+            return Ok(Some(fields));
+        }
+    }
+}
+
+/// Reads a file list. Folder rows are skipped, since the index only holds files; a missing
+/// size or date reads as zero or the FILETIME epoch, as Everything treats them.
+pub fn read_efu(reader: impl BufRead) -> Result<Vec<FileMetadata>, EfuError> {
+    let mut reader = reader;
+    let mut line = 0;
+    let Some(mut header) = read_record(&mut reader, &mut line)? else { return Err(EfuError::MissingFilename) };
+    if let Some(first) = header.first_mut() {
+        *first = first.trim_start_matches('\u{feff}').to_string();
+    }
+    let column = |name: &str| header.iter().position(|column| column.trim().eq_ignore_ascii_case(name));
+    let filename = column("Filename").ok_or(EfuError::MissingFilename)?;
+    let (size, modified, created, attributes) = (column("Size"), column("Date Modified"), column("Date Created"), column("Attributes"));
+
+    let mut files = Vec::new();
+    while let Some(record) = read_record(&mut reader, &mut line)? {
+        let field = |index: Option<usize>| index.and_then(|index| record.get(index)).map(|value| value.trim()).filter(|value| !value.is_empty());
+        let number = |index: Option<usize>, name: &str| {
+            field(index)
+                .map(|value| value.parse::<i64>().map_err(|_| EfuError::Parse { line, message: format!("{} '{}' is not a number", name, value) }))
+                .transpose()
+        };
+        let date = |index: Option<usize>, name: &str| {
+            number(index, name)?
+                .map(|ticks| from_filetime(ticks).ok_or_else(|| EfuError::Parse { line, message: format!("{} {} is out of range", name, ticks) }))
+                .transpose()
+        };
+
+        let Some(path) = field(Some(filename)) else { continue };
+        let attributes = number(attributes, "Attributes")?.map(|bits| bits as u32);
+        if attributes.is_some_and(|bits| bits & FILE_ATTRIBUTE_DIRECTORY != 0) {
+            continue;
+        }
+        let path = path.trim_end_matches(['\\', '/']);
+        files.push(FileMetadata {
+            path: path.to_string(),
+            name: path.rsplit(['\\', '/']).next().unwrap_or(path).to_string(),
+            size: number(size, "Size")?.unwrap_or(0).max(0) as u64,
+            modified_time: date(modified, "Date Modified")?.unwrap_or_else(|| from_filetime(0).unwrap_or_default()),
+            created_time: date(created, "Date Created")?,
+            attributes,
+            source: None,
+            volume: None,
+        });
+    }
+    Ok(files)
+}
+
+/// Reads `path` and tags every entry with the file list it came from (its canonical path),
+/// ready for [`indexer::replace_source`](crate::indexer::replace_source).
+pub fn import_efu(path: &Path) -> Result<(String, Vec<FileMetadata>), EfuError> {
+    let files = read_efu(BufReader::new(fs::File::open(path)?))?;
+    let source = fs::canonicalize(path)?.to_string_lossy().into_owned();
+    Ok((source, files))
+}
+
+/// Entries under `root` (or, with `None`, all of them), as an export to EFU should include them.
+pub fn under_root<'a>(files: &'a [FileMetadata], root: Option<&'a Path>) -> impl Iterator<Item = &'a FileMetadata> + 'a {
+    files.iter().filter(move |file| root.is_none_or(|root| Path::new(&file.path).starts_with(root)))
+}
+
+pub fn write_efu_header(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{}", HEADER)
+}
+
+pub fn write_efu_row(out: &mut impl Write, file: &FileMetadata) -> io::Result<()> {
+    let optional = |value: Option<i64>| value.map(|value| value.to_string()).unwrap_or_default();
+    writeln!(
+        out,
+        "{},{},{},{},{}",
+        csv_field(&file.path, true),
+        file.size,
+        optional(to_filetime(file.modified_time)),
+        optional(file.created_time.and_then(to_filetime)),
+        optional(file.attributes.map(i64::from)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_round_trip() {
+        let modified_time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        assert_eq!(to_filetime(modified_time), Some(133_590_384_000_000_000));
+        assert_eq!(to_filetime(DateTime::<Utc>::MAX_UTC), None);
+        assert_eq!(from_filetime(133_590_384_000_000_000), Some(modified_time));
+
+        let files = vec![
+            FileMetadata { path: "D:\\Photos\\a, \"b\".jpg".to_string(), name: "a, \"b\".jpg".to_string(), size: 10, modified_time, ..Default::default() },
+            FileMetadata {
+                path: "/mnt/usb/notes.txt".to_string(),
+                name: "notes.txt".to_string(),
+                size: 20,
+                modified_time,
+                created_time: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+                attributes: Some(0x20),
+                ..Default::default()
+            },
+        ];
+        let mut out = Vec::new();
+        write_efu_header(&mut out).unwrap();
+        for file in &files {
+            write_efu_row(&mut out, file).unwrap();
+        }
+        assert_eq!(read_efu(out.as_slice()).unwrap(), files);
+
+        let far_future = FileMetadata { path: "/x".to_string(), modified_time: DateTime::<Utc>::MAX_UTC, created_time: Some(DateTime::<Utc>::MAX_UTC), ..Default::default() };
+        let mut out = Vec::new();
+        write_efu_row(&mut out, &far_future).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\"/x\",0,,,\n");
+    }
+
+    #[test]
+    fn test_read_everything_export() {
+        // As Everything writes it: a BOM, reordered columns, a folder row and a name with a line break.
+        let efu = "\u{feff}Filename,Date Modified,Size,Attributes\r\n\
+                   \"D:\\Music\",133590384000000000,,16\r\n\
+                   \"D:\\Music\\song.mp3\",133590384000000000,4096,32\r\n\
+                   \"D:\\odd\r\nname.txt\",,,\r\n";
+        let files = read_efu(efu.as_bytes()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!((files[0].name.as_str(), files[0].size, files[0].attributes), ("song.mp3", 4096, Some(32)));
+        assert_eq!(files[1].name, "odd\r\nname.txt");
+
+        assert!(matches!(read_efu("Name,Size\nx,1\n".as_bytes()), Err(EfuError::MissingFilename)));
+        assert!(matches!(read_efu("Filename,Size\nx,big\n".as_bytes()), Err(EfuError::Parse { line: 2, .. })));
+    }
+}
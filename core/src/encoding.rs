@@ -0,0 +1,117 @@
+//! Text encoding detection (BOMs, chardetng) and decoding, plus the binary-file heuristic.
+
+use std::borrow::Cow;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// How many leading bytes the UTF-16 and charset heuristics look at.
+const SNIFF_LEN: usize = 64 * 1024;
+/// How many leading bytes the binary check looks at.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub struct Decoded<'a> {
+    pub text: Cow<'a, str>,
+    pub encoding: &'static Encoding,
+    pub had_errors: bool,
+}
+
+/// Looks up a user-supplied encoding label such as `gbk`, `utf-16le` or `shift_jis`.
+pub fn resolve_label(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| format!("Unknown encoding: {}", label))
+}
+
+/// UTF-16 text without a BOM still shows up as NULs in every other byte for Latin text.
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN) & !1];
+    if sample.len() < 4 {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_nuls = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    if odd_nuls * 10 >= pairs * 4 && even_nuls * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_nuls * 10 >= pairs * 4 && odd_nuls * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// NUL bytes in the first block mean binary, unless a BOM or the UTF-16 pattern explains them.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    let block = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    if !block.contains(&0) {
+        return false;
+    }
+    Encoding::for_bom(block).is_none() && sniff_utf16(block).is_none()
+}
+
+/// Picks an encoding for `bytes`: the override if given, then BOM, UTF-16 and UTF-8 checks,
+/// and finally a statistical guess (which covers GBK/GB18030, Big5, Shift_JIS, Windows-125x...).
+pub fn detect(bytes: &[u8], forced: Option<&'static Encoding>) -> (&'static Encoding, usize) {
+    if let Some(encoding) = forced {
+        return (encoding, 0);
+    }
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_len);
+    }
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return (encoding, 0);
+    }
+    match std::str::from_utf8(bytes) {
+        // A sequence cut off at the very end still means UTF-8, e.g. for a truncated preview.
+        Ok(_) => return (UTF_8, 0),
+        Err(e) if e.error_len().is_none() => return (UTF_8, 0),
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    detector.feed(sample, sample.len() == bytes.len());
+    (detector.guess(None, true), 0)
+}
+
+/// Transcodes `bytes` to UTF-8, borrowing when the input already is valid UTF-8.
+pub fn decode<'a>(bytes: &'a [u8], forced: Option<&'static Encoding>) -> Decoded<'a> {
+    let (encoding, bom_len) = detect(bytes, forced);
+    let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    Decoded { text, encoding, had_errors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_bom_and_utf16() {
+        let mut utf16: Vec<u8> = vec![0xFF, 0xFE];
+        utf16.extend("hello 世界".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        let decoded = decode(&utf16, None);
+        assert_eq!(decoded.encoding, UTF_16LE);
+        assert_eq!(decoded.text, "hello 世界");
+
+        let no_bom: Vec<u8> = "plain text".encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+        assert_eq!(decode(&no_bom, None).text, "plain text");
+    }
+
+    #[test]
+    fn test_decode_gbk() {
+        let (bytes, _, _) = encoding_rs::GBK.encode("中文文本搜索测试，这是一个简体中文文件。");
+        let decoded = decode(&bytes, None);
+        assert!(!decoded.had_errors);
+        assert_eq!(decoded.text, "中文文本搜索测试，这是一个简体中文文件。");
+
+        let forced = decode(&bytes, Some(resolve_label("gb18030").unwrap()));
+        assert_eq!(forced.encoding.name(), "gb18030");
+        assert!(resolve_label("no-such-charset").is_err());
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(looks_binary(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00"));
+        assert!(!looks_binary(b"plain text, no nul bytes"));
+        let utf16: Vec<u8> = "text".encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+        assert!(!looks_binary(&utf16));
+    }
+}
@@ -0,0 +1,659 @@
+//! Compatibility with Everything's `es.exe`: its search syntax, command line switches and output formats.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use chrono::{DateTime, Local, Utc};
+use regex::{Regex, RegexBuilder};
+use thiserror::Error;
+use crate::efu::{csv_field, to_filetime};
+use crate::indexer::FileMetadata;
+use crate::query::SortKey;
+
+/// es.exe exit codes, so scripts checking them keep working.
+#[derive(Debug, Error)]
+pub enum EsError {
+    #[error("Expected an additional command line option after {0}")]
+    MissingValue(String),
+    #[error("Unknown command line option: {0}")]
+    UnknownSwitch(String),
+    #[error("Invalid value for {switch}: {value}")]
+    BadValue { switch: String, value: String },
+    #[error("Invalid search: {0}")]
+    BadSearch(#[from] regex::Error),
+}
+
+impl EsError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            EsError::MissingValue(_) => 4,
+            EsError::UnknownSwitch(_) | EsError::BadValue { .. } | EsError::BadSearch(_) => 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Match terms against the full path instead of the file name.
+    pub match_path: bool,
+    /// Treat the whole search as one regular expression.
+    pub regex: bool,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    /// Matches the name, or the full path when the term asked for it.
+    Text { regex: Regex, full_path: bool },
+    Extensions(Vec<String>),
+}
+
+#[derive(Debug)]
+struct Alternative {
+    negate: bool,
+    matcher: Matcher,
+}
+
+/// A compiled Everything search: space-separated terms must all match, `a|b` matches either, `!a` excludes.
+/// Terms with `*` or `?` match the whole name, terms containing a path separator match the full path,
+/// and `ext:rs;toml` and `path:` work like in Everything.
+#[derive(Debug)]
+pub struct EverythingQuery {
+    clauses: Vec<Vec<Alternative>>,
+}
+
+/// Splits on whitespace outside double quotes; the quotes themselves are dropped.
+fn tokenize(search: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in search.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn wildcard_pattern(term: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in term.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+fn text_regex(pattern: &str, options: &MatchOptions) -> Result<Regex, regex::Error> {
+    let pattern = if options.whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern.to_string() };
+    RegexBuilder::new(&pattern).case_insensitive(!options.case_sensitive).build()
+}
+
+fn compile_term(term: &str, options: &MatchOptions) -> Result<Alternative, regex::Error> {
+    let (negate, term) = match term.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, term),
+    };
+    let lower = term.to_ascii_lowercase();
+    if let Some(list) = lower.strip_prefix("ext:") {
+        let extensions = list.split(';').filter(|ext| !ext.is_empty()).map(|ext| ext.trim_start_matches('.').to_string()).collect();
+        return Ok(Alternative { negate, matcher: Matcher::Extensions(extensions) });
+    }
+    let (term, force_path) = match lower.strip_prefix("path:") {
+        Some(_) => (&term[5..], true),
+        None => (term, false),
+    };
+    let full_path = force_path || options.match_path || term.contains(['/', '\\']);
+    let pattern = if term.contains(['*', '?']) { wildcard_pattern(term) } else { regex::escape(term) };
+    Ok(Alternative { negate, matcher: Matcher::Text { regex: text_regex(&pattern, options)?, full_path } })
+}
+
+impl EverythingQuery {
+    pub fn parse(search: &str, options: &MatchOptions) -> Result<Self, regex::Error> {
+        if options.regex {
+            let matcher = Matcher::Text { regex: text_regex(search, options)?, full_path: options.match_path };
+            return Ok(EverythingQuery { clauses: vec![vec![Alternative { negate: false, matcher }]] });
+        }
+
+        // `a | b` with spaces around the bar is the same as `a|b`.
+        let mut groups: Vec<String> = Vec::new();
+        let mut join_next = false;
+        for token in tokenize(search) {
+            if token == "|" {
+                join_next = true;
+                continue;
+            }
+            match groups.last_mut() {
+                Some(last) if join_next || last.ends_with('|') || token.starts_with('|') => {
+                    if !last.ends_with('|') && !token.starts_with('|') {
+                        last.push('|');
+                    }
+                    last.push_str(&token);
+                }
+                _ => groups.push(token),
+            }
+            join_next = false;
+        }
+
+        let clauses = groups
+            .iter()
+            .map(|group| group.split('|').filter(|term| !term.is_empty()).map(|term| compile_term(term, options)).collect())
+            .collect::<Result<Vec<Vec<_>>, _>>()?;
+        Ok(EverythingQuery { clauses: clauses.into_iter().filter(|clause| !clause.is_empty()).collect() })
+    }
+
+    pub fn matches(&self, file: &FileMetadata) -> bool {
+        self.clauses.iter().all(|clause| {
+            clause.iter().any(|alternative| {
+                let hit = match &alternative.matcher {
+                    Matcher::Text { regex, full_path: true } => regex.is_match(&file.path),
+                    Matcher::Text { regex, full_path: false } => regex.is_match(&file.name),
+                    Matcher::Extensions(extensions) => {
+                        file.extension().is_some_and(|ext| extensions.iter().any(|wanted| ext.eq_ignore_ascii_case(wanted)))
+                    }
+                };
+                hit != alternative.negate
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// `Filename` in CSV/EFU: the full path and name.
+    FullPath,
+    Name,
+    /// The containing folder.
+    Path,
+    Extension,
+    Size,
+    DateModified,
+    DateCreated,
+    DateAccessed,
+    Attributes,
+}
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::FullPath => "Filename",
+            Column::Name => "Name",
+            Column::Path => "Path",
+            Column::Extension => "Extension",
+            Column::Size => "Size",
+            Column::DateModified => "Date Modified",
+            Column::DateCreated => "Date Created",
+            Column::DateAccessed => "Date Accessed",
+            Column::Attributes => "Attributes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Txt,
+    Csv,
+    Tsv,
+    Efu,
+    M3u,
+    M3u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathFilter {
+    /// Anywhere below the folder.
+    Under(PathBuf),
+    /// Directly inside the folder.
+    Parent(PathBuf),
+}
+
+impl PathFilter {
+    pub fn matches(&self, file: &FileMetadata) -> bool {
+        let path = Path::new(&file.path);
+        match self {
+            PathFilter::Under(folder) => path.starts_with(folder),
+            PathFilter::Parent(folder) => path.parent() == Some(folder.as_path()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EsAction {
+    #[default]
+    List,
+    ResultCount,
+    TotalSize,
+    Help,
+}
+
+/// Everything es.exe understood, in the shape the rest of this module uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EsArgs {
+    pub search: String,
+    pub match_options: MatchOptions,
+    pub path_filter: Option<PathFilter>,
+    /// `/ad`: the index only holds files, so this always yields nothing.
+    pub folders_only: bool,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub offset: usize,
+    pub max_results: Option<usize>,
+    /// In command line order; empty means just the full path.
+    pub columns: Vec<Column>,
+    pub format: OutputFormat,
+    pub export: Option<PathBuf>,
+    pub no_header: bool,
+    pub double_quote: bool,
+    /// 0 = auto, 1 = bytes, 2 = KB, 3 = MB.
+    pub size_format: u8,
+    /// 0 = local, 1 = ISO-8601 local, 2 = FILETIME, 3 = ISO-8601 UTC.
+    pub date_format: u8,
+    pub action: EsAction,
+}
+
+impl Default for EsArgs {
+    fn default() -> Self {
+        EsArgs {
+            search: String::new(),
+            match_options: MatchOptions::default(),
+            path_filter: None,
+            folders_only: false,
+            sort: SortKey::Name,
+            descending: false,
+            offset: 0,
+            max_results: None,
+            columns: Vec::new(),
+            format: OutputFormat::Txt,
+            export: None,
+            no_header: false,
+            double_quote: false,
+            size_format: 1,
+            date_format: 0,
+            action: EsAction::List,
+        }
+    }
+}
+
+fn sort_key(name: &str) -> Option<SortKey> {
+    match name {
+        "name" | "n" => Some(SortKey::Name),
+        "path" | "full-path" | "p" => Some(SortKey::Path),
+        "size" | "s" => Some(SortKey::Size),
+        "extension" | "ext" | "e" => Some(SortKey::Extension),
+        "date-modified" | "dm" | "d" => Some(SortKey::Modified),
+        _ => None,
+    }
+}
+
+/// The `-ascending`/`-descending` suffix of `-sort size-descending`, `-sort-size-ascending` and friends.
+fn split_direction(name: &str) -> (&str, Option<bool>) {
+    if let Some(name) = name.strip_suffix("-descending") {
+        (name, Some(true))
+    } else if let Some(name) = name.strip_suffix("-ascending") {
+        (name, Some(false))
+    } else {
+        (name, None)
+    }
+}
+
+fn column(name: &str) -> Option<Column> {
+    match name {
+        "name" => Some(Column::Name),
+        "path-column" => Some(Column::Path),
+        "full-path-and-name" | "filename-column" => Some(Column::FullPath),
+        "extension" | "ext" => Some(Column::Extension),
+        "size" => Some(Column::Size),
+        "date-modified" | "dm" => Some(Column::DateModified),
+        "date-created" | "dc" => Some(Column::DateCreated),
+        "date-accessed" | "da" => Some(Column::DateAccessed),
+        "attributes" | "attribs" | "attrib" => Some(Column::Attributes),
+        _ => None,
+    }
+}
+
+fn format_of(name: &str) -> Option<OutputFormat> {
+    match name {
+        "txt" => Some(OutputFormat::Txt),
+        "csv" => Some(OutputFormat::Csv),
+        "tsv" => Some(OutputFormat::Tsv),
+        "efu" => Some(OutputFormat::Efu),
+        "m3u" => Some(OutputFormat::M3u),
+        "m3u8" => Some(OutputFormat::M3u8),
+        _ => None,
+    }
+}
+
+impl EsArgs {
+    /// Parses es.exe arguments (without the program name). Switches start with `-` or `/` and are
+    /// case-insensitive; anything else is search text. On Unix a `/...` argument that isn't a known
+    /// switch is treated as search text, since it's most likely a path.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, EsError> {
+        let mut parsed = EsArgs::default();
+        let mut terms: Vec<String> = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let Some(switch) = arg.strip_prefix('-').or_else(|| arg.strip_prefix('/')).filter(|s| !s.is_empty()) else {
+                terms.push(arg);
+                continue;
+            };
+            let switch = switch.to_ascii_lowercase();
+            let value = |args: &mut dyn Iterator<Item = String>| args.next().ok_or_else(|| EsError::MissingValue(arg.clone()));
+            let number = |raw: String| raw.parse::<usize>().map_err(|_| EsError::BadValue { switch: arg.clone(), value: raw });
+
+            match switch.as_str() {
+                "r" | "regex" => {
+                    parsed.match_options.regex = true;
+                    terms.push(value(&mut args)?);
+                }
+                "i" | "case" => parsed.match_options.case_sensitive = true,
+                "nocase" => parsed.match_options.case_sensitive = false,
+                "w" | "ww" | "whole-word" | "whole-words" => parsed.match_options.whole_word = true,
+                "p" | "match-path" => parsed.match_options.match_path = true,
+                // Diacritics are always significant here.
+                "a" | "diacritics" | "nodiacritics" => {}
+                "o" | "offset" => parsed.offset = number(value(&mut args)?)?,
+                "n" | "max-results" => parsed.max_results = Some(number(value(&mut args)?)?),
+                "path" => parsed.path_filter = Some(PathFilter::Under(PathBuf::from(value(&mut args)?))),
+                "parent-path" => {
+                    let path = PathBuf::from(value(&mut args)?);
+                    parsed.path_filter = Some(PathFilter::Under(path.parent().map(Path::to_path_buf).unwrap_or(path)));
+                }
+                "parent" => parsed.path_filter = Some(PathFilter::Parent(PathBuf::from(value(&mut args)?))),
+                "ad" => parsed.folders_only = true,
+                "a-d" => parsed.folders_only = false,
+                "s" => parsed.sort = SortKey::Path,
+                "sort" => {
+                    let raw = value(&mut args)?.to_ascii_lowercase();
+                    let (name, descending) = split_direction(&raw);
+                    parsed.sort = sort_key(name).ok_or_else(|| EsError::BadValue { switch: arg.clone(), value: raw.clone() })?;
+                    if let Some(descending) = descending {
+                        parsed.descending = descending;
+                    }
+                }
+                "sort-ascending" => parsed.descending = false,
+                "sort-descending" => parsed.descending = true,
+                "no-header" => parsed.no_header = true,
+                "double-quote" => parsed.double_quote = true,
+                "size-format" | "date-format" => {
+                    let raw = value(&mut args)?;
+                    let format = raw.parse::<u8>().ok().filter(|format| *format <= 3).ok_or(EsError::BadValue { switch: arg.clone(), value: raw })?;
+                    if switch == "size-format" { parsed.size_format = format } else { parsed.date_format = format }
+                }
+                "get-result-count" => parsed.action = EsAction::ResultCount,
+                "get-total-size" => parsed.action = EsAction::TotalSize,
+                "h" | "help" | "?" => parsed.action = EsAction::Help,
+                // There is only ever one index, so instance selection and IPC timeouts don't apply.
+                "instance" | "timeout" => {
+                    value(&mut args)?;
+                }
+                other => {
+                    if let Some(format) = format_of(other) {
+                        parsed.format = format;
+                    } else if let Some(format) = other.strip_prefix("export-").and_then(format_of) {
+                        parsed.format = format;
+                        parsed.export = Some(PathBuf::from(value(&mut args)?));
+                    } else if let Some(column) = column(other) {
+                        if !parsed.columns.contains(&column) {
+                            parsed.columns.push(column);
+                        }
+                    } else if let Some((key, descending)) = other.strip_prefix("sort-").map(split_direction).and_then(|(name, dir)| Some((sort_key(name)?, dir))) {
+                        parsed.sort = key;
+                        parsed.descending = descending.unwrap_or(false);
+                    } else if let Some((key, descending)) = other.strip_prefix("o").and_then(|order| {
+                        let (descending, order) = match order.strip_prefix('-') { Some(order) => (true, order), None => (false, order) };
+                        Some((sort_key(order)?, descending))
+                    }) {
+                        // DIR-style `/on`, `/o-s`...
+                        parsed.sort = key;
+                        parsed.descending = descending;
+                    } else if cfg!(not(windows)) && arg.starts_with('/') {
+                        terms.push(arg);
+                    } else {
+                        return Err(EsError::UnknownSwitch(arg));
+                    }
+                }
+            }
+        }
+
+        parsed.search = terms.join(" ");
+        Ok(parsed)
+    }
+
+    /// The matching page of `files`, in the requested order.
+    pub fn run<'a>(&self, files: &'a [FileMetadata]) -> Result<Vec<&'a FileMetadata>, EsError> {
+        if self.folders_only {
+            return Ok(Vec::new());
+        }
+        let query = EverythingQuery::parse(&self.search, &self.match_options)?;
+        let mut results: Vec<&FileMetadata> = files
+            .iter()
+            .filter(|file| self.path_filter.as_ref().is_none_or(|filter| filter.matches(file)) && query.matches(file))
+            .collect();
+        results.sort_by(|a, b| {
+            let ordering = self.sort.compare(a, b);
+            if self.descending { ordering.reverse() } else { ordering }
+        });
+        Ok(results.into_iter().skip(self.offset).take(self.max_results.unwrap_or(usize::MAX)).collect())
+    }
+}
+
+fn format_size(size: u64, size_format: u8) -> String {
+    match size_format {
+        0 if size < 1024 => format!("{} bytes", size),
+        0 if size < 1024 * 1024 => format!("{} KB", size.div_ceil(1024)),
+        0 if size < 1024 * 1024 * 1024 => format!("{:.1} MB", size as f64 / (1024.0 * 1024.0)),
+        0 => format!("{:.1} GB", size as f64 / (1024.0 * 1024.0 * 1024.0)),
+        2 => format!("{} KB", size.div_ceil(1024)),
+        3 => format!("{} MB", size.div_ceil(1024 * 1024)),
+        _ => size.to_string(),
+    }
+}
+
+fn format_date(time: DateTime<Utc>, date_format: u8) -> String {
+    match date_format {
+        1 => time.with_timezone(&Local).format("%Y-%m-%dT%H:%M:%S").to_string(),
+        2 => to_filetime(time).map(|ticks| ticks.to_string()).unwrap_or_default(),
+        3 => time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        _ => time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+    }
+}
+
+fn parent_of(path: &str) -> &str {
+    Path::new(path).parent().and_then(Path::to_str).unwrap_or_default()
+}
+
+/// Writes results the way es.exe prints or exports them.
+pub fn write_results(out: &mut impl Write, results: &[&FileMetadata], args: &EsArgs) -> io::Result<()> {
+    let columns = match args.format {
+        // EFU has a fixed layout that Everything's importer expects.
+        OutputFormat::Efu => vec![Column::FullPath, Column::Size, Column::DateModified, Column::DateCreated, Column::Attributes],
+        _ if args.columns.is_empty() => vec![Column::FullPath],
+        _ => args.columns.clone(),
+    };
+    let date_format = if args.format == OutputFormat::Efu { 2 } else { args.date_format };
+    let cell = |file: &FileMetadata, column: Column| -> String {
+        match column {
+            Column::FullPath => file.path.clone(),
+            Column::Name => file.name.clone(),
+            Column::Path => parent_of(&file.path).to_string(),
+            Column::Extension => file.extension().unwrap_or_default().to_string(),
+            Column::Size if args.format == OutputFormat::Efu => file.size.to_string(),
+            Column::Size => format_size(file.size, args.size_format),
+            Column::DateModified => format_date(file.modified_time, date_format),
+            Column::DateCreated => file.created_time.map(|time| format_date(time, date_format)).unwrap_or_default(),
+            Column::Attributes => file.attributes.map(|bits| bits.to_string()).unwrap_or_default(),
+            // Not recorded by the scanner.
+            Column::DateAccessed => String::new(),
+        }
+    };
+
+    match args.format {
+        OutputFormat::M3u | OutputFormat::M3u8 => {
+            if !args.no_header {
+                writeln!(out, "#EXTM3U")?;
+            }
+            for file in results {
+                writeln!(out, "{}", file.path)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Efu | OutputFormat::Tsv => {
+            let tsv = args.format == OutputFormat::Tsv;
+            let separator = if tsv { "\t" } else { "," };
+            if !args.no_header {
+                writeln!(out, "{}", columns.iter().map(|column| column.header()).collect::<Vec<_>>().join(separator))?;
+            }
+            for file in results {
+                let row: Vec<String> = columns
+                    .iter()
+                    .map(|&column| {
+                        let value = cell(file, column);
+                        if tsv {
+                            value
+                        } else {
+                            let is_text = matches!(column, Column::FullPath | Column::Name | Column::Path | Column::Extension);
+                            csv_field(&value, args.double_quote || (args.format == OutputFormat::Efu && is_text))
+                        }
+                    })
+                    .collect();
+                writeln!(out, "{}", row.join(separator))?;
+            }
+        }
+        OutputFormat::Txt => {
+            let rows: Vec<Vec<String>> = results.iter().map(|file| columns.iter().map(|&column| cell(file, column)).collect()).collect();
+            let size_width = columns
+                .iter()
+                .position(|&column| column == Column::Size)
+                .map(|index| rows.iter().map(|row| row[index].len()).max().unwrap_or(0))
+                .unwrap_or(0);
+            for row in rows {
+                let line: Vec<String> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|(&column, value)| match column {
+                        Column::Size => format!("{:>width$}", value, width = size_width),
+                        Column::FullPath | Column::Name | Column::Path if args.double_quote => format!("\"{}\"", value),
+                        _ => value,
+                    })
+                    .collect();
+                writeln!(out, "{}", line.join(" "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub const HELP: &str = "\
+Usage: es [options] [search text]
+
+Search options
+  -r, -regex <search>        Search with a regular expression.
+  -i, -case                  Match case.
+  -w, -ww, -whole-word       Match whole words.
+  -p, -match-path            Match the full path and file name.
+  -o, -offset <n>            Skip the first <n> results.
+  -n, -max-results <n>       Show at most <n> results.
+  -path <path>               Only files under <path>.
+  -parent-path <path>        Only files under the parent of <path>.
+  -parent <path>             Only files directly inside <path>.
+  /ad, /a-d                  Folders only (never any) / files only.
+
+Sorting
+  -s                         Sort by full path.
+  -sort <name[-ascending|-descending]>
+                             name, path, size, extension or date-modified.
+  -sort-ascending, -sort-descending
+  /on, /o-s, /oe, /od ...    DIR-style sort switches.
+
+Display
+  -name -path-column -full-path-and-name -extension -size
+  -date-modified (-dm) -date-created (-dc) -date-accessed (-da) -attributes
+  -size-format <0-3>         0 auto, 1 bytes, 2 KB, 3 MB.
+  -date-format <0-3>         0 local, 1 ISO-8601, 2 FILETIME, 3 ISO-8601 UTC.
+  -csv -efu -tsv -txt -m3u -m3u8
+  -export-csv <file> (and -export-efu, -export-tsv, -export-txt, -export-m3u, -export-m3u8)
+  -no-header -double-quote
+  -get-result-count -get-total-size
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn file(path: &str, size: u64) -> FileMetadata {
+        FileMetadata {
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            size,
+            modified_time: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn args(line: &str) -> EsArgs {
+        EsArgs::parse(line.split_whitespace().map(str::to_string)).unwrap()
+    }
+
+    #[test]
+    fn test_search_syntax() {
+        let files = vec![
+            file("/home/me/report.pdf", 3000),
+            file("/home/me/Report-draft.docx", 2000),
+            file("/home/me/src/main.rs", 100),
+            file("/srv/report.txt", 10),
+        ];
+        let names = |line: &str| args(line).run(&files).unwrap().into_iter().map(|file| file.name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names("report !draft"), vec!["report.pdf", "report.txt"]);
+        assert_eq!(names("report ext:pdf;txt -sort size-descending"), vec!["report.pdf", "report.txt"]);
+        assert_eq!(names("*.rs | *.txt"), vec!["main.rs", "report.txt"]);
+        assert_eq!(names("me/ -n 1 -sort-size"), vec!["main.rs"]);
+        assert_eq!(names("-i Report"), vec!["Report-draft.docx"]);
+        assert_eq!(names("-r ^report\\.(pdf|txt)$ -path /home"), vec!["report.pdf"]);
+        assert_eq!(names("-parent /home/me /o-s"), vec!["report.pdf", "Report-draft.docx"]);
+        assert_eq!(names("-ww main"), vec!["main.rs"]);
+        assert!(names("report /ad").is_empty());
+
+        assert!(matches!(EsArgs::parse(["-n".to_string()]), Err(EsError::MissingValue(_))));
+        assert_eq!(EsArgs::parse(["-bogus".to_string()]).unwrap_err().exit_code(), 6);
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let files = [file("/data/a,b.txt", 1500), file("/data/c.rs", 20)];
+        let results: Vec<&FileMetadata> = files.iter().collect();
+        let render = |line: &str| {
+            let mut out = Vec::new();
+            write_results(&mut out, &results, &args(line)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(render(""), "/data/a,b.txt\n/data/c.rs\n");
+        assert_eq!(render("-size -name"), "1500 a,b.txt\n  20 c.rs\n");
+        assert_eq!(render("-csv -name -size -size-format 2"), "Name,Size\n\"a,b.txt\",2 KB\nc.rs,1 KB\n");
+        assert_eq!(
+            render("-efu -no-header"),
+            "\"/data/a,b.txt\",1500,133590384000000000,,\n\"/data/c.rs\",20,133590384000000000,,\n"
+        );
+        assert_eq!(render("-m3u8"), "#EXTM3U\n/data/a,b.txt\n/data/c.rs\n");
+        assert_eq!(render("-tsv -path-column -extension -date-format 3"), "Path\tExtension\n/data\ttxt\n/data\trs\n");
+        assert_eq!(format_date(DateTime::<Utc>::MAX_UTC, 2), "");
+    }
+}
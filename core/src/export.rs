@@ -0,0 +1,254 @@
+//! Writing search results out as CSV, JSON, JSON Lines, M3U, plain path lists or EFU, one row at a time.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::efu::{self, csv_field};
+use crate::indexer::FileMetadata;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One array; written incrementally, never built in memory.
+    Json,
+    Jsonl,
+    /// One path per line.
+    Paths,
+    M3u,
+    /// Everything's file list: `Filename,Size,Date Modified,Date Created,Attributes` with FILETIME dates.
+    Efu,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            "paths" | "txt" | "lst" => Ok(ExportFormat::Paths),
+            "m3u" | "m3u8" => Ok(ExportFormat::M3u),
+            "efu" => Ok(ExportFormat::Efu),
+            other => Err(format!("Unknown export format '{}' (expected csv, json, jsonl, paths, m3u or efu)", other)),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Guesses the format from a file name such as `results.jsonl`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|ext| ext.to_str()).and_then(|ext| ext.parse().ok())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportColumn {
+    Path,
+    Name,
+    /// The containing folder.
+    Directory,
+    Extension,
+    Size,
+    Modified,
+    /// Id of the removable or external volume the file is on.
+    Volume,
+    /// Whether the entry comes from a catalog (an imported list or an unplugged volume).
+    Offline,
+}
+
+impl FromStr for ExportColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "path" => Ok(ExportColumn::Path),
+            "name" => Ok(ExportColumn::Name),
+            "directory" | "dir" | "folder" => Ok(ExportColumn::Directory),
+            "extension" | "ext" => Ok(ExportColumn::Extension),
+            "size" => Ok(ExportColumn::Size),
+            "modified" | "date" | "mtime" => Ok(ExportColumn::Modified),
+            "volume" => Ok(ExportColumn::Volume),
+            "offline" => Ok(ExportColumn::Offline),
+            other => Err(format!("Unknown column '{}' (expected path, name, directory, extension, size, modified, volume or offline)", other)),
+        }
+    }
+}
+
+impl ExportColumn {
+    pub const DEFAULT: [ExportColumn; 4] = [ExportColumn::Path, ExportColumn::Name, ExportColumn::Size, ExportColumn::Modified];
+
+    fn key(self) -> &'static str {
+        match self {
+            ExportColumn::Path => "path",
+            ExportColumn::Name => "name",
+            ExportColumn::Directory => "directory",
+            ExportColumn::Extension => "extension",
+            ExportColumn::Size => "size",
+            ExportColumn::Modified => "modified",
+            ExportColumn::Volume => "volume",
+            ExportColumn::Offline => "offline",
+        }
+    }
+
+    fn text(self, file: &FileMetadata) -> String {
+        match self {
+            ExportColumn::Path => file.path.clone(),
+            ExportColumn::Name => file.name.clone(),
+            ExportColumn::Directory => Path::new(&file.path).parent().map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_default(),
+            ExportColumn::Extension => file.extension().unwrap_or_default().to_string(),
+            ExportColumn::Size => file.size.to_string(),
+            ExportColumn::Modified => file.modified_time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            ExportColumn::Volume => file.volume.clone().unwrap_or_default(),
+            ExportColumn::Offline => file.source.is_some().to_string(),
+        }
+    }
+
+    /// Sizes stay numbers and times are Unix seconds, as in the rest of the JSON the app produces.
+    fn json(self, file: &FileMetadata) -> Value {
+        match self {
+            ExportColumn::Size => Value::from(file.size),
+            ExportColumn::Modified => Value::from(file.modified_time.timestamp()),
+            ExportColumn::Volume => file.volume.clone().map_or(Value::Null, Value::from),
+            ExportColumn::Offline => Value::from(file.source.is_some()),
+            column => Value::from(column.text(file)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// In output order; empty means path, name, size and modified. Path lists, M3U and EFU have fixed columns.
+    pub columns: Vec<ExportColumn>,
+}
+
+impl ExportOptions {
+    fn columns(&self) -> &[ExportColumn] {
+        if self.columns.is_empty() { &ExportColumn::DEFAULT } else { &self.columns }
+    }
+}
+
+/// Writes `files` to `out` as they come and returns how many were written.
+pub fn write_export<'a>(out: &mut impl Write, files: impl IntoIterator<Item = &'a FileMetadata>, options: &ExportOptions) -> io::Result<usize> {
+    let columns = options.columns();
+    let mut count = 0;
+    match options.format {
+        ExportFormat::Csv => writeln!(out, "{}", columns.iter().map(|column| column.key()).collect::<Vec<_>>().join(","))?,
+        ExportFormat::Json => out.write_all(b"[")?,
+        ExportFormat::M3u => writeln!(out, "#EXTM3U")?,
+        ExportFormat::Efu => efu::write_efu_header(out)?,
+        ExportFormat::Jsonl | ExportFormat::Paths => {}
+    }
+
+    for file in files {
+        match options.format {
+            ExportFormat::Csv => {
+                let row: Vec<String> = columns.iter().map(|column| csv_field(&column.text(file), false)).collect();
+                writeln!(out, "{}", row.join(","))?;
+            }
+            ExportFormat::Json | ExportFormat::Jsonl => {
+                if options.format == ExportFormat::Json {
+                    out.write_all(if count == 0 { b"\n" } else { b",\n" })?;
+                }
+                let row: Map<String, Value> = columns.iter().map(|column| (column.key().to_string(), column.json(file))).collect();
+                serde_json::to_writer(&mut *out, &row)?;
+                if options.format == ExportFormat::Jsonl {
+                    out.write_all(b"\n")?;
+                }
+            }
+            ExportFormat::Paths | ExportFormat::M3u => writeln!(out, "{}", file.path)?,
+            ExportFormat::Efu => efu::write_efu_row(out, file)?,
+        }
+        count += 1;
+    }
+
+    if options.format == ExportFormat::Json {
+        out.write_all(if count == 0 { b"]\n" } else { b"\n]\n" })?;
+    }
+    Ok(count)
+}
+
+/// Exports into `path`, which only appears once the export is complete.
+pub fn export_to_file<'a>(path: &Path, files: impl IntoIterator<Item = &'a FileMetadata>, options: &ExportOptions) -> io::Result<usize> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = Path::new(&partial);
+
+    let written = (|| {
+        let mut writer = BufWriter::new(fs::File::create(partial)?);
+        let count = write_export(&mut writer, files, options)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(count)
+    })();
+    match written {
+        Ok(count) => fs::rename(partial, path).map(|()| count),
+        Err(e) => {
+            let _ = fs::remove_file(partial);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn files() -> Vec<FileMetadata> {
+        let modified_time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        vec![
+            FileMetadata { path: "/data/a,b.txt".to_string(), name: "a,b.txt".to_string(), size: 10, modified_time, ..Default::default() },
+            FileMetadata { path: "/data/c.rs".to_string(), name: "c.rs".to_string(), size: 20, modified_time, ..Default::default() },
+        ]
+    }
+
+    fn render(format: ExportFormat, columns: &[ExportColumn]) -> String {
+        let mut out = Vec::new();
+        let options = ExportOptions { format, columns: columns.to_vec() };
+        assert_eq!(write_export(&mut out, &files(), &options).unwrap(), 2);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            render(ExportFormat::Csv, &[]),
+            "path,name,size,modified\n\"/data/a,b.txt\",\"a,b.txt\",10,2024-05-01T12:00:00Z\n/data/c.rs,c.rs,20,2024-05-01T12:00:00Z\n"
+        );
+        assert_eq!(render(ExportFormat::Csv, &[ExportColumn::Extension, ExportColumn::Directory]), "extension,directory\ntxt,/data\nrs,/data\n");
+
+        let json: Value = serde_json::from_str(&render(ExportFormat::Json, &[ExportColumn::Name, ExportColumn::Size])).unwrap();
+        assert_eq!(json, serde_json::json!([{ "name": "a,b.txt", "size": 10 }, { "name": "c.rs", "size": 20 }]));
+        let mut empty = Vec::new();
+        write_export(&mut empty, &[], &ExportOptions { format: ExportFormat::Json, columns: Vec::new() }).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&empty).unwrap(), serde_json::json!([]));
+
+        assert_eq!(render(ExportFormat::Jsonl, &[ExportColumn::Modified]), "{\"modified\":1714564800}\n{\"modified\":1714564800}\n");
+        assert_eq!(render(ExportFormat::Paths, &[ExportColumn::Size]), "/data/a,b.txt\n/data/c.rs\n");
+        assert_eq!(render(ExportFormat::M3u, &[]), "#EXTM3U\n/data/a,b.txt\n/data/c.rs\n");
+        assert!(render(ExportFormat::Efu, &[]).ends_with("\"/data/c.rs\",20,133590384000000000,,\n"));
+    }
+
+    #[test]
+    fn test_export_to_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("results.jsonl");
+        let format = ExportFormat::from_path(&path).unwrap();
+        assert_eq!(export_to_file(&path, &files(), &ExportOptions { format, columns: Vec::new() }).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert!(!temp_dir.path().join("results.jsonl.partial").exists());
+
+        let missing = temp_dir.path().join("missing").join("out.csv");
+        assert!(export_to_file(&missing, &files(), &ExportOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,497 @@
+//! Plain-text extraction from documents (docx, xlsx, pptx, odt, pdf...) with a small result cache.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use log::{debug, warn};
+use quick_xml::{events::Event, Reader};
+use zip::ZipArchive;
+
+const CACHE_CAPACITY: usize = 256;
+
+/// Turns a document format into plain text. Implementations must not touch the filesystem;
+/// the registry reads the file and enforces size limits.
+pub trait TextExtractor: Send + Sync {
+    fn extensions(&self) -> &[&'static str];
+    fn default_size_limit(&self) -> u64;
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    #[error("file is larger than the {limit} byte limit for .{extension} extraction")]
+    TooLarge { extension: String, limit: u64 },
+    #[error("failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed document: {0}")]
+    Malformed(String),
+}
+
+impl From<zip::result::ZipError> for ExtractError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ExtractError::Malformed(e.to_string())
+    }
+}
+
+impl From<quick_xml::Error> for ExtractError {
+    fn from(e: quick_xml::Error) -> Self {
+        ExtractError::Malformed(e.to_string())
+    }
+}
+
+/// The most a single part of an Office or OpenDocument file may decompress to, so a zip bomb can't exhaust memory.
+const MAX_ZIP_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+fn read_zip_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>, ExtractError> {
+    let too_large = || ExtractError::TooLarge { extension: "xml".to_string(), limit: MAX_ZIP_ENTRY_SIZE };
+    let entry = archive.by_name(name)?;
+    if entry.size() > MAX_ZIP_ENTRY_SIZE {
+        return Err(too_large());
+    }
+    // The declared size can lie, so the read itself is capped too.
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.take(MAX_ZIP_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ZIP_ENTRY_SIZE {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
+/// Entries named `{prefix}{n}.xml`, in numeric order (slide2 before slide10).
+fn numbered_entries(archive: &ZipArchive<Cursor<&[u8]>>, prefix: &str) -> Vec<String> {
+    let mut entries: Vec<(u32, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name.strip_prefix(prefix)?.strip_suffix(".xml")?.parse().ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    entries.sort();
+    entries.into_iter().map(|(_, name)| name).collect()
+}
+
+/// Which XML elements carry text and which ones end a line or a cell.
+struct XmlTextRules {
+    text: &'static [&'static [u8]],
+    newline: &'static [&'static [u8]],
+    tab: &'static [&'static [u8]],
+    space: &'static [&'static [u8]],
+}
+
+fn xml_text(xml: &[u8], rules: &XmlTextRules, out: &mut String) -> Result<(), ExtractError> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut text_depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) if rules.text.contains(&e.local_name().as_ref()) => text_depth += 1,
+            Event::End(e) => {
+                let name = e.local_name();
+                if rules.text.contains(&name.as_ref()) {
+                    text_depth = text_depth.saturating_sub(1);
+                }
+                if rules.newline.contains(&name.as_ref()) {
+                    out.push('\n');
+                } else if rules.tab.contains(&name.as_ref()) {
+                    out.push('\t');
+                }
+            }
+            Event::Empty(e) => {
+                let name = e.local_name();
+                if rules.newline.contains(&name.as_ref()) {
+                    out.push('\n');
+                } else if rules.tab.contains(&name.as_ref()) {
+                    out.push('\t');
+                } else if rules.space.contains(&name.as_ref()) {
+                    out.push(' ');
+                }
+            }
+            Event::Text(t) if text_depth > 0 => {
+                out.push_str(&t.unescape().map_err(|e| ExtractError::Malformed(e.to_string()))?);
+            }
+            Event::CData(t) if text_depth > 0 => out.push_str(&String::from_utf8_lossy(&t)),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+const WORD_RULES: XmlTextRules = XmlTextRules { text: &[b"t"], newline: &[b"p", b"br", b"cr"], tab: &[b"tab"], space: &[] };
+const SLIDE_RULES: XmlTextRules = XmlTextRules { text: &[b"t"], newline: &[b"p"], tab: &[], space: &[] };
+const ODF_RULES: XmlTextRules = XmlTextRules {
+    text: &[b"p", b"h"],
+    newline: &[b"p", b"h", b"line-break", b"table-row"],
+    tab: &[b"tab", b"table-cell"],
+    space: &[b"s"],
+};
+
+pub struct DocxExtractor;
+
+impl TextExtractor for DocxExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["docx", "docm", "dotx"]
+    }
+
+    fn default_size_limit(&self) -> u64 {
+        50 * 1024 * 1024
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut text = String::new();
+        xml_text(&read_zip_entry(&mut archive, "word/document.xml")?, &WORD_RULES, &mut text)?;
+        Ok(text)
+    }
+}
+
+pub struct PptxExtractor;
+
+impl TextExtractor for PptxExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["pptx", "pptm", "ppsx"]
+    }
+
+    fn default_size_limit(&self) -> u64 {
+        100 * 1024 * 1024
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut text = String::new();
+        for slide in numbered_entries(&archive, "ppt/slides/slide") {
+            xml_text(&read_zip_entry(&mut archive, &slide)?, &SLIDE_RULES, &mut text)?;
+        }
+        Ok(text)
+    }
+}
+
+pub struct XlsxExtractor;
+
+impl XlsxExtractor {
+    fn shared_strings(xml: &[u8]) -> Result<Vec<String>, ExtractError> {
+        let mut reader = Reader::from_reader(xml);
+        let mut buf = Vec::new();
+        let mut strings = Vec::new();
+        let mut current = String::new();
+        let mut in_text = false;
+
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+                Event::End(e) if e.local_name().as_ref() == b"t" => in_text = false,
+                Event::End(e) if e.local_name().as_ref() == b"si" => strings.push(std::mem::take(&mut current)),
+                Event::Text(t) if in_text => {
+                    current.push_str(&t.unescape().map_err(|e| ExtractError::Malformed(e.to_string()))?);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(strings)
+    }
+
+    /// One line per row, cells separated by tabs; shared-string cells are resolved.
+    fn sheet_text(xml: &[u8], shared: &[String], out: &mut String) -> Result<(), ExtractError> {
+        let mut reader = Reader::from_reader(xml);
+        let mut buf = Vec::new();
+        let mut cell_is_shared = false;
+        let mut in_value = false;
+        let mut row_has_cells = false;
+
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) if e.local_name().as_ref() == b"c" => {
+                    cell_is_shared = e
+                        .attributes()
+                        .flatten()
+                        .any(|attr| attr.key.local_name().as_ref() == b"t" && attr.value.as_ref() == b"s");
+                }
+                Event::Start(e) if matches!(e.local_name().as_ref(), b"v" | b"t") => in_value = true,
+                Event::End(e) if matches!(e.local_name().as_ref(), b"v" | b"t") => in_value = false,
+                Event::End(e) if e.local_name().as_ref() == b"row" => {
+                    if row_has_cells {
+                        out.push('\n');
+                    }
+                    row_has_cells = false;
+                }
+                Event::Text(t) if in_value => {
+                    let value = t.unescape().map_err(|e| ExtractError::Malformed(e.to_string()))?;
+                    if row_has_cells {
+                        out.push('\t');
+                    }
+                    match cell_is_shared.then(|| value.trim().parse::<usize>().ok()).flatten() {
+                        Some(index) => out.push_str(shared.get(index).map(String::as_str).unwrap_or_default()),
+                        None => out.push_str(&value),
+                    }
+                    row_has_cells = true;
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(())
+    }
+}
+
+impl TextExtractor for XlsxExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["xlsx", "xlsm"]
+    }
+
+    fn default_size_limit(&self) -> u64 {
+        50 * 1024 * 1024
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let shared = match read_zip_entry(&mut archive, "xl/sharedStrings.xml") {
+            Ok(xml) => Self::shared_strings(&xml)?,
+            Err(_) => Vec::new(),
+        };
+        let mut text = String::new();
+        for sheet in numbered_entries(&archive, "xl/worksheets/sheet") {
+            Self::sheet_text(&read_zip_entry(&mut archive, &sheet)?, &shared, &mut text)?;
+        }
+        Ok(text)
+    }
+}
+
+/// OpenDocument text, spreadsheet and presentation files all keep their body in `content.xml`.
+pub struct OdfExtractor;
+
+impl TextExtractor for OdfExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["odt", "ods", "odp"]
+    }
+
+    fn default_size_limit(&self) -> u64 {
+        50 * 1024 * 1024
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut text = String::new();
+        xml_text(&read_zip_entry(&mut archive, "content.xml")?, &ODF_RULES, &mut text)?;
+        Ok(text)
+    }
+}
+
+pub struct PdfExtractor;
+
+impl TextExtractor for PdfExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["pdf"]
+    }
+
+    fn default_size_limit(&self) -> u64 {
+        100 * 1024 * 1024
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        // pdf-extract panics on some malformed files instead of returning an error.
+        match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes)) {
+            Ok(Ok(text)) => Ok(text),
+            Ok(Err(e)) => Err(ExtractError::Malformed(e.to_string())),
+            Err(_) => Err(ExtractError::Malformed("PDF parser panicked".to_string())),
+        }
+    }
+}
+
+struct CachedText {
+    modified: SystemTime,
+    size: u64,
+    text: Arc<String>,
+}
+
+#[derive(Default)]
+struct TextCache {
+    entries: HashMap<PathBuf, CachedText>,
+    order: VecDeque<PathBuf>,
+}
+
+pub struct ExtractorRegistry {
+    extractors: Vec<Box<dyn TextExtractor>>,
+    size_limits: HashMap<String, u64>,
+    cache: Mutex<TextCache>,
+}
+
+impl std::fmt::Debug for ExtractorRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtractorRegistry")
+            .field("extractors", &self.extractors.len())
+            .field("size_limits", &self.size_limits)
+            .finish()
+    }
+}
+
+impl Default for ExtractorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExtractorRegistry {
+    pub fn new() -> Self {
+        ExtractorRegistry { extractors: Vec::new(), size_limits: HashMap::new(), cache: Mutex::new(TextCache::default()) }
+    }
+
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(PdfExtractor));
+        registry.register(Box::new(DocxExtractor));
+        registry.register(Box::new(XlsxExtractor));
+        registry.register(Box::new(PptxExtractor));
+        registry.register(Box::new(OdfExtractor));
+        registry
+    }
+
+    /// Later registrations take precedence for the extensions they claim.
+    pub fn register(&mut self, extractor: Box<dyn TextExtractor>) {
+        self.extractors.insert(0, extractor);
+    }
+
+    /// Overrides the size limit for one extension (without the dot).
+    pub fn set_size_limit(&mut self, extension: &str, limit: u64) {
+        self.size_limits.insert(extension.to_ascii_lowercase(), limit);
+    }
+
+    fn find(&self, path: &Path) -> Option<(&dyn TextExtractor, String)> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        let extractor = self.extractors.iter().find(|e| e.extensions().contains(&extension.as_str()))?;
+        Some((extractor.as_ref(), extension))
+    }
+
+    pub fn supports(&self, path: &Path) -> bool {
+        self.find(path).is_some()
+    }
+
+    fn limit_for(&self, extractor: &dyn TextExtractor, extension: &str) -> u64 {
+        self.size_limits.get(extension).copied().unwrap_or_else(|| extractor.default_size_limit())
+    }
+
+    /// The size limit for documents like `path`, or `None` when no extractor handles its extension.
+    pub fn size_limit(&self, path: &Path) -> Option<u64> {
+        let (extractor, extension) = self.find(path)?;
+        Some(self.limit_for(extractor, &extension))
+    }
+
+    /// Extracted text from `bytes` already read for `path`, such as an archive entry. Nothing is cached.
+    pub fn extract_bytes(&self, path: &Path, bytes: &[u8]) -> Result<Option<String>, ExtractError> {
+        let Some((extractor, extension)) = self.find(path) else { return Ok(None) };
+        let limit = self.limit_for(extractor, &extension);
+        if bytes.len() as u64 > limit {
+            return Err(ExtractError::TooLarge { extension, limit });
+        }
+        extractor.extract(bytes).map(Some)
+    }
+
+    /// Extracted text for `path`, or `None` when no extractor handles its extension.
+    /// Results are cached until the file's mtime or size changes.
+    pub fn extract(&self, path: &Path) -> Result<Option<Arc<String>>, ExtractError> {
+        let Some((extractor, extension)) = self.find(path) else { return Ok(None) };
+        let meta = fs::metadata(path)?;
+        let limit = self.limit_for(extractor, &extension);
+        if meta.len() > limit {
+            return Err(ExtractError::TooLarge { extension, limit });
+        }
+        let modified = meta.modified()?;
+
+        if let Ok(cache) = self.cache.lock() {
+            if let Some(cached) = cache.entries.get(path) {
+                if cached.modified == modified && cached.size == meta.len() {
+                    return Ok(Some(cached.text.clone()));
+                }
+            }
+        }
+
+        let bytes = fs::read(path)?;
+        let text = match extractor.extract(&bytes) {
+            Ok(text) => Arc::new(text),
+            Err(e) => {
+                warn!("Text extraction failed for {}: {}", path.display(), e);
+                return Err(e);
+            }
+        };
+        debug!("Extracted {} chars from {}", text.len(), path.display());
+
+        if let Ok(mut cache) = self.cache.lock() {
+            let cached = CachedText { modified, size: meta.len(), text: text.clone() };
+            if cache.entries.insert(path.to_path_buf(), cached).is_none() {
+                cache.order.push_back(path.to_path_buf());
+            }
+            while cache.order.len() > CACHE_CAPACITY {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.entries.remove(&oldest);
+                }
+            }
+        }
+
+        Ok(Some(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::write::SimpleFileOptions;
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_docx_and_xlsx() {
+        let temp_dir = tempdir().unwrap();
+        let docx = temp_dir.path().join("report.docx");
+        write_zip(&docx, &[(
+            "word/document.xml",
+            r#"<w:document xmlns:w="w"><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:t xml:space="preserve"> report &amp; 数据</w:t></w:r></w:p><w:p><w:r><w:t>Second</w:t></w:r></w:p></w:body></w:document>"#,
+        )]);
+        let xlsx = temp_dir.path().join("book.xlsx");
+        write_zip(&xlsx, &[
+            ("xl/sharedStrings.xml", r#"<sst><si><t>Name</t></si><si><t>Alice</t></si></sst>"#),
+            ("xl/worksheets/sheet1.xml", r#"<worksheet><sheetData><row><c t="s"><v>0</v></c><c><v>42</v></c></row><row><c t="s"><v>1</v></c></row></sheetData></worksheet>"#),
+        ]);
+
+        let registry = ExtractorRegistry::with_defaults();
+        assert_eq!(registry.extract(&docx).unwrap().unwrap().as_str(), "Quarterly report & 数据\nSecond\n");
+        assert_eq!(registry.extract(&xlsx).unwrap().unwrap().as_str(), "Name\t42\nAlice\n");
+        assert!(registry.extract(&temp_dir.path().join("plain.txt")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_size_limit_and_cache() {
+        let temp_dir = tempdir().unwrap();
+        let odt = temp_dir.path().join("notes.odt");
+        write_zip(&odt, &[("content.xml", r#"<office:document-content><text:p>Meeting<text:s/>notes</text:p></office:document-content>"#)]);
+
+        let mut registry = ExtractorRegistry::with_defaults();
+        let first = registry.extract(&odt).unwrap().unwrap();
+        assert_eq!(first.as_str(), "Meeting notes\n");
+        assert!(Arc::ptr_eq(&first, &registry.extract(&odt).unwrap().unwrap()));
+
+        registry.set_size_limit("odt", 10);
+        assert!(matches!(registry.extract(&odt), Err(ExtractError::TooLarge { .. })));
+    }
+}
@@ -0,0 +1,240 @@
+//! Renaming, moving and trashing indexed files, keeping the in-memory index in step with the file system.
+
+use std::{
+    fs, io,
+    path::{is_separator, Path, PathBuf},
+    sync::Mutex,
+};
+use serde::Serialize;
+use thiserror::Error;
+use crate::{archive, desktop, indexer::FileMetadata};
+
+#[derive(Debug, Error)]
+pub enum FileOpError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0} is not in the index")]
+    NotIndexed(String),
+    #[error("{0} is only in a catalog (an imported file list or an unplugged volume) and can't be changed")]
+    ReadOnly(String),
+    #[error("{0} is inside an archive and can't be changed on its own")]
+    InArchive(String),
+    #[error("{0} already exists")]
+    Exists(String),
+    #[error("'{0}' is not a valid file name")]
+    InvalidName(String),
+}
+
+/// What happened to one file of a batch.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FileOpResult {
+    pub path: String,
+    /// Where the file is now, after a rename or move.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Fails unless `path` is an indexed file that exists on disk as itself: not a catalog entry or an archive entry.
+pub fn check_writable(files: &[FileMetadata], path: &str) -> Result<(), FileOpError> {
+    let file = files.iter().find(|file| file.path == path).ok_or_else(|| FileOpError::NotIndexed(path.to_string()))?;
+    check_entry_writable(file)
+}
+
+pub fn check_entry_writable(file: &FileMetadata) -> Result<(), FileOpError> {
+    if file.source.is_some() {
+        return Err(FileOpError::ReadOnly(file.path.clone()));
+    }
+    if archive::split_virtual_path(&file.path).is_some() {
+        return Err(FileOpError::InArchive(file.path.clone()));
+    }
+    Ok(())
+}
+
+pub(crate) fn ensure_vacant(source: &Path, target: &Path) -> Result<(), FileOpError> {
+    if target.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    // Changing only the case of a name on a case-insensitive file system finds the file itself.
+    match (fs::canonicalize(source), fs::canonicalize(target)) {
+        (Ok(source), Ok(target)) if source == target => Ok(()),
+        _ => Err(FileOpError::Exists(target.display().to_string())),
+    }
+}
+
+/// A single path component: no separators, and not `.` or `..`.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.chars().any(|c| is_separator(c) || c == '\0')
+}
+
+/// Renames `path` within its folder and returns the new path.
+pub fn rename(path: &Path, new_name: &str) -> Result<PathBuf, FileOpError> {
+    if !is_valid_name(new_name) {
+        return Err(FileOpError::InvalidName(new_name.to_string()));
+    }
+    let target = path.with_file_name(new_name);
+    if target == path {
+        return Ok(target);
+    }
+    ensure_vacant(path, &target)?;
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Moves `path` into `folder` and returns the new path. Across file systems the file is copied,
+/// keeping its modification time, and the original removed once the copy is complete.
+pub fn move_into(path: &Path, folder: &Path) -> Result<PathBuf, FileOpError> {
+    if !folder.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a folder", folder.display())).into());
+    }
+    let name = path.file_name().ok_or_else(|| FileOpError::InvalidName(path.display().to_string()))?;
+    let target = folder.join(name);
+    if target == path {
+        return Ok(target);
+    }
+    ensure_vacant(path, &target)?;
+    match fs::rename(path, &target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let copied = fs::copy(path, &target).and_then(|_| {
+                let modified = fs::metadata(path)?.modified()?;
+                fs::File::options().write(true).open(&target)?.set_modified(modified)
+            });
+            if let Err(e) = copied {
+                let _ = fs::remove_file(&target);
+                return Err(e.into());
+            }
+            fs::remove_file(path)?;
+        }
+        result => result?,
+    }
+    Ok(target)
+}
+
+/// Points the entry for `from`, and the entries inside it if it's an archive, at `to`.
+pub fn relocate(files: &mut [FileMetadata], from: &str, to: &str) {
+    let inner_prefix = archive::virtual_path(from, "");
+    let name = Path::new(to).file_name().map(|name| name.to_string_lossy().into_owned());
+    for file in files.iter_mut() {
+        if file.path == from {
+            file.path = to.to_string();
+            if let Some(name) = &name {
+                file.name = name.clone();
+            }
+        } else if let Some(entry) = file.path.strip_prefix(&inner_prefix) {
+            file.path = archive::virtual_path(to, entry);
+        }
+    }
+}
+
+/// Drops the entry for `path` and the entries inside it if it's an archive.
+pub fn remove(files: &mut Vec<FileMetadata>, path: &str) {
+    let inner_prefix = archive::virtual_path(path, "");
+    files.retain(|file| file.path != path && !file.path.starts_with(&inner_prefix));
+}
+
+/// Runs `op` on each of `paths` that may be changed and updates `files` as each one succeeds: `op` returns
+/// the file's new path, or `None` once it's gone. The index is locked only around those checks and updates,
+/// never while `op` touches the disk, and one file failing doesn't stop the others.
+pub fn apply_batch(
+    files: &Mutex<Vec<FileMetadata>>,
+    paths: &[String],
+    mut op: impl FnMut(&Path) -> Result<Option<PathBuf>, FileOpError>,
+) -> Vec<FileOpResult> {
+    let lock = || match files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+    paths
+        .iter()
+        .map(|path| {
+            let outcome = check_writable(&lock(), path).and_then(|()| op(Path::new(path)));
+            match outcome {
+                Ok(Some(new_path)) => {
+                    let new_path = new_path.to_string_lossy().into_owned();
+                    relocate(&mut lock(), path, &new_path);
+                    FileOpResult { path: path.clone(), new_path: Some(new_path), error: None }
+                }
+                Ok(None) => {
+                    remove(&mut lock(), path);
+                    FileOpResult { path: path.clone(), new_path: None, error: None }
+                }
+                Err(e) => FileOpResult { path: path.clone(), new_path: None, error: Some(e.to_string()) },
+            }
+        })
+        .collect()
+}
+
+/// Sends each of `paths` to the trash and drops it from the index.
+pub fn trash_all(files: &Mutex<Vec<FileMetadata>>, paths: &[String]) -> Vec<FileOpResult> {
+    apply_batch(files, paths, |path| desktop::trash(path).map(|()| None).map_err(FileOpError::from))
+}
+
+/// Moves each of `paths` into `folder`.
+pub fn move_all(files: &Mutex<Vec<FileMetadata>>, paths: &[String], folder: &Path) -> Vec<FileOpResult> {
+    apply_batch(files, paths, |path| move_into(path, folder).map(Some))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(path: &Path) -> FileMetadata {
+        let path = path.to_string_lossy().into_owned();
+        FileMetadata { name: path.rsplit('/').next().unwrap().to_string(), path, ..Default::default() }
+    }
+
+    #[test]
+    fn test_rename_and_relocate() {
+        let temp_dir = tempdir().unwrap();
+        let archive_path = temp_dir.path().join("bundle.zip");
+        fs::write(&archive_path, "zip").unwrap();
+        fs::write(temp_dir.path().join("taken.zip"), "other").unwrap();
+
+        assert!(matches!(rename(&archive_path, "taken.zip"), Err(FileOpError::Exists(_))));
+        assert!(matches!(rename(&archive_path, "../escape.zip"), Err(FileOpError::InvalidName(_))));
+        let renamed = rename(&archive_path, "renamed.zip").unwrap();
+        assert_eq!(renamed, temp_dir.path().join("renamed.zip"));
+        assert!(renamed.exists() && !archive_path.exists());
+
+        let archive_str = archive_path.to_string_lossy().into_owned();
+        let mut files = vec![
+            entry(&archive_path),
+            FileMetadata { path: archive::virtual_path(&archive_str, "docs/readme.md"), name: "readme.md".to_string(), ..Default::default() },
+            entry(&temp_dir.path().join("bundle.zip.bak")),
+        ];
+        relocate(&mut files, &archive_str, &renamed.to_string_lossy());
+        assert_eq!(files[0].name, "renamed.zip");
+        assert_eq!(files[1].path, archive::virtual_path(&renamed.to_string_lossy(), "docs/readme.md"));
+        assert!(files[2].path.ends_with("bundle.zip.bak"));
+
+        remove(&mut files, &renamed.to_string_lossy());
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn test_batch_reports_each_file() {
+        let temp_dir = tempdir().unwrap();
+        let (src, dest) = (temp_dir.path().join("src"), temp_dir.path().join("dest"));
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(src.join(name), name).unwrap();
+        }
+        fs::write(dest.join("b.txt"), "already there").unwrap();
+
+        let imported = FileMetadata { source: Some("/lists/old.efu".to_string()), ..entry(&src.join("c.txt")) };
+        let files = Mutex::new(vec![entry(&src.join("a.txt")), entry(&src.join("b.txt")), imported]);
+        let paths: Vec<String> = ["a.txt", "b.txt", "c.txt", "missing.txt"].iter().map(|name| src.join(name).to_string_lossy().into_owned()).collect();
+        let results = move_all(&files, &paths, &dest);
+
+        assert_eq!(results[0].new_path.as_deref(), Some(dest.join("a.txt").to_string_lossy().as_ref()));
+        assert!(results[0].error.is_none() && dest.join("a.txt").exists());
+        assert!(results[1].error.as_deref().unwrap().contains("already exists"));
+        assert!(results[2].error.as_deref().unwrap().contains("can't be changed"));
+        assert!(results[3].error.as_deref().unwrap().contains("not in the index"));
+        assert_eq!(fs::read_to_string(src.join("b.txt")).unwrap(), "b.txt");
+
+        let files = files.into_inner().unwrap();
+        assert_eq!(files[0].path, dest.join("a.txt").to_string_lossy());
+        assert_eq!(files[1].path, src.join("b.txt").to_string_lossy());
+    }
+}
@@ -0,0 +1,179 @@
+//! Hex dump rows, magic-number file type detection and byte pattern search.
+
+use memchr::memmem;
+use serde::Serialize;
+
+pub const BYTES_PER_ROW: usize = 16;
+/// Default page size when the caller doesn't ask for a length.
+pub const DEFAULT_HEX_PAGE: u64 = 4096;
+/// Upper bound for one page, so the webview never gets more than a few thousand rows at once.
+pub const MAX_HEX_PAGE: u64 = 64 * 1024;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FileType {
+    pub mime: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HexRow {
+    pub offset: u64,
+    /// Space-separated byte values, with a wider gap after the eighth byte.
+    pub hex: String,
+    /// Printable ASCII, with `.` for everything else.
+    pub ascii: String,
+}
+
+/// A pattern match in absolute file offsets; it may extend past the page it was reported with.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ByteMatch {
+    pub start: u64,
+    pub end: u64,
+}
+
+struct Magic {
+    offset: usize,
+    signature: &'static [u8],
+    /// A second signature some container formats need to be told apart (RIFF, ftyp...).
+    subtype: Option<(usize, &'static [u8])>,
+    mime: &'static str,
+    description: &'static str,
+}
+
+const fn magic(offset: usize, signature: &'static [u8], mime: &'static str, description: &'static str) -> Magic {
+    Magic { offset, signature, subtype: None, mime, description }
+}
+
+const MAGICS: &[Magic] = &[
+    magic(0, b"\x7fELF", "application/x-executable", "ELF executable"),
+    magic(0, b"MZ", "application/vnd.microsoft.portable-executable", "Windows executable (PE/DOS)"),
+    magic(0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary", "Mach-O executable (64-bit)"),
+    magic(0, b"\xca\xfe\xba\xbe", "application/java-vm", "Java class file or Mach-O fat binary"),
+    magic(0, b"\0asm", "application/wasm", "WebAssembly module"),
+    magic(0, b"\x89PNG\r\n\x1a\n", "image/png", "PNG image"),
+    magic(0, b"\xff\xd8\xff", "image/jpeg", "JPEG image"),
+    magic(0, b"GIF8", "image/gif", "GIF image"),
+    magic(0, b"BM", "image/bmp", "BMP image"),
+    magic(0, b"II*\0", "image/tiff", "TIFF image (little-endian)"),
+    magic(0, b"MM\0*", "image/tiff", "TIFF image (big-endian)"),
+    Magic { offset: 0, signature: b"RIFF", subtype: Some((8, b"WEBP")), mime: "image/webp", description: "WebP image" },
+    Magic { offset: 0, signature: b"RIFF", subtype: Some((8, b"WAVE")), mime: "audio/wav", description: "WAVE audio" },
+    Magic { offset: 0, signature: b"RIFF", subtype: Some((8, b"AVI ")), mime: "video/x-msvideo", description: "AVI video" },
+    magic(4, b"ftyp", "video/mp4", "MP4/QuickTime media"),
+    magic(0, b"ID3", "audio/mpeg", "MP3 audio"),
+    magic(0, b"fLaC", "audio/flac", "FLAC audio"),
+    magic(0, b"OggS", "audio/ogg", "Ogg media"),
+    magic(0, b"\x1a\x45\xdf\xa3", "video/x-matroska", "Matroska/WebM video"),
+    magic(0, b"%PDF-", "application/pdf", "PDF document"),
+    magic(0, b"PK\x03\x04", "application/zip", "ZIP archive (also docx/xlsx/jar...)"),
+    magic(0, b"\x1f\x8b", "application/gzip", "gzip compressed data"),
+    magic(0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed", "7-Zip archive"),
+    magic(0, b"Rar!\x1a\x07", "application/vnd.rar", "RAR archive"),
+    magic(0, b"BZh", "application/x-bzip2", "bzip2 compressed data"),
+    magic(0, b"\xfd7zXZ\0", "application/x-xz", "XZ compressed data"),
+    magic(0, b"\x28\xb5\x2f\xfd", "application/zstd", "Zstandard compressed data"),
+    magic(257, b"ustar", "application/x-tar", "tar archive"),
+    magic(0, b"SQLite format 3\0", "application/vnd.sqlite3", "SQLite database"),
+    magic(0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "application/x-ole-storage", "OLE compound file (doc/xls/msi...)"),
+    magic(0x8001, b"CD001", "application/x-iso9660-image", "ISO 9660 disk image"),
+];
+
+fn has_signature(bytes: &[u8], offset: usize, signature: &[u8]) -> bool {
+    bytes.get(offset..offset + signature.len()) == Some(signature)
+}
+
+/// Identifies a file from its leading bytes.
+pub fn detect_type(bytes: &[u8]) -> Option<FileType> {
+    MAGICS
+        .iter()
+        .find(|magic| {
+            has_signature(bytes, magic.offset, magic.signature)
+                && magic.subtype.is_none_or(|(offset, signature)| has_signature(bytes, offset, signature))
+        })
+        .map(|magic| FileType { mime: magic.mime, description: magic.description })
+}
+
+/// Reads queries like `7f 45 4c 46`, `7F454C46`, `0x7f,0x45` or `\x7f\x45` as a byte pattern.
+pub fn parse_hex_pattern(query: &str) -> Option<Vec<u8>> {
+    let digits: String = query
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .flat_map(|token| token.split("\\x"))
+        .map(|token| token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token))
+        .collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub fn format_rows(bytes: &[u8], base: u64) -> Vec<HexRow> {
+    bytes
+        .chunks(BYTES_PER_ROW)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut hex = String::with_capacity(BYTES_PER_ROW * 3 + 1);
+            for (i, byte) in chunk.iter().enumerate() {
+                if i > 0 {
+                    hex.push_str(if i == BYTES_PER_ROW / 2 { "  " } else { " " });
+                }
+                hex.push_str(&format!("{:02x}", byte));
+            }
+            let ascii = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            HexRow { offset: base + (index * BYTES_PER_ROW) as u64, hex, ascii }
+        })
+        .collect()
+}
+
+/// Matches of `pattern` that overlap `bytes[start..end]`, including ones straddling the page edges.
+pub fn find_matches(bytes: &[u8], start: usize, end: usize, pattern: &[u8]) -> Vec<ByteMatch> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    let window_start = start.saturating_sub(pattern.len() - 1);
+    let window_end = (end + pattern.len() - 1).min(bytes.len());
+    memmem::find_iter(&bytes[window_start..window_end], pattern)
+        .map(|found| window_start + found)
+        .filter(|&at| at + pattern.len() > start && at < end)
+        .map(|at| ByteMatch { start: at as u64, end: (at + pattern.len()) as u64 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_type_and_pattern() {
+        assert_eq!(detect_type(b"\x7fELF\x02\x01").unwrap().description, "ELF executable");
+        assert_eq!(detect_type(b"RIFF\0\0\0\0WEBPVP8 ").unwrap().mime, "image/webp");
+        assert_eq!(detect_type(b"RIFF\0\0\0\0????"), None);
+        assert_eq!(detect_type(b"plain"), None);
+
+        assert_eq!(parse_hex_pattern("7f 45 4C 46"), Some(vec![0x7f, 0x45, 0x4c, 0x46]));
+        assert_eq!(parse_hex_pattern("0x7f,0x45"), Some(vec![0x7f, 0x45]));
+        assert_eq!(parse_hex_pattern("\\x7f\\x45"), Some(vec![0x7f, 0x45]));
+        assert_eq!(parse_hex_pattern("7f4"), None);
+        assert_eq!(parse_hex_pattern("hello"), None);
+    }
+
+    #[test]
+    fn test_rows_and_matches() {
+        let bytes: Vec<u8> = (0u8..40).collect();
+        let rows = format_rows(&bytes[16..], 16);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].offset, 16);
+        assert_eq!(rows[0].hex, "10 11 12 13 14 15 16 17  18 19 1a 1b 1c 1d 1e 1f");
+        assert_eq!(rows[1].ascii, " !\"#$%&'");
+
+        // A match straddling the page start is still reported.
+        let matches = find_matches(&bytes, 16, 32, &[0x0f, 0x10]);
+        assert_eq!(matches, vec![ByteMatch { start: 15, end: 17 }]);
+        assert!(find_matches(&bytes, 16, 32, &[0x01, 0x02]).is_empty());
+    }
+}
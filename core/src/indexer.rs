@@ -0,0 +1,253 @@
+//! The file index: `FileMetadata` records, their on-disk format and summary statistics.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+/// Same folder name Tauri uses for the app data directory, so the GUI and the CLI share one index.
+pub const APP_IDENTIFIER: &str = "com.ultrasearch.app";
+/// Overrides the index location for both the GUI and the CLI.
+pub const INDEX_PATH_ENV: &str = "ULTRASEARCH_INDEX";
+const INDEX_MAGIC: &[u8; 4] = b"USIX";
+/// Bump whenever `FileMetadata` changes shape; older files are then ignored instead of misread.
+const INDEX_FORMAT_VERSION: u32 = 3;
+const TOP_EXTENSIONS: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct FileMetadata {
+    /// Filesystem path, or an `archive!/entry` virtual path for files inside archives.
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub modified_time: DateTime<Utc>,
+    /// Not every filesystem records it.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub created_time: Option<DateTime<Utc>>,
+    /// Windows `FILE_ATTRIBUTE_*` bits, when known.
+    #[serde(default)]
+    pub attributes: Option<u32>,
+    /// The imported file list this entry comes from; `None` for files found by scanning.
+    /// Such entries are read-only and survive rescans.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Id of the removable or external volume the file is on, if any.
+    #[serde(default)]
+    pub volume: Option<String>,
+}
+
+impl FileMetadata {
+    pub fn extension(&self) -> Option<&str> {
+        Path::new(&self.name).extension().and_then(|os| os.to_str())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not an UltraSearch index: {0}")]
+    Format(String),
+    #[error("Index format version {found} is not supported (expected {expected})")]
+    Version { found: u32, expected: u32 },
+}
+
+impl From<bincode::Error> for IndexError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) => IndexError::Io(e),
+            other => IndexError::Format(other.to_string()),
+        }
+    }
+}
+
+/// `$ULTRASEARCH_INDEX`, or `index.bin` in the per-user app data directory.
+pub fn default_index_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(INDEX_PATH_ENV).filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("index.bin"))
+}
+
+/// Writes the index next to its final location first, so readers never see a partial file.
+pub fn save_index(path: &Path, files: &[FileMetadata]) -> Result<(), IndexError> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    {
+        let mut writer = BufWriter::new(fs::File::create(&partial)?);
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&INDEX_FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, files)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Where a JSON side file kept with the index lives: `index.<name>.json` beside `index.bin`.
+pub fn side_file_path(index_path: &Path, name: &str) -> PathBuf {
+    index_path.with_extension(format!("{}.json", name))
+}
+
+/// Reads a side file such as the volume catalog or the rename journal; the default value when there's none yet.
+pub fn load_side_file<T: DeserializeOwned + Default>(path: &Path) -> io::Result<T> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes a side file the same way as the index, so a crash never leaves half of one behind.
+pub fn save_side_file<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    {
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(&serde_json::to_vec_pretty(value).map_err(io::Error::other)?)?;
+        file.sync_all()?;
+    }
+    fs::rename(partial, path)
+}
+
+pub fn load_index(path: &Path) -> Result<Vec<FileMetadata>, IndexError> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).map_err(|_| IndexError::Format(path.display().to_string()))?;
+    if &header[..4] != INDEX_MAGIC {
+        return Err(IndexError::Format(path.display().to_string()));
+    }
+    let found = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if found != INDEX_FORMAT_VERSION {
+        return Err(IndexError::Version { found, expected: INDEX_FORMAT_VERSION });
+    }
+    Ok(bincode::deserialize_from(reader)?)
+}
+
+/// Swaps whatever `files` held under `roots` (archive entries included) for a fresh scan of them.
+/// Imported entries are left alone even when their paths fall under a root.
+pub fn replace_roots(files: &mut Vec<FileMetadata>, roots: &[PathBuf], scanned: Vec<FileMetadata>) {
+    files.retain(|file| file.source.is_some() || !roots.iter().any(|root| Path::new(&file.path).starts_with(root)));
+    files.extend(scanned);
+}
+
+/// Swaps the entries imported from `source` for `imported`; an empty list removes the source.
+pub fn replace_source(files: &mut Vec<FileMetadata>, source: &str, imported: Vec<FileMetadata>) {
+    files.retain(|file| file.source.as_deref() != Some(source));
+    files.extend(imported.into_iter().map(|file| FileMetadata { source: Some(source.to_string()), ..file }));
+}
+
+/// Imported sources and how many entries each contributes, by name.
+pub fn sources(files: &[FileMetadata]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for source in files.iter().filter_map(|file| file.source.as_deref()) {
+        *counts.entry(source).or_default() += 1;
+    }
+    let mut sources: Vec<(String, usize)> = counts.into_iter().map(|(source, count)| (source.to_string(), count)).collect();
+    sources.sort();
+    sources
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct IndexStats {
+    pub file_count: usize,
+    /// Files indexed from inside archives, included in `file_count`.
+    pub archive_entries: usize,
+    /// Entries from imported file lists, included in `file_count`.
+    pub imported_entries: usize,
+    pub total_size: u64,
+    pub newest: Option<DateTime<Utc>>,
+    /// Most common extensions (lowercased) with their file counts.
+    pub top_extensions: Vec<(String, usize)>,
+}
+
+pub fn index_stats(files: &[FileMetadata]) -> IndexStats {
+    let mut by_extension: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        if let Some(extension) = file.extension() {
+            *by_extension.entry(extension.to_lowercase()).or_default() += 1;
+        }
+    }
+    let mut top_extensions: Vec<(String, usize)> = by_extension.into_iter().collect();
+    top_extensions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_extensions.truncate(TOP_EXTENSIONS);
+
+    IndexStats {
+        file_count: files.len(),
+        archive_entries: files.iter().filter(|file| crate::archive::split_virtual_path(&file.path).is_some()).count(),
+        imported_entries: files.iter().filter(|file| file.source.is_some()).count(),
+        total_size: files.iter().map(|file| file.size).sum(),
+        newest: files.iter().map(|file| file.modified_time).max(),
+        top_extensions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn file(path: &str, size: u64) -> FileMetadata {
+        FileMetadata {
+            path: path.to_string(),
+            name: Path::new(path).file_name().unwrap().to_string_lossy().to_string(),
+            size,
+            modified_time: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_index() {
+        let temp_dir = tempdir().unwrap();
+        let index_path = temp_dir.path().join("nested").join("index.bin");
+        let created = FileMetadata { created_time: Some(Utc.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap()), attributes: Some(0x20), ..file("/data/notes.txt", 13) };
+        let files = vec![file("/data/report.PDF", 2048), created];
+
+        save_index(&index_path, &files).unwrap();
+        assert_eq!(load_index(&index_path).unwrap(), files);
+        assert!(!index_path.with_extension("partial").exists());
+
+        fs::write(&index_path, b"USIX\x09\0\0\0").unwrap();
+        assert!(matches!(load_index(&index_path), Err(IndexError::Version { found: 9, .. })));
+        fs::write(&index_path, b"not an index").unwrap();
+        assert!(matches!(load_index(&index_path), Err(IndexError::Format(_))));
+        assert!(matches!(load_index(&temp_dir.path().join("missing.bin")), Err(IndexError::Io(_))));
+    }
+
+    #[test]
+    fn test_index_stats() {
+        let files = vec![
+            file("/data/a.txt", 10),
+            file("/data/b.TXT", 20),
+            file("/data/c.rs", 30),
+            file("/data/bundle.zip!/inner/d.rs", 40),
+            file("/data/Makefile", 5),
+        ];
+        let stats = index_stats(&files);
+        assert_eq!(stats.file_count, 5);
+        assert_eq!(stats.archive_entries, 1);
+        assert_eq!(stats.total_size, 105);
+        assert_eq!(stats.top_extensions, vec![("rs".to_string(), 2), ("txt".to_string(), 2)]);
+
+        let mut files = files;
+        files.push(file("/other/keep.md", 1));
+        replace_source(&mut files, "drive.efu", vec![file("/data/offline.doc", 7)]);
+        replace_roots(&mut files, &[PathBuf::from("/data")], vec![file("/data/new.rs", 1)]);
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["/other/keep.md", "/data/offline.doc", "/data/new.rs"]);
+        assert_eq!(sources(&files), vec![("drive.efu".to_string(), 1)]);
+        assert_eq!(index_stats(&files).imported_entries, 1);
+
+        replace_source(&mut files, "drive.efu", Vec::new());
+        assert!(sources(&files).is_empty());
+    }
+}
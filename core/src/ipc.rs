@@ -0,0 +1,382 @@
+//! Unix socket IPC for launchers and other local tools: one JSON request per line, one JSON response per line.
+//!
+//! A request is `{"v": 1, "id": 7, "method": "search", "params": {"query": "report", "limit": 20}}`.
+//! The response echoes `v` and `id` and carries either `"ok": true, "result": ...` or
+//! `"ok": false, "error": {"code": ..., "message": ...}`. Methods:
+//!
+//! - `hello`: server name, version and the protocol versions it speaks
+//! - `search`: `query`, `ext`, `min_size`, `max_size`, `sort`, `descending`, `offset`, `limit`;
+//!   returns `{total, offset, limit, results: [{path, name, size, modified_time}]}`
+//! - `open` / `reveal`: `{"path": ...}` for an indexed file
+
+use std::{
+    env,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::indexer::FileMetadata;
+use crate::query::{AdvancedFilterOptions, FileQuery, SortKey};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const SOCKET_PATH_ENV: &str = "ULTRASEARCH_SOCKET";
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 1000;
+/// Longer lines are answered with an error and the connection is closed.
+const MAX_REQUEST_LINE: u64 = 64 * 1024;
+
+/// `$ULTRASEARCH_SOCKET`, otherwise `ultrasearch.sock` in the user's runtime directory
+/// (falling back to a per-user name in the temp directory).
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = env::var_os(SOCKET_PATH_ENV).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("ultrasearch.sock"),
+        None => env::temp_dir().join(format!("ultrasearch-{}.sock", env::var("USER").unwrap_or_default())),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenAction {
+    Open,
+    Reveal,
+}
+
+pub type OpenHandler = dyn Fn(&Path, OpenAction) -> Result<(), String> + Send + Sync;
+
+/// What the endpoint answers from; `on_open` is only ever called with indexed paths.
+pub struct IpcContext {
+    pub files: Arc<Mutex<Vec<FileMetadata>>>,
+    pub on_open: Box<OpenHandler>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SearchParams {
+    pub query: String,
+    pub ext: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub sort: Option<SortKey>,
+    pub descending: bool,
+    pub offset: usize,
+    /// Defaults to 50, capped at 1000.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchReply {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub results: Vec<FileMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PathParams {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    v: u32,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+fn reply(id: &Value, result: Result<Value, (&str, String)>) -> String {
+    let body = match result {
+        Ok(result) => json!({ "v": PROTOCOL_VERSION, "id": id, "ok": true, "result": result }),
+        Err((code, message)) => json!({ "v": PROTOCOL_VERSION, "id": id, "ok": false, "error": { "code": code, "message": message } }),
+    };
+    body.to_string()
+}
+
+fn params<T: for<'de> Deserialize<'de>>(value: Value) -> Result<T, (&'static str, String)> {
+    let value = if value.is_null() { json!({}) } else { value };
+    serde_json::from_value(value).map_err(|e| ("bad_request", e.to_string()))
+}
+
+/// Turns one request line into one reply line. Malformed input still gets a reply, with a null id
+/// when the request couldn't be parsed far enough to find one, so a client never waits on a dropped line.
+pub fn handle_line(line: &str, ctx: &IpcContext) -> String {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return reply(&Value::Null, Err(("bad_request", e.to_string()))),
+    };
+    if request.v != PROTOCOL_VERSION {
+        let message = format!("protocol version {} is not supported (this server speaks {})", request.v, PROTOCOL_VERSION);
+        return reply(&request.id, Err(("unsupported_version", message)));
+    }
+
+    let result = match request.method.as_str() {
+        "hello" => Ok(json!({ "server": "ultrasearch", "version": env!("CARGO_PKG_VERSION"), "protocols": [PROTOCOL_VERSION] })),
+        "search" => params(request.params).map(|params| search(&params, ctx)),
+        "open" | "reveal" => params::<PathParams>(request.params).and_then(|params| {
+            let action = if request.method == "open" { OpenAction::Open } else { OpenAction::Reveal };
+            open(&params.path, action, ctx)
+        }),
+        other => Err(("unknown_method", format!("unknown method '{}'", other))),
+    };
+    reply(&request.id, result)
+}
+
+fn search(params: &SearchParams, ctx: &IpcContext) -> Value {
+    let limit = params.limit.map_or(DEFAULT_LIMIT, |limit| limit.min(MAX_LIMIT));
+    let filters = AdvancedFilterOptions { file_type: params.ext.clone(), min_size: params.min_size, max_size: params.max_size };
+    let mut query = FileQuery::new(&params.query).with_filters(filters);
+    if let Some(key) = params.sort {
+        query = query.with_sort(key, params.descending);
+    }
+    let files = match ctx.files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+    let page = query.page(&files, params.offset, limit);
+    let reply = SearchReply { total: page.total, offset: params.offset, limit, results: page.results.into_iter().cloned().collect() };
+    serde_json::to_value(reply).unwrap_or_default()
+}
+
+fn open(path: &Path, action: OpenAction, ctx: &IpcContext) -> Result<Value, (&'static str, String)> {
+    // Only indexed files: other processes must not be able to launch arbitrary paths through us.
+    let indexed = {
+        let files = match ctx.files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+        files.iter().any(|file| Path::new(&file.path) == path)
+    };
+    if !indexed {
+        return Err(("forbidden", format!("{} is not indexed", path.display())));
+    }
+    (ctx.on_open)(path, action).map(|()| json!({})).map_err(|e| ("failed", e))
+}
+
+fn serve_connection(stream: UnixStream, ctx: &IpcContext) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_REQUEST_LINE).read_line(&mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if !line.ends_with('\n') && read as u64 == MAX_REQUEST_LINE {
+            writeln!(writer, "{}", reply(&Value::Null, Err(("bad_request", "request line too long".to_string()))))?;
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", handle_line(&line, ctx))?;
+    }
+}
+
+/// A listening socket; stopped, and the socket file removed, on drop.
+#[derive(Debug)]
+pub struct IpcServer {
+    path: PathBuf,
+    stopping: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl IpcServer {
+    /// Fails with `AddrInUse` when another instance is already answering on `path`;
+    /// a socket file left behind by one that crashed is replaced.
+    pub fn start(path: &Path, ctx: Arc<IpcContext>) -> io::Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use by another instance", path.display())));
+            }
+            fs::remove_file(path)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let stopping = stopping.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let ctx = ctx.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = serve_connection(stream, &ctx) {
+                                    debug!("IPC connection closed: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Failed to accept IPC connection: {}", e),
+                    }
+                }
+            })
+        };
+        debug!("IPC listening on {}", path.display());
+        Ok(IpcServer { path: path.to_path_buf(), stopping, acceptor: Some(acceptor) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // Wakes the acceptor blocked in `accept`.
+        let _ = UnixStream::connect(&self.path);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        let _ = fs::remove_file(&self.path);
+        debug!("IPC on {} stopped", self.path.display());
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum IpcError {
+    #[error("IPC I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Malformed IPC message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{message} ({code})")]
+    Remote { code: String, message: String },
+    #[error("Connection closed by the server")]
+    Closed,
+}
+
+/// Reference client for the protocol, also usable from other Rust tools.
+#[derive(Debug)]
+pub struct IpcClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl IpcClient {
+    pub fn connect(path: &Path) -> Result<Self, IpcError> {
+        let stream = UnixStream::connect(path)?;
+        Ok(IpcClient { writer: stream.try_clone()?, reader: BufReader::new(stream), next_id: 1 })
+    }
+
+    /// Sends one request and waits for its response's `result`.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, IpcError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        writeln!(self.writer, "{}", json!({ "v": PROTOCOL_VERSION, "id": id, "method": method, "params": params }))?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(IpcError::Closed);
+        }
+        let mut response: Value = serde_json::from_str(&line)?;
+        if response["ok"].as_bool() == Some(true) {
+            return Ok(response["result"].take());
+        }
+        let error = &response["error"];
+        Err(IpcError::Remote {
+            code: error["code"].as_str().unwrap_or("unknown").to_string(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    pub fn search(&mut self, params: &SearchParams) -> Result<SearchReply, IpcError> {
+        let result = self.call("search", serde_json::to_value(params)?)?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    pub fn open(&mut self, path: &Path, action: OpenAction) -> Result<(), IpcError> {
+        let method = match action {
+            OpenAction::Open => "open",
+            OpenAction::Reveal => "reveal",
+        };
+        self.call(method, json!({ "path": path })).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    type Opened = Arc<Mutex<Vec<(PathBuf, OpenAction)>>>;
+
+    /// A small shared drive with three quarterly reports among other files; `on_open` records
+    /// what it was asked to do instead of launching anything.
+    fn context() -> (IpcContext, Opened) {
+        let files = [("/srv/share/reports/q1.pdf", 300), ("/srv/share/reports/q2.pdf", 500), ("/srv/share/reports/q3.pdf", 400), ("/srv/share/photos/team.jpg", 2000)]
+            .into_iter()
+            .map(|(path, size)| FileMetadata {
+                path: path.to_string(),
+                name: Path::new(path).file_name().unwrap().to_string_lossy().into_owned(),
+                size,
+                modified_time: Utc::now(),
+                ..Default::default()
+            })
+            .collect();
+        let opened: Opened = Arc::new(Mutex::new(Vec::new()));
+        let recorder = opened.clone();
+        let ctx = IpcContext {
+            files: Arc::new(Mutex::new(files)),
+            on_open: Box::new(move |path, action| {
+                recorder.lock().unwrap().push((path.to_path_buf(), action));
+                Ok(())
+            }),
+        };
+        (ctx, opened)
+    }
+
+    #[test]
+    fn test_handle_line() {
+        let (ctx, _) = context();
+        let parse = |line: &str| serde_json::from_str::<Value>(&handle_line(line, &ctx)).unwrap();
+
+        let response = parse(r#"{"v":1,"id":"a","method":"search","params":{"query":"REPORTS","sort":"size","descending":true,"limit":2}}"#);
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"]["total"], 3);
+        assert_eq!(response["result"]["results"][0]["name"], "q2.pdf");
+
+        assert_eq!(parse(r#"{"v":2,"id":1,"method":"hello"}"#)["error"]["code"], "unsupported_version");
+        assert_eq!(parse(r#"{"v":1,"id":1,"method":"delete"}"#)["error"]["code"], "unknown_method");
+        assert_eq!(parse(r#"{"v":1,"id":1,"method":"search","params":{"limit":-1}}"#)["error"]["code"], "bad_request");
+        assert_eq!(parse("not json")["error"]["code"], "bad_request");
+    }
+
+    #[test]
+    fn test_client_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket = temp_dir.path().join("ultrasearch.sock");
+        let (ctx, opened) = context();
+        let server = IpcServer::start(&socket, Arc::new(ctx)).unwrap();
+        assert!(matches!(IpcServer::start(&socket, Arc::new(context().0)), Err(e) if e.kind() == io::ErrorKind::AddrInUse));
+
+        let mut client = IpcClient::connect(&socket).unwrap();
+        assert_eq!(client.call("hello", Value::Null).unwrap()["protocols"][0], PROTOCOL_VERSION);
+        let reply = client.search(&SearchParams { query: "share".to_string(), offset: 3, ..Default::default() }).unwrap();
+        assert_eq!((reply.total, reply.results.len()), (4, 1));
+
+        client.open(Path::new("/srv/share/photos/team.jpg"), OpenAction::Reveal).unwrap();
+        assert!(matches!(client.open(Path::new("/etc/passwd"), OpenAction::Open), Err(IpcError::Remote { code, .. }) if code == "forbidden"));
+        assert_eq!(*opened.lock().unwrap(), vec![(PathBuf::from("/srv/share/photos/team.jpg"), OpenAction::Reveal)]);
+
+        drop(server);
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,56 @@
+//! Indexing, search and preview engine behind UltraSearch, usable without the desktop app.
+//!
+//! The usual flow is: walk folders with [`scanner::scan`] into a list of [`indexer::FileMetadata`],
+//! persist it with [`indexer::save_index`], then answer name queries with [`query::FileQuery`] and
+//! content queries with [`searcher::ContentSearcher`] or a [`content_index::ContentIndex`].
+//! [`preview::FilePreview`] renders any result for display.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use ultrasearch_core::{
+//!     indexer,
+//!     query::{FileQuery, SortKey},
+//!     scanner::{self, ScanEvent, ScanOptions},
+//! };
+//!
+//! let mut files = Vec::new();
+//! scanner::scan(Path::new("/home/me/projects"), &ScanOptions::default(), |event| {
+//!     if let ScanEvent::File(file) = event {
+//!         files.push(file);
+//!     }
+//!     true
+//! });
+//! if let Some(index_path) = indexer::default_index_path() {
+//!     indexer::save_index(&index_path, &files)?;
+//! }
+//!
+//! for file in FileQuery::new("readme").with_sort(SortKey::Size, true).search(&files, 0, 20) {
+//!     println!("{} ({} bytes)", file.path, file.size);
+//! }
+//! # Ok::<(), ultrasearch_core::indexer::IndexError>(())
+//! ```
+
+pub mod archive;
+pub mod content_index;
+pub mod desktop;
+pub mod efu;
+pub mod encoding;
+pub mod everything;
+pub mod export;
+pub mod extractor;
+pub mod fileops;
+pub mod hexdump;
+pub mod indexer;
+#[cfg(unix)]
+pub mod ipc;
+pub mod line_index;
+pub mod preview;
+pub mod protocol;
+pub mod query;
+pub mod rename;
+pub mod scanner;
+pub mod searcher;
+pub mod server;
+pub mod syntax;
+pub mod thumbnail;
+pub mod volumes;
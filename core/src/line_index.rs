@@ -0,0 +1,135 @@
+//! Sparse line offset index for paging through large text files.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+
+/// Only every Nth line start is stored, so the index of a multi-gigabyte log stays small.
+const CHECKPOINT_STRIDE: usize = 1024;
+
+/// Sparse index of line start offsets, extended lazily as later lines are asked for.
+#[derive(Debug)]
+pub struct LineIndex {
+    encoding: &'static Encoding,
+    /// Where line 1 starts, i.e. the BOM length.
+    origin: usize,
+    /// `checkpoints[k]` is the byte offset of (zero-based) line `k * CHECKPOINT_STRIDE`.
+    checkpoints: Vec<u64>,
+    lines_seen: usize,
+    scan_pos: usize,
+    total_lines: Option<usize>,
+}
+
+impl LineIndex {
+    pub fn new(encoding: &'static Encoding, origin: usize, len: usize) -> Self {
+        LineIndex {
+            encoding,
+            origin,
+            checkpoints: vec![origin as u64],
+            lines_seen: 1,
+            scan_pos: origin,
+            total_lines: (origin >= len).then_some(0),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    /// Known once a scan has reached the end of the file.
+    pub fn total_lines(&self) -> Option<usize> {
+        self.total_lines
+    }
+
+    /// Offset just past the next newline at or after `from`. UTF-16 newlines only count on code unit boundaries.
+    fn next_line_start(&self, bytes: &[u8], from: usize) -> Option<usize> {
+        let mut pos = from;
+        while let Some(found) = memchr::memchr(b'\n', &bytes[pos..]) {
+            let at = pos + found;
+            let aligned = (at - self.origin).is_multiple_of(2);
+            if self.encoding == UTF_16LE {
+                if aligned && bytes.get(at + 1) == Some(&0) {
+                    return Some(at + 2);
+                }
+            } else if self.encoding == UTF_16BE {
+                if !aligned && bytes[at - 1] == 0 {
+                    return Some(at + 1);
+                }
+            } else {
+                return Some(at + 1);
+            }
+            pos = at + 1;
+        }
+        None
+    }
+
+    fn extend_to(&mut self, bytes: &[u8], line: usize) {
+        while self.total_lines.is_none() && self.checkpoints.len() <= line / CHECKPOINT_STRIDE {
+            match self.next_line_start(bytes, self.scan_pos) {
+                Some(next) if next < bytes.len() => {
+                    if self.lines_seen.is_multiple_of(CHECKPOINT_STRIDE) {
+                        self.checkpoints.push(next as u64);
+                    }
+                    self.lines_seen += 1;
+                    self.scan_pos = next;
+                }
+                _ => self.total_lines = Some(self.lines_seen),
+            }
+        }
+    }
+
+    /// Byte offset where the zero-based `line` starts, or `None` past the end of the file.
+    pub fn line_start(&mut self, bytes: &[u8], line: usize) -> Option<usize> {
+        if self.total_lines.is_some_and(|total| line >= total) {
+            return None;
+        }
+        self.extend_to(bytes, line);
+        let mut offset = *self.checkpoints.get(line / CHECKPOINT_STRIDE)? as usize;
+        let base = line - line % CHECKPOINT_STRIDE;
+        for walked in 1..=line % CHECKPOINT_STRIDE {
+            match self.next_line_start(bytes, offset) {
+                Some(next) if next < bytes.len() => offset = next,
+                _ => {
+                    self.total_lines = Some(base + walked);
+                    return None;
+                }
+            }
+        }
+        Some(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::UTF_8;
+
+    #[test]
+    fn test_line_starts_across_checkpoints() {
+        let text: String = (0..3000).map(|n| format!("line {}\n", n)).collect();
+        let bytes = text.as_bytes();
+        let mut index = LineIndex::new(UTF_8, 0, bytes.len());
+
+        let start = index.line_start(bytes, 2500).unwrap();
+        assert!(bytes[start..].starts_with(b"line 2500\n"));
+        assert_eq!(index.total_lines(), None);
+        assert_eq!(index.line_start(bytes, 0), Some(0));
+        assert_eq!(index.line_start(bytes, 3000), None);
+        assert_eq!(index.total_lines(), Some(3000));
+    }
+
+    #[test]
+    fn test_utf16_line_starts() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("甲\n\u{0A00}\nlast".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        let mut index = LineIndex::new(UTF_16LE, 2, bytes.len());
+
+        assert_eq!(index.line_start(&bytes, 0), Some(2));
+        assert_eq!(index.line_start(&bytes, 1), Some(6));
+        assert_eq!(index.line_start(&bytes, 2), Some(10));
+        assert_eq!(index.line_start(&bytes, 3), None);
+        assert_eq!(LineIndex::new(UTF_8, 0, 0).line_start(b"", 0), None);
+    }
+}
@@ -0,0 +1,1063 @@
+//! File previews: text, images, archives, directories, ranged reads, source highlighting and hex pages.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+use anyhow::Result;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use encoding_rs::{Encoding, BIG5, EUC_JP, EUC_KR, GB18030, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8};
+use memmap2::Mmap;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize, Serializer};
+use crate::archive::{ArchiveEntry, ArchiveError};
+use crate::extractor::{ExtractError, ExtractorRegistry};
+use crate::hexdump::{ByteMatch, FileType, HexRow, BYTES_PER_ROW, DEFAULT_HEX_PAGE, MAX_HEX_PAGE};
+use crate::line_index::LineIndex;
+use crate::searcher::MatchRange;
+use crate::syntax::{HighlightedSource, LineState, ParseCheckpoints, SyntaxHighlighter, CHECKPOINT_LINES};
+
+/// Archive entries are decompressed into memory, so previews of them are capped.
+const MAX_ARCHIVE_ENTRY_PREVIEW: u64 = 16 * 1024 * 1024;
+/// Text beyond this many bytes is cut off and the preview marked as truncated.
+const MAX_TEXT_PREVIEW: usize = 1024 * 1024;
+/// Images larger than this are reported without inline data.
+const MAX_IMAGE_PREVIEW: u64 = 10 * 1024 * 1024;
+/// Archive listings stop after this many entries.
+const MAX_ARCHIVE_LISTING: usize = 10_000;
+/// Directory listings show at most this many children.
+const MAX_DIRECTORY_CHILDREN: usize = 1_000;
+const LARGEST_CHILDREN: usize = 10;
+/// Line indexes are kept for this many recently previewed files.
+const LINE_INDEX_CACHE_CAPACITY: usize = 32;
+/// How much source one request parses ahead of its page to find the state the page starts in;
+/// the next request carries on from there.
+const MAX_CHECKPOINT_PARSE: usize = 8 * 1024 * 1024;
+/// How much of a file is sampled to pick its encoding for ranged reads.
+const RANGE_SNIFF_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewKind {
+    Text,
+    Image,
+    Binary,
+    Archive,
+    Directory,
+    Unsupported,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PreviewPayload {
+    pub kind: PreviewKind,
+    /// Decoded text, for `Text` previews.
+    pub content: Option<String>,
+    /// Base64 file data, for `Image` previews.
+    pub data: Option<String>,
+    pub mime: Option<String>,
+    pub encoding: Option<String>,
+    pub size: u64,
+    pub truncated: bool,
+    /// Some bytes were invalid in `encoding`, so another encoding may read better.
+    pub lossy: bool,
+    pub archive: Option<ArchiveListing>,
+    pub directory: Option<DirectoryListing>,
+}
+
+impl PreviewPayload {
+    fn empty(kind: PreviewKind, size: u64) -> Self {
+        PreviewPayload {
+            kind,
+            content: None,
+            data: None,
+            mime: None,
+            encoding: None,
+            size,
+            truncated: false,
+            lossy: false,
+            archive: None,
+            directory: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchiveListing {
+    pub entries: Vec<ArchiveEntry>,
+    pub entry_count: usize,
+    pub total_size: u64,
+    /// Only known when every file entry reports its compressed size.
+    pub total_compressed_size: Option<u64>,
+    pub truncated: bool,
+}
+
+impl ArchiveListing {
+    fn new(mut entries: Vec<ArchiveEntry>) -> Self {
+        let files = || entries.iter().filter(|entry| !entry.is_dir);
+        let total_size = files().map(|entry| entry.size).sum();
+        let total_compressed_size = files().map(|entry| entry.compressed_size).sum();
+        let entry_count = entries.len();
+        entries.truncate(MAX_ARCHIVE_LISTING);
+        ArchiveListing { entries, entry_count, total_size, total_compressed_size, truncated: entry_count > MAX_ARCHIVE_LISTING }
+    }
+}
+
+/// A file as recorded in the in-memory index.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexedFile<'a> {
+    pub path: &'a str,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DirectoryChild {
+    pub name: String,
+    pub is_dir: bool,
+    /// For directories, the total size and number of indexed files beneath them.
+    pub size: u64,
+    pub file_count: usize,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DirectoryListing {
+    pub file_count: usize,
+    pub dir_count: usize,
+    pub total_size: u64,
+    /// Directories first, then files, each by name.
+    pub children: Vec<DirectoryChild>,
+    pub largest: Vec<DirectoryChild>,
+    pub truncated: bool,
+}
+
+/// A window into a file, so large files can be paged through instead of sent whole.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum PreviewRange {
+    Bytes { offset: u64, length: u64 },
+    /// `start_line` is 1-based, like search hit line numbers.
+    Lines { start_line: usize, line_count: usize },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RangePreview {
+    pub content: String,
+    pub encoding: String,
+    pub size: u64,
+    /// Byte span of `content` in the file, after snapping to character boundaries.
+    pub offset: u64,
+    pub length: u64,
+    /// Only known for line ranges.
+    pub start_line: Option<usize>,
+    /// Known once the line index has reached the end of the file.
+    pub total_lines: Option<usize>,
+    pub has_more: bool,
+    /// The requested range was over the per-request limit and got cut short.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SourcePreview {
+    #[serde(flatten)]
+    pub range: RangePreview,
+    #[serde(flatten)]
+    pub source: HighlightedSource,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HexPreview {
+    pub file_type: Option<FileType>,
+    pub size: u64,
+    /// Start of the page, aligned down to a whole row.
+    pub offset: u64,
+    pub length: u64,
+    pub rows: Vec<HexRow>,
+    /// Matches of the hex pattern that touch this page.
+    pub matches: Vec<ByteMatch>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct HighlightOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub use_regex: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Highlight {
+    /// The content HTML-escaped, with each match wrapped in `<mark>`.
+    pub html: String,
+    pub ranges: Vec<MatchRange>,
+}
+
+#[derive(Debug)]
+struct CachedLineIndex {
+    modified: SystemTime,
+    size: u64,
+    last_used: SystemTime,
+    index: LineIndex,
+    /// Syntax highlighter states along the file, once a source preview has paged into it.
+    checkpoints: Option<ParseCheckpoints>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PreviewError {
+    #[error("file not found: {0}")]
+    NotFound(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("{0} is a directory")]
+    IsDirectory(String),
+    #[error("{0} is not a text file")]
+    NotText(String),
+    #[error("{0} is not a supported image")]
+    NotImage(String),
+    #[error("file is {size} bytes, over the {limit} byte limit")]
+    TooLarge { size: u64, limit: u64 },
+    #[error("{0}")]
+    UnknownEncoding(String),
+    #[error("failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Extract(#[from] ExtractError),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error("failed to decode image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+impl PreviewError {
+    pub(crate) fn from_io(e: std::io::Error, path: &Path) -> Self {
+        match e.kind() {
+            ErrorKind::NotFound => PreviewError::NotFound(path.display().to_string()),
+            ErrorKind::PermissionDenied => PreviewError::PermissionDenied(path.display().to_string()),
+            _ => PreviewError::Io(e),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            PreviewError::NotFound(_) => "not_found",
+            PreviewError::PermissionDenied(_) => "permission_denied",
+            PreviewError::IsDirectory(_) => "is_directory",
+            PreviewError::NotText(_) => "not_text",
+            PreviewError::NotImage(_) => "not_image",
+            PreviewError::TooLarge { .. } => "too_large",
+            PreviewError::UnknownEncoding(_) => "unknown_encoding",
+            PreviewError::Io(_) => "io",
+            PreviewError::Extract(_) => "extract",
+            PreviewError::Archive(_) => "archive",
+            PreviewError::Image(_) => "image",
+        }
+    }
+}
+
+/// Sent to the frontend as `{ "code": "...", "message": "..." }`.
+impl Serialize for PreviewError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("PreviewError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+fn truncate_text(text: &str) -> (&str, bool) {
+    if text.len() <= MAX_TEXT_PREVIEW {
+        return (text, false);
+    }
+    let mut end = MAX_TEXT_PREVIEW;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&text[..end], true)
+}
+
+pub(crate) fn image_mime(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    })
+}
+
+/// Media and disk images that no preview mode can show; these are never read.
+fn is_unsupported(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|os| os.to_str()) else { return false };
+    const UNSUPPORTED: &[&str] = &[
+        "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "mp3", "flac", "wav", "aac", "ogg", "m4a",
+        "iso", "img", "vmdk", "vdi", "vhd", "vhdx", "qcow2",
+    ];
+    UNSUPPORTED.iter().any(|unsupported| extension.eq_ignore_ascii_case(unsupported))
+}
+
+/// Length of the character `bytes` starts with, in one of the East Asian multi-byte encodings; `None` for others.
+fn multi_byte_char_len(encoding: &'static Encoding, bytes: &[u8]) -> Option<usize> {
+    let lead = bytes[0];
+    let len = if encoding == SHIFT_JIS {
+        if matches!(lead, 0x81..=0x9F | 0xE0..=0xFC) { 2 } else { 1 }
+    } else if encoding == EUC_JP {
+        match lead {
+            0x8F => 3,
+            0x8E | 0xA1..=0xFE => 2,
+            _ => 1,
+        }
+    } else if encoding == GBK || encoding == GB18030 {
+        match (lead, bytes.get(1)) {
+            (0x81..=0xFE, Some(0x30..=0x39)) => 4,
+            (0x81..=0xFE, _) => 2,
+            _ => 1,
+        }
+    } else if encoding == BIG5 || encoding == EUC_KR {
+        if matches!(lead, 0x81..=0xFE) { 2 } else { 1 }
+    } else {
+        return None;
+    };
+    Some(len)
+}
+
+/// Moves `pos` off the middle of a character so a slice starting or ending there decodes cleanly.
+fn snap_to_char(bytes: &[u8], mut pos: usize, origin: usize, encoding: &'static Encoding, forward: bool) -> usize {
+    if pos >= bytes.len() {
+        return bytes.len();
+    }
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        if (pos - origin) % 2 == 1 {
+            pos = if forward { pos + 1 } else { pos - 1 };
+        }
+    } else if encoding == UTF_8 {
+        while pos > origin && pos < bytes.len() && bytes[pos] & 0xC0 == 0x80 {
+            pos = if forward { pos + 1 } else { pos - 1 };
+        }
+    } else if multi_byte_char_len(encoding, &bytes[pos..]).is_some() {
+        // A trail byte can look like a lead byte or ASCII, so characters are walked from the start of the line:
+        // newlines are never trail bytes in these encodings, so that's always a boundary.
+        let mut at = memchr::memrchr(b'\n', &bytes[origin..pos]).map_or(origin, |newline| origin + newline + 1);
+        while at < pos {
+            let next = at + multi_byte_char_len(encoding, &bytes[at..]).unwrap_or(1);
+            if next > pos {
+                return if forward { next.min(bytes.len()) } else { at };
+            }
+            at = next;
+        }
+    }
+    pos.min(bytes.len())
+}
+
+fn read_range(bytes: &[u8], index: &mut LineIndex, range: PreviewRange) -> RangePreview {
+    let origin = index.origin();
+    let encoding = index.encoding();
+    let (start, end, start_line, truncated) = match range {
+        PreviewRange::Bytes { offset, length } => {
+            let start = (offset as usize).max(origin).min(bytes.len());
+            let end = start.saturating_add(length.min(MAX_TEXT_PREVIEW as u64) as usize).min(bytes.len());
+            let start = snap_to_char(bytes, start, origin, encoding, true);
+            let end = snap_to_char(bytes, end, origin, encoding, false).max(start);
+            (start, end, None, length > MAX_TEXT_PREVIEW as u64)
+        }
+        PreviewRange::Lines { start_line, line_count } => {
+            let first = start_line.max(1) - 1;
+            match index.line_start(bytes, first) {
+                Some(start) => {
+                    let end = index.line_start(bytes, first.saturating_add(line_count)).unwrap_or(bytes.len());
+                    if end - start > MAX_TEXT_PREVIEW {
+                        let cut = snap_to_char(bytes, start + MAX_TEXT_PREVIEW, origin, encoding, false);
+                        (start, cut, Some(first + 1), true)
+                    } else {
+                        (start, end, Some(first + 1), false)
+                    }
+                }
+                None => (bytes.len(), bytes.len(), Some(first + 1), false),
+            }
+        }
+    };
+
+    let (content, _) = encoding.decode_without_bom_handling(&bytes[start..end]);
+    RangePreview {
+        content: content.into_owned(),
+        encoding: encoding.name().to_string(),
+        size: bytes.len() as u64,
+        offset: start as u64,
+        length: (end - start) as u64,
+        start_line,
+        total_lines: index.total_lines(),
+        has_more: end < bytes.len(),
+        truncated,
+    }
+}
+
+pub(crate) fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn highlight_pattern(query: &str, options: HighlightOptions) -> Result<Regex, String> {
+    let source = if options.use_regex { query.to_string() } else { regex::escape(query) };
+    let source = if options.whole_word { format!(r"\b(?:{})\b", source) } else { source };
+    RegexBuilder::new(&source)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid highlight pattern: {}", e))
+}
+
+#[derive(Debug)]
+pub struct FilePreview {
+    extractors: Option<Arc<ExtractorRegistry>>,
+    line_indexes: Mutex<HashMap<PathBuf, CachedLineIndex>>,
+    /// Loading the syntax definitions takes a while, so it waits for the first source preview.
+    syntax: OnceLock<SyntaxHighlighter>,
+}
+
+impl Default for FilePreview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilePreview {
+    pub fn new() -> Self {
+        FilePreview { extractors: None, line_indexes: Mutex::new(HashMap::new()), syntax: OnceLock::new() }
+    }
+
+    pub fn with_extractors(mut self, extractors: Arc<ExtractorRegistry>) -> Self {
+        self.extractors = Some(extractors);
+        self
+    }
+
+    pub fn preview_file(&self, path: &Path, encoding: Option<&str>) -> Result<PreviewPayload, PreviewError> {
+        let forced = encoding
+            .map(crate::encoding::resolve_label)
+            .transpose()
+            .map_err(PreviewError::UnknownEncoding)?;
+
+        if let Some((archive_path, entry)) = path.to_str().and_then(crate::archive::split_virtual_path) {
+            let bytes = crate::archive::read_entry(Path::new(archive_path), entry, MAX_ARCHIVE_ENTRY_PREVIEW)?;
+            return Ok(self.preview_bytes(Path::new(entry), &bytes, bytes.len() as u64, forced));
+        }
+
+        let meta = std::fs::metadata(path).map_err(|e| PreviewError::from_io(e, path))?;
+        if meta.is_dir() {
+            return Err(PreviewError::IsDirectory(path.display().to_string()));
+        }
+
+        if let Some(extractors) = &self.extractors {
+            if let Some(text) = extractors.extract(path)? {
+                let mut payload = PreviewPayload::empty(PreviewKind::Text, meta.len());
+                let (content, truncated) = truncate_text(text.as_str());
+                payload.content = Some(content.to_string());
+                payload.truncated = truncated;
+                return Ok(payload);
+            }
+        }
+
+        if crate::archive::archive_kind(path).is_some() {
+            let mut payload = PreviewPayload::empty(PreviewKind::Archive, meta.len());
+            payload.archive = Some(ArchiveListing::new(crate::archive::list_entries(path)?));
+            return Ok(payload);
+        }
+        if is_unsupported(path) {
+            return Ok(PreviewPayload::empty(PreviewKind::Unsupported, meta.len()));
+        }
+        if meta.len() == 0 {
+            let mut payload = PreviewPayload::empty(PreviewKind::Text, 0);
+            payload.content = Some(String::new());
+            return Ok(payload);
+        }
+        if image_mime(path).is_some() && meta.len() > MAX_IMAGE_PREVIEW {
+            let mut payload = PreviewPayload::empty(PreviewKind::Image, meta.len());
+            payload.mime = image_mime(path).map(str::to_string);
+            payload.truncated = true;
+            return Ok(payload);
+        }
+
+        let file = File::open(path).map_err(|e| PreviewError::from_io(e, path))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| PreviewError::from_io(e, path))?;
+        Ok(self.preview_bytes(path, &mmap, meta.len(), forced))
+    }
+
+    fn preview_bytes(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        size: u64,
+        forced: Option<&'static Encoding>,
+    ) -> PreviewPayload {
+        if let Some(mime) = image_mime(path) {
+            let mut payload = PreviewPayload::empty(PreviewKind::Image, size);
+            payload.mime = Some(mime.to_string());
+            payload.data = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+            return payload;
+        }
+        if forced.is_none() && crate::encoding::looks_binary(bytes) {
+            return PreviewPayload::empty(PreviewKind::Binary, size);
+        }
+
+        let truncated = bytes.len() > MAX_TEXT_PREVIEW;
+        let decoded = crate::encoding::decode(&bytes[..bytes.len().min(MAX_TEXT_PREVIEW)], forced);
+        let mut payload = PreviewPayload::empty(PreviewKind::Text, size);
+        payload.content = Some(decoded.text.into_owned());
+        payload.encoding = Some(decoded.encoding.name().to_string());
+        payload.truncated = truncated;
+        payload.lossy = decoded.had_errors;
+        payload
+    }
+
+    /// Reads just `range` of a file, reusing a cached line index so paging through huge files stays cheap.
+    pub fn preview_range(
+        &self,
+        path: &Path,
+        range: PreviewRange,
+        encoding: Option<&str>,
+    ) -> Result<RangePreview, PreviewError> {
+        let forced = encoding
+            .map(crate::encoding::resolve_label)
+            .transpose()
+            .map_err(PreviewError::UnknownEncoding)?;
+
+        if let Some((archive_path, entry)) = path.to_str().and_then(crate::archive::split_virtual_path) {
+            let bytes = crate::archive::read_entry(Path::new(archive_path), entry, MAX_ARCHIVE_ENTRY_PREVIEW)?;
+            return self.range_of_bytes(path, &bytes, range, forced);
+        }
+
+        let meta = std::fs::metadata(path).map_err(|e| PreviewError::from_io(e, path))?;
+        if meta.is_dir() {
+            return Err(PreviewError::IsDirectory(path.display().to_string()));
+        }
+
+        if let Some(extractors) = &self.extractors {
+            if let Some(text) = extractors.extract(path)? {
+                let mut index = LineIndex::new(UTF_8, 0, text.len());
+                return Ok(read_range(text.as_bytes(), &mut index, range));
+            }
+        }
+        if meta.len() == 0 {
+            return self.range_of_bytes(path, &[], range, forced);
+        }
+
+        let file = File::open(path).map_err(|e| PreviewError::from_io(e, path))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| PreviewError::from_io(e, path))?;
+        let modified = meta.modified().map_err(|e| PreviewError::from_io(e, path))?;
+        let (encoding, origin) = self.range_encoding(path, &mmap, forced)?;
+
+        let cached = match self.line_indexes.lock() {
+            Ok(mut cache) => cache.remove(path),
+            Err(_) => None,
+        };
+        let (mut index, checkpoints) = match cached {
+            Some(cached) if cached.modified == modified && cached.size == meta.len() && cached.index.encoding() == encoding => {
+                (cached.index, cached.checkpoints)
+            }
+            _ => (LineIndex::new(encoding, origin, mmap.len()), None),
+        };
+        let preview = read_range(&mmap, &mut index, range);
+
+        if let Ok(mut cache) = self.line_indexes.lock() {
+            if cache.len() >= LINE_INDEX_CACHE_CAPACITY {
+                let oldest = cache.iter().min_by_key(|(_, cached)| cached.last_used).map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+            let entry = CachedLineIndex { modified, size: meta.len(), last_used: SystemTime::now(), index, checkpoints };
+            cache.insert(path.to_path_buf(), entry);
+        }
+        Ok(preview)
+    }
+
+    /// Lists a directory from the in-memory index rather than walking it again.
+    pub fn preview_directory<'a>(&self, dir: &Path, files: impl IntoIterator<Item = IndexedFile<'a>>) -> PreviewPayload {
+        let mut prefix = dir.to_string_lossy().to_string();
+        if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+            prefix.push(std::path::MAIN_SEPARATOR);
+        }
+
+        let mut by_name: HashMap<&str, DirectoryChild> = HashMap::new();
+        for file in files {
+            let Some(relative) = file.path.strip_prefix(prefix.as_str()) else { continue };
+            // Archive entries are listed when the archive itself is previewed.
+            if relative.contains(crate::archive::ARCHIVE_SEPARATOR) {
+                continue;
+            }
+            let (name, is_dir) = match relative.find(std::path::MAIN_SEPARATOR) {
+                Some(end) => (&relative[..end], true),
+                None => (relative, false),
+            };
+            let child = by_name.entry(name).or_insert_with(|| DirectoryChild {
+                name: name.to_string(),
+                is_dir,
+                size: 0,
+                file_count: 0,
+                modified: file.modified,
+            });
+            child.size += file.size;
+            child.file_count += 1;
+            child.modified = child.modified.max(file.modified);
+        }
+
+        let mut children: Vec<DirectoryChild> = by_name.into_values().collect();
+        let file_count = children.iter().map(|child| child.file_count).sum();
+        let dir_count = children.iter().filter(|child| child.is_dir).count();
+        let total_size = children.iter().map(|child| child.size).sum();
+
+        let mut largest = children.clone();
+        largest.sort_by_key(|child| std::cmp::Reverse(child.size));
+        largest.truncate(LARGEST_CHILDREN);
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        let truncated = children.len() > MAX_DIRECTORY_CHILDREN;
+        children.truncate(MAX_DIRECTORY_CHILDREN);
+
+        let mut payload = PreviewPayload::empty(PreviewKind::Directory, total_size);
+        payload.directory = Some(DirectoryListing { file_count, dir_count, total_size, children, largest, truncated });
+        payload
+    }
+
+    /// A ranged read rendered as syntax-highlighted HTML, with the language picked by extension or shebang.
+    pub fn preview_source(
+        &self,
+        path: &Path,
+        range: PreviewRange,
+        encoding: Option<&str>,
+    ) -> Result<SourcePreview, PreviewError> {
+        let range_preview = self.preview_range(path, range, encoding)?;
+        // The shebang sits on line 1, which a later page doesn't include.
+        let first_line = if range_preview.offset == 0 || range_preview.start_line == Some(1) {
+            range_preview.content.lines().next().unwrap_or_default().to_string()
+        } else {
+            let first = PreviewRange::Lines { start_line: 1, line_count: 1 };
+            self.preview_range(path, first, encoding)?.content
+        };
+        let highlighter = self.syntax.get_or_init(SyntaxHighlighter::new);
+        let content = &range_preview.content;
+        let source = match range_preview.start_line {
+            Some(start_line) if start_line > 1 => match self.state_at_line(path, &first_line, start_line - 1, encoding)? {
+                Some(state) => highlighter.highlight_at(path, &first_line, content, state),
+                None => highlighter.plain(path, &first_line, content),
+            },
+            // A byte range from the middle starts on an unknown line, so in an unknown state.
+            None if range_preview.offset > 0 => highlighter.plain(path, &first_line, content),
+            _ => highlighter.highlight(path, &first_line, content),
+        };
+        Ok(SourcePreview { range: range_preview, source })
+    }
+
+    /// The highlighter's state where (zero-based) `line` starts, parsing forward from the cached checkpoints.
+    /// `None` when that's more than one request may parse, or no syntax matches the file.
+    fn state_at_line(&self, path: &Path, first_line: &str, line: usize, encoding: Option<&str>) -> Result<Option<LineState>, PreviewError> {
+        let highlighter = self.syntax.get_or_init(SyntaxHighlighter::new);
+        let cached = match self.line_indexes.lock() {
+            Ok(mut cache) => cache.get_mut(path).and_then(|cached| cached.checkpoints.take()),
+            Err(_) => None,
+        };
+        let Some(mut checkpoints) = cached.or_else(|| highlighter.checkpoints(path, first_line)) else { return Ok(None) };
+
+        let from = line / CHECKPOINT_LINES * CHECKPOINT_LINES;
+        let mut budget = MAX_CHECKPOINT_PARSE;
+        while checkpoints.last_line() < from {
+            let next = PreviewRange::Lines { start_line: checkpoints.last_line() + 1, line_count: CHECKPOINT_LINES };
+            let chunk = self.preview_range(path, next, encoding)?;
+            if chunk.truncated || chunk.length as usize > budget || !highlighter.extend_checkpoints(&mut checkpoints, &chunk.content) {
+                break;
+            }
+            budget -= chunk.length as usize;
+        }
+        let state = if checkpoints.last_line() >= from {
+            let skipped = if line > from {
+                let skipped = self.preview_range(path, PreviewRange::Lines { start_line: from + 1, line_count: line - from }, encoding)?;
+                (!skipped.truncated).then_some(skipped.content)
+            } else {
+                Some(String::new())
+            };
+            skipped.and_then(|skipped| highlighter.state_at(&checkpoints, path, first_line, line, &skipped))
+        } else {
+            None
+        };
+
+        if let Ok(mut cache) = self.line_indexes.lock() {
+            if let Some(cached) = cache.get_mut(path) {
+                cached.checkpoints = Some(checkpoints);
+            }
+        }
+        Ok(state)
+    }
+
+    /// One page of a hex+ASCII dump. `pattern` is highlighted when it reads as a hex byte sequence.
+    pub fn preview_hex(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: Option<u64>,
+        pattern: Option<&str>,
+    ) -> Result<HexPreview, PreviewError> {
+        let entry_bytes;
+        let mmap;
+        let bytes: &[u8] = if let Some((archive_path, entry)) = path.to_str().and_then(crate::archive::split_virtual_path) {
+            entry_bytes = crate::archive::read_entry(Path::new(archive_path), entry, MAX_ARCHIVE_ENTRY_PREVIEW)?;
+            &entry_bytes
+        } else {
+            let meta = std::fs::metadata(path).map_err(|e| PreviewError::from_io(e, path))?;
+            if meta.is_dir() {
+                return Err(PreviewError::IsDirectory(path.display().to_string()));
+            }
+            if meta.len() == 0 {
+                &[]
+            } else {
+                let file = File::open(path).map_err(|e| PreviewError::from_io(e, path))?;
+                mmap = unsafe { Mmap::map(&file) }.map_err(|e| PreviewError::from_io(e, path))?;
+                &mmap
+            }
+        };
+
+        let start = (offset as usize).min(bytes.len()) / BYTES_PER_ROW * BYTES_PER_ROW;
+        let length = length.unwrap_or(DEFAULT_HEX_PAGE).min(MAX_HEX_PAGE) as usize;
+        let end = (start + length).min(bytes.len());
+        let matches = pattern
+            .and_then(crate::hexdump::parse_hex_pattern)
+            .map(|pattern| crate::hexdump::find_matches(bytes, start, end, &pattern))
+            .unwrap_or_default();
+
+        Ok(HexPreview {
+            file_type: crate::hexdump::detect_type(bytes),
+            size: bytes.len() as u64,
+            offset: start as u64,
+            length: (end - start) as u64,
+            rows: crate::hexdump::format_rows(&bytes[start..end], start as u64),
+            matches,
+            has_more: end < bytes.len(),
+        })
+    }
+
+    fn range_encoding(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        forced: Option<&'static Encoding>,
+    ) -> Result<(&'static Encoding, usize), PreviewError> {
+        if forced.is_none() && crate::encoding::looks_binary(bytes) {
+            return Err(PreviewError::NotText(path.display().to_string()));
+        }
+        Ok(crate::encoding::detect(&bytes[..bytes.len().min(RANGE_SNIFF_LEN)], forced))
+    }
+
+    fn range_of_bytes(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        range: PreviewRange,
+        forced: Option<&'static Encoding>,
+    ) -> Result<RangePreview, PreviewError> {
+        let (encoding, origin) = self.range_encoding(path, bytes, forced)?;
+        let mut index = LineIndex::new(encoding, origin, bytes.len());
+        Ok(read_range(bytes, &mut index, range))
+    }
+
+    /// Escapes `content` for the webview and marks every match of `query`; file content is never trusted as HTML.
+    pub fn highlight_content(&self, content: &str, query: &str, options: HighlightOptions) -> Result<Highlight, String> {
+        let mut html = String::with_capacity(content.len() + content.len() / 8);
+        let mut ranges = Vec::new();
+        if query.is_empty() {
+            push_escaped(&mut html, content);
+            return Ok(Highlight { html, ranges });
+        }
+
+        let pattern = highlight_pattern(query, options)?;
+        let (mut last, mut chars) = (0, 0);
+        for m in pattern.find_iter(content).filter(|m| !m.is_empty()) {
+            let between = &content[last..m.start()];
+            push_escaped(&mut html, between);
+            html.push_str("<mark>");
+            push_escaped(&mut html, m.as_str());
+            html.push_str("</mark>");
+            let range = MatchRange::with_char_start(content, m.start(), m.end(), chars + between.chars().count());
+            chars = range.char_end;
+            ranges.push(range);
+            last = m.end();
+        }
+        push_escaped(&mut html, &content[last..]);
+        Ok(Highlight { html, ranges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_preview() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        
+        let mut file = File::create(&file_path).unwrap();
+        file.write_all(b"Hello, world! This is a test file.").unwrap();
+        
+        let previewer = FilePreview::new();
+        let preview = previewer.preview_file(&file_path, None).unwrap();
+        assert_eq!(preview.kind, PreviewKind::Text);
+        assert_eq!(preview.encoding.as_deref(), Some("UTF-8"));
+        assert!(preview.content.unwrap().contains("Hello, world!"));
+    }
+
+    #[test]
+    fn test_preview_utf16_and_override() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("utf16.txt");
+
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("预览 preview".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        File::create(&file_path).unwrap().write_all(&bytes).unwrap();
+
+        let previewer = FilePreview::new();
+        let preview = previewer.preview_file(&file_path, None).unwrap();
+        assert_eq!(preview.content.as_deref(), Some("预览 preview"));
+        assert_eq!(preview.encoding.as_deref(), Some("UTF-16LE"));
+        assert!(matches!(
+            previewer.preview_file(&file_path, Some("not-an-encoding")),
+            Err(PreviewError::UnknownEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_preview_kinds_and_errors() {
+        let temp_dir = tempdir().unwrap();
+        let binary = temp_dir.path().join("data.bin");
+        File::create(&binary).unwrap().write_all(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00").unwrap();
+        let video = temp_dir.path().join("clip.mp4");
+        File::create(&video).unwrap().write_all(b"not really a video").unwrap();
+        let large = temp_dir.path().join("large.log");
+        File::create(&large).unwrap().write_all(&vec![b'x'; MAX_TEXT_PREVIEW + 10]).unwrap();
+
+        let previewer = FilePreview::new();
+        assert_eq!(previewer.preview_file(&binary, None).unwrap().kind, PreviewKind::Binary);
+        assert_eq!(previewer.preview_file(&video, None).unwrap().kind, PreviewKind::Unsupported);
+
+        let preview = previewer.preview_file(&large, None).unwrap();
+        assert!(preview.truncated);
+        assert_eq!(preview.size, MAX_TEXT_PREVIEW as u64 + 10);
+        assert_eq!(preview.content.unwrap().len(), MAX_TEXT_PREVIEW);
+
+        let missing = previewer.preview_file(&temp_dir.path().join("missing.txt"), None).unwrap_err();
+        assert_eq!(missing.code(), "not_found");
+        assert_eq!(serde_json::to_value(&missing).unwrap()["code"], "not_found");
+        assert!(matches!(previewer.preview_file(temp_dir.path(), None), Err(PreviewError::IsDirectory(_))));
+    }
+
+    #[test]
+    fn test_preview_ranges() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("big.log");
+        let text: String = (1..=5000).map(|n| format!("第{}行 entry\n", n)).collect();
+        File::create(&file_path).unwrap().write_all(text.as_bytes()).unwrap();
+
+        let previewer = FilePreview::new();
+        let lines = previewer
+            .preview_range(&file_path, PreviewRange::Lines { start_line: 4000, line_count: 2 }, None)
+            .unwrap();
+        assert_eq!(lines.content, "第4000行 entry\n第4001行 entry\n");
+        assert_eq!(lines.start_line, Some(4000));
+        assert!(lines.has_more);
+
+        let tail = previewer
+            .preview_range(&file_path, PreviewRange::Lines { start_line: 4999, line_count: 10 }, None)
+            .unwrap();
+        assert_eq!(tail.content, "第4999行 entry\n第5000行 entry\n");
+        assert_eq!(tail.total_lines, Some(5000));
+        assert!(!tail.has_more);
+        let rest = previewer
+            .preview_range(&file_path, PreviewRange::Lines { start_line: 4999, line_count: usize::MAX }, None)
+            .unwrap();
+        assert_eq!(rest.content, tail.content);
+
+        // Byte 1 lands inside the first "第", so the range snaps forward to the next character.
+        let bytes = previewer
+            .preview_range(&file_path, PreviewRange::Bytes { offset: 1, length: 20 }, None)
+            .unwrap();
+        assert_eq!(bytes.offset, 3);
+        assert!(bytes.content.starts_with("1行 entry"));
+
+        // In Shift_JIS "ソ" is 0x83 0x5C, so byte 5 looks like a backslash; both ends snap to whole characters.
+        let sjis = temp_dir.path().join("sjis.txt");
+        let (encoded, _, _) = SHIFT_JIS.encode("abc\nソソソソ\n");
+        File::create(&sjis).unwrap().write_all(&encoded).unwrap();
+        let bytes = previewer
+            .preview_range(&sjis, PreviewRange::Bytes { offset: 5, length: 4 }, Some("shift_jis"))
+            .unwrap();
+        assert_eq!((bytes.offset, bytes.length), (6, 2));
+        assert_eq!(bytes.content, "ソ");
+
+        let range: PreviewRange = serde_json::from_str(r#"{"start_line": 3, "line_count": 1}"#).unwrap();
+        assert_eq!(range, PreviewRange::Lines { start_line: 3, line_count: 1 });
+
+        let binary = temp_dir.path().join("data.bin");
+        File::create(&binary).unwrap().write_all(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00").unwrap();
+        let range = PreviewRange::Bytes { offset: 0, length: 10 };
+        assert!(matches!(previewer.preview_range(&binary, range, None), Err(PreviewError::NotText(_))));
+    }
+
+    #[test]
+    fn test_preview_archive_and_directory() {
+        let temp_dir = tempdir().unwrap();
+        let zip_path = temp_dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("a.txt", options).unwrap();
+        writer.write_all(&[b'a'; 1000]).unwrap();
+        writer.start_file("b.txt", options).unwrap();
+        writer.write_all(b"bb").unwrap();
+        writer.finish().unwrap();
+
+        let previewer = FilePreview::new();
+        let preview = previewer.preview_file(&zip_path, None).unwrap();
+        assert_eq!(preview.kind, PreviewKind::Archive);
+        let listing = preview.archive.unwrap();
+        assert_eq!(listing.entry_count, 2);
+        assert_eq!(listing.total_size, 1002);
+        assert!(listing.total_compressed_size.unwrap() < 1002);
+
+        let root = Path::new("/data");
+        let paths: Vec<String> = ["docs/a.md", "docs/deep/b.md", "big.iso", "small.txt", "big.iso!/inner"]
+            .iter()
+            .map(|relative| root.join(relative).to_string_lossy().to_string())
+            .collect();
+        let sizes = [10, 20, 5000, 1, 7];
+        let files = paths.iter().zip(sizes).map(|(path, size)| IndexedFile { path, size, modified: Utc::now() });
+
+        let listing = previewer.preview_directory(root, files).directory.unwrap();
+        assert_eq!((listing.file_count, listing.dir_count, listing.total_size), (4, 1, 5031));
+        assert_eq!(listing.children[0].name, "docs");
+        assert_eq!(listing.children[0].file_count, 2);
+        assert_eq!(listing.largest[0].name, "big.iso");
+    }
+
+    #[test]
+    fn test_preview_source_ranges() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("build");
+        let script: String = std::iter::once("#!/bin/bash\n".to_string())
+            .chain((1..=50).map(|n| format!("echo \"step {}\"\n", n)))
+            .collect();
+        File::create(&file_path).unwrap().write_all(script.as_bytes()).unwrap();
+
+        let previewer = FilePreview::new();
+        let page = previewer
+            .preview_source(&file_path, PreviewRange::Lines { start_line: 40, line_count: 5 }, None)
+            .unwrap();
+        assert_eq!(page.range.start_line, Some(40));
+        assert_eq!(page.source.language.as_deref(), Some("Bourne Again Shell (bash)"));
+        assert!(page.source.highlighted);
+        assert!(page.source.html.contains("step 39"));
+    }
+
+    #[test]
+    fn test_preview_source_inside_block_comment() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("lib.rs");
+        let source: String = std::iter::once("fn main() {}\n/*\n".to_string())
+            .chain((0..3000).map(|n| format!("let x{} = \"not code\";\n", n)))
+            .chain(std::iter::once("*/\nfn after() {}\n".to_string()))
+            .collect();
+        File::create(&file_path).unwrap().write_all(source.as_bytes()).unwrap();
+
+        // Past two checkpoints, in the middle of the comment.
+        let previewer = FilePreview::new();
+        let page = previewer
+            .preview_source(&file_path, PreviewRange::Lines { start_line: 1500, line_count: 3 }, None)
+            .unwrap();
+        assert!(page.source.highlighted);
+        let highlighter = SyntaxHighlighter::new();
+        let in_comment = highlighter.highlight(&file_path, "fn main() {}", &format!("/*\n{}", page.range.content));
+        assert!(in_comment.html.ends_with(&page.source.html));
+        assert_ne!(page.source.html, highlighter.highlight(&file_path, "fn main() {}", &page.range.content).html);
+
+        // The cached checkpoints serve a later page too, and one read by bytes from the middle isn't guessed at.
+        let later = previewer.preview_source(&file_path, PreviewRange::Lines { start_line: 2000, line_count: 1 }, None).unwrap();
+        assert!(highlighter.highlight(&file_path, "fn main() {}", &format!("/*\n{}", later.range.content)).html.ends_with(&later.source.html));
+        let bytes = previewer.preview_source(&file_path, PreviewRange::Bytes { offset: 5000, length: 100 }, None).unwrap();
+        assert!(!bytes.source.highlighted);
+    }
+
+    #[test]
+    fn test_preview_hex() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("program");
+        let mut bytes = b"\x7fELF\x02\x01\x01\x00".to_vec();
+        bytes.extend((0..100u8).map(|b| b.wrapping_mul(7)));
+        bytes.extend(b"\xde\xad\xbe\xef");
+        File::create(&file_path).unwrap().write_all(&bytes).unwrap();
+
+        let previewer = FilePreview::new();
+        let page = previewer.preview_hex(&file_path, 100, Some(32), Some("de ad be ef")).unwrap();
+        assert_eq!(page.file_type.unwrap().description, "ELF executable");
+        assert_eq!(page.offset, 96);
+        assert_eq!(page.rows.len(), 1);
+        assert_eq!(page.matches, vec![ByteMatch { start: 108, end: 112 }]);
+        assert!(!page.has_more);
+
+        let first = previewer.preview_hex(&file_path, 0, None, Some("not hex")).unwrap();
+        assert!(first.rows[0].ascii.starts_with(".ELF"));
+        assert!(first.matches.is_empty());
+    }
+
+    #[test]
+    fn test_content_highlighting() {
+        let previewer = FilePreview::new();
+        let content = "Hello, world! This is a test.";
+        let highlighted = previewer.highlight_content(content, "test", HighlightOptions::default()).unwrap();
+        assert!(highlighted.html.contains("<mark>test</mark>"));
+        assert_eq!(highlighted.ranges[0].byte_start, 24);
+    }
+
+    #[test]
+    fn test_highlighting_escapes_and_options() {
+        let previewer = FilePreview::new();
+        let content = "<script>alert('x')</script> Test tested";
+
+        let highlighted = previewer.highlight_content(content, "test", HighlightOptions::default()).unwrap();
+        assert!(highlighted.html.starts_with("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; "));
+        assert!(!highlighted.html.contains("<script>"));
+        assert_eq!(highlighted.ranges.len(), 2);
+
+        let whole_word = HighlightOptions { case_sensitive: true, whole_word: true, ..Default::default() };
+        assert_eq!(previewer.highlight_content(content, "Test", whole_word).unwrap().ranges.len(), 1);
+
+        let regex = HighlightOptions { use_regex: true, ..Default::default() };
+        let highlighted = previewer.highlight_content(content, "<scr.pt>", regex).unwrap();
+        assert!(highlighted.html.starts_with("<mark>&lt;script&gt;</mark>"));
+        assert!(previewer.highlight_content(content, "(", regex).is_err());
+
+        // A megabyte with a match every few chars: offsets are counted as the matches go, not from the start each time.
+        let large = "日本e ".repeat(256 * 1024);
+        let started = std::time::Instant::now();
+        let highlighted = previewer.highlight_content(&large, "e", HighlightOptions::default()).unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(highlighted.ranges.len(), 256 * 1024);
+        let last = highlighted.ranges.last().unwrap();
+        assert_eq!((last.char_start, last.char_end), (large.chars().count() - 2, large.chars().count() - 1));
+        assert_eq!(last.byte_start, large.len() - 2);
+    }
+}
@@ -0,0 +1,294 @@
+//! Request handling for the `ultrasearch://` URI scheme, independent of the webview that serves it.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+use http::{header, Method, Request, Response, StatusCode};
+use crate::preview::{FilePreview, PreviewError, PreviewRange};
+use crate::thumbnail::{Thumbnailer, DEFAULT_THUMBNAIL_SIZE};
+
+/// Served as `ultrasearch://localhost/...` (`http://ultrasearch.localhost/...` on Windows).
+pub const SCHEME: &str = "ultrasearch";
+/// Largest body a single response carries; open-ended and missing ranges are cut to this.
+const MAX_CHUNK: u64 = 8 * 1024 * 1024;
+/// Archive entries have to be decompressed into memory before any range can be served.
+const MAX_ARCHIVE_ENTRY: u64 = 64 * 1024 * 1024;
+
+/// What the protocol handler needs from the app, so it can be driven without a webview.
+pub struct ProtocolContext<'a> {
+    pub preview: &'a FilePreview,
+    pub thumbnailer: &'a Thumbnailer,
+    /// Only indexed files and files under the allowed roots may be served.
+    pub is_allowed: &'a dyn Fn(&Path) -> bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    Satisfiable { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+/// Parses a single-range `Range` header against a body of `size` bytes; `end` is exclusive.
+fn parse_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else { return ByteRange::Full };
+    // Multiple ranges would need a multipart body; serving the first one is allowed.
+    let first = spec.split(',').next().unwrap_or_default().trim();
+    let Some((start, end)) = first.split_once('-') else { return ByteRange::Full };
+    let (start, end) = match (start.trim().parse::<u64>(), end.trim().parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.saturating_add(1).min(size)),
+        (Ok(start), Err(_)) if end.trim().is_empty() => (start, size),
+        (Err(_), Ok(suffix)) if start.trim().is_empty() => (size.saturating_sub(suffix), size),
+        _ => return ByteRange::Full,
+    };
+    if start >= size || start >= end {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Satisfiable { start, end }
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    if let Some(mime) = crate::preview::image_mime(path) {
+        return mime;
+    }
+    let extension = path.extension().and_then(|os| os.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "aac" => "audio/aac",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "pdf" => "application/pdf",
+        "txt" | "log" | "md" | "csv" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn builder(status: StatusCode) -> http::response::Builder {
+    Response::builder()
+        .status(status)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_EXPOSE_HEADERS, "*")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        // Served files are data, never a page: an SVG or HTML file opened directly must not run scripts.
+        .header(header::CONTENT_SECURITY_POLICY, "default-src 'none'; style-src 'unsafe-inline'; sandbox")
+}
+
+fn finish(builder: http::response::Builder, body: Vec<u8>) -> Response<Vec<u8>> {
+    builder.body(body).unwrap_or_else(|_| {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    })
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response<Vec<u8>> {
+    let body = serde_json::json!({ "code": code, "message": message }).to_string().into_bytes();
+    finish(builder(status).header(header::CONTENT_TYPE, "application/json"), body)
+}
+
+pub(crate) fn preview_error_status(e: &PreviewError) -> StatusCode {
+    match e {
+        PreviewError::NotFound(_) => StatusCode::NOT_FOUND,
+        PreviewError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+        PreviewError::IsDirectory(_) | PreviewError::UnknownEncoding(_) => StatusCode::BAD_REQUEST,
+        PreviewError::NotText(_) | PreviewError::NotImage(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        PreviewError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn preview_error(e: PreviewError) -> Response<Vec<u8>> {
+    error_response(preview_error_status(&e), e.code(), &e.to_string())
+}
+
+/// Handles one request: `/file` (raw bytes, honours `Range`), `/thumbnail` and `/text` (a decoded range).
+/// Every endpoint takes the target as a percent-encoded `path` query parameter.
+pub fn respond(request: &Request<Vec<u8>>, ctx: &ProtocolContext) -> Response<Vec<u8>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "only GET and HEAD are supported");
+    }
+    let query: HashMap<Cow<str>, Cow<str>> =
+        url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes()).collect();
+    let Some(path) = query.get("path").map(|path| Path::new(path.as_ref())) else {
+        return error_response(StatusCode::BAD_REQUEST, "bad_request", "missing path parameter");
+    };
+    if !(ctx.is_allowed)(path) {
+        return error_response(StatusCode::FORBIDDEN, "forbidden", "path is not indexed or under an allowed root");
+    }
+
+    let number = |name: &str| query.get(name).and_then(|value| value.parse::<u64>().ok());
+    let mut response = match request.uri().path().trim_end_matches('/') {
+        "/file" => {
+            let range = request.headers().get(header::RANGE).and_then(|value| value.to_str().ok());
+            serve_file(path, range)
+        }
+        "/thumbnail" => {
+            let size = number("size").map(|size| size as u32).unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+            match ctx.thumbnailer.thumbnail_bytes(path, size) {
+                Ok((info, bytes)) => finish(
+                    builder(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, info.mime.as_str())
+                        .header(header::CACHE_CONTROL, "max-age=3600")
+                        .header("X-Image-Width", info.width)
+                        .header("X-Image-Height", info.height),
+                    bytes,
+                ),
+                Err(e) => preview_error(e),
+            }
+        }
+        "/text" => {
+            let range = match (number("offset"), number("start_line")) {
+                (Some(offset), _) => PreviewRange::Bytes { offset, length: number("length").unwrap_or(MAX_CHUNK) },
+                (None, start_line) => PreviewRange::Lines {
+                    start_line: start_line.unwrap_or(1) as usize,
+                    line_count: number("line_count").unwrap_or(1000) as usize,
+                },
+            };
+            match ctx.preview.preview_range(path, range, query.get("encoding").map(|e| e.as_ref())) {
+                Ok(preview) => {
+                    let mut response = builder(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                        .header("X-Encoding", preview.encoding.as_str())
+                        .header("X-Offset", preview.offset)
+                        .header("X-Length", preview.length)
+                        .header("X-Has-More", preview.has_more.to_string());
+                    if let Some(total_lines) = preview.total_lines {
+                        response = response.header("X-Total-Lines", total_lines);
+                    }
+                    finish(response, preview.content.into_bytes())
+                }
+                Err(e) => preview_error(e),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "not_found", "unknown endpoint"),
+    };
+
+    if request.method() == Method::HEAD {
+        response.body_mut().clear();
+    }
+    response
+}
+
+fn serve_file(path: &Path, range: Option<&str>) -> Response<Vec<u8>> {
+    let entry_bytes = match path.to_str().and_then(crate::archive::split_virtual_path) {
+        Some((archive, entry)) => match crate::archive::read_entry(Path::new(archive), entry, MAX_ARCHIVE_ENTRY) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => return preview_error(e.into()),
+        },
+        None => None,
+    };
+    let size = match &entry_bytes {
+        Some(bytes) => bytes.len() as u64,
+        None => match std::fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => return preview_error(PreviewError::IsDirectory(path.display().to_string())),
+            Ok(meta) => meta.len(),
+            Err(e) => return preview_error(PreviewError::from_io(e, path)),
+        },
+    };
+
+    let (status, start, end) = match parse_range(range, size) {
+        ByteRange::Satisfiable { start, end } => (StatusCode::PARTIAL_CONTENT, start, end.min(start + MAX_CHUNK)),
+        // Large files are only ever sent in chunks, even when the client didn't ask for a range.
+        ByteRange::Full if size > MAX_CHUNK => (StatusCode::PARTIAL_CONTENT, 0, MAX_CHUNK),
+        ByteRange::Full => (StatusCode::OK, 0, size),
+        ByteRange::Unsatisfiable => {
+            return finish(
+                builder(StatusCode::RANGE_NOT_SATISFIABLE).header(header::CONTENT_RANGE, format!("bytes */{}", size)),
+                Vec::new(),
+            )
+        }
+    };
+
+    let body = match entry_bytes {
+        Some(bytes) => bytes[start as usize..end as usize].to_vec(),
+        None => {
+            let read = File::open(path).and_then(|mut file| {
+                file.seek(SeekFrom::Start(start))?;
+                let mut body = vec![0; (end - start) as usize];
+                file.read_exact(&mut body)?;
+                Ok(body)
+            });
+            match read {
+                Ok(body) => body,
+                Err(e) => return preview_error(PreviewError::from_io(e, path)),
+            }
+        }
+    };
+
+    let mut response = builder(status)
+        .header(header::CONTENT_TYPE, content_type(path))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_LENGTH, body.len());
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, size));
+    }
+    finish(response, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=10-19"), 100), ByteRange::Satisfiable { start: 10, end: 20 });
+        assert_eq!(parse_range(Some("bytes=90-"), 100), ByteRange::Satisfiable { start: 90, end: 100 });
+        assert_eq!(parse_range(Some("bytes=-5"), 100), ByteRange::Satisfiable { start: 95, end: 100 });
+        assert_eq!(parse_range(Some("bytes=50-500"), 100), ByteRange::Satisfiable { start: 50, end: 100 });
+        assert_eq!(parse_range(Some("bytes=100-"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range(Some("items=1-2"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_respond_ranges_and_access() {
+        let temp_dir = tempdir().unwrap();
+        let clip = temp_dir.path().join("clip.mp4");
+        File::create(&clip).unwrap().write_all(&(0..=255u8).collect::<Vec<_>>()).unwrap();
+        let secret = temp_dir.path().join("secret.txt");
+        File::create(&secret).unwrap().write_all(b"top secret").unwrap();
+
+        let preview = FilePreview::new();
+        let thumbnailer = Thumbnailer::new(temp_dir.path().join("thumbnails"));
+        let is_allowed = |path: &Path| path == clip;
+        let ctx = ProtocolContext { preview: &preview, thumbnailer: &thumbnailer, is_allowed: &is_allowed };
+        let encoded = |path: &Path| url::form_urlencoded::byte_serialize(path.to_str().unwrap().as_bytes()).collect::<String>();
+        let get = |uri: String, range: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range);
+            }
+            respond(&request.body(Vec::new()).unwrap(), &ctx)
+        };
+
+        let full = get(format!("ultrasearch://localhost/file?path={}", encoded(&clip)), None);
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.headers()[header::CONTENT_TYPE], "video/mp4");
+        assert_eq!(full.body().len(), 256);
+
+        let partial = get(format!("ultrasearch://localhost/file?path={}", encoded(&clip)), Some("bytes=16-31"));
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.headers()[header::CONTENT_RANGE], "bytes 16-31/256");
+        assert_eq!(partial.body(), &(16..32u8).collect::<Vec<_>>());
+
+        let beyond = get(format!("ultrasearch://localhost/file?path={}", encoded(&clip)), Some("bytes=300-"));
+        assert_eq!(beyond.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let denied = get(format!("ultrasearch://localhost/file?path={}", encoded(&secret)), None);
+        assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+        assert!(get("ultrasearch://localhost/file".to_string(), None).status().is_client_error());
+    }
+}
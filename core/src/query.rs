@@ -0,0 +1,223 @@
+//! File name queries over the index: substring matching, filters, sorting and paging.
+
+use std::{cmp::Ordering, str::FromStr};
+use serde::{Deserialize, Serialize};
+use crate::indexer::FileMetadata;
+use crate::searcher::MatchRange;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AdvancedFilterOptions {
+    /// Extension without the dot, compared case-insensitively; empty means any.
+    pub file_type: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+impl AdvancedFilterOptions {
+    pub fn matches(&self, file: &FileMetadata) -> bool {
+        if let Some(ext_filter) = self.file_type.as_deref().filter(|ext| !ext.is_empty()) {
+            if !file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(ext_filter)) {
+                return false;
+            }
+        }
+        self.min_size.is_none_or(|min_size| file.size >= min_size)
+            && self.max_size.is_none_or(|max_size| file.size <= max_size)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Name,
+    Path,
+    Size,
+    Modified,
+    Extension,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "name" => Ok(SortKey::Name),
+            "path" => Ok(SortKey::Path),
+            "size" => Ok(SortKey::Size),
+            "modified" | "date" | "mtime" => Ok(SortKey::Modified),
+            "extension" | "ext" => Ok(SortKey::Extension),
+            other => Err(format!("Unknown sort key '{}' (expected name, path, size, modified or extension)", other)),
+        }
+    }
+}
+
+fn cmp_ignore_case(a: &str, b: &str) -> Ordering {
+    a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase))
+}
+
+impl SortKey {
+    /// Ties fall back to the path, so results come out in a stable order.
+    pub fn compare(self, a: &FileMetadata, b: &FileMetadata) -> Ordering {
+        let primary = match self {
+            SortKey::Name => cmp_ignore_case(&a.name, &b.name),
+            SortKey::Path => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified_time.cmp(&b.modified_time),
+            SortKey::Extension => cmp_ignore_case(a.extension().unwrap_or_default(), b.extension().unwrap_or_default()),
+        };
+        primary.then_with(|| cmp_ignore_case(&a.path, &b.path))
+    }
+}
+
+/// One row in the results list: a file for name searches, or a matching line for content searches.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub file_path: String,
+    pub name: String,
+    pub size: u64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub modified_time: chrono::DateTime<chrono::Utc>,
+    pub line_number: Option<i32>,
+    pub content: Option<String>,
+    pub matches: Option<Vec<String>>,
+    pub context_before: Option<Vec<String>>,
+    pub context_after: Option<Vec<String>>,
+    pub match_ranges: Option<Vec<MatchRange>>,
+    /// Set for read-only entries from an imported file list or an unplugged volume.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Id of the removable or external volume the file is on.
+    #[serde(default)]
+    pub volume: Option<String>,
+    /// The entry comes from a catalog rather than a file that's reachable right now.
+    #[serde(default)]
+    pub offline: bool,
+}
+
+impl From<FileMetadata> for SearchResult {
+    fn from(metadata: FileMetadata) -> Self {
+        SearchResult {
+            file_path: metadata.path.clone(),
+            name: metadata.name.clone(),
+            size: metadata.size,
+            modified_time: metadata.modified_time,
+            line_number: None,
+            content: None,
+            matches: None,
+            context_before: None,
+            context_after: None,
+            match_ranges: None,
+            offline: metadata.source.is_some(),
+            source: metadata.source,
+            volume: metadata.volume,
+        }
+    }
+}
+
+/// A file name query as run by the search box: a case-insensitive substring of the name or path,
+/// plus the advanced filters and an optional sort order.
+#[derive(Debug, Clone, Default)]
+pub struct FileQuery {
+    query_lower: String,
+    filters: AdvancedFilterOptions,
+    sort: Option<(SortKey, bool)>,
+}
+
+impl FileQuery {
+    pub fn new(query: &str) -> Self {
+        FileQuery { query_lower: query.to_lowercase(), ..Default::default() }
+    }
+
+    pub fn with_filters(mut self, filters: AdvancedFilterOptions) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn with_sort(mut self, key: SortKey, descending: bool) -> Self {
+        self.sort = Some((key, descending));
+        self
+    }
+
+    pub fn matches(&self, file: &FileMetadata) -> bool {
+        (self.query_lower.is_empty()
+            || file.name.to_lowercase().contains(&self.query_lower)
+            || file.path.to_lowercase().contains(&self.query_lower))
+            && self.filters.matches(file)
+    }
+
+    fn sort_results(&self, results: &mut [&FileMetadata]) {
+        if let Some((key, descending)) = self.sort {
+            results.sort_by(|a, b| {
+                let ordering = key.compare(a, b);
+                if descending { ordering.reverse() } else { ordering }
+            });
+        }
+    }
+
+    /// Up to `limit` matches after skipping `offset`. Unsorted queries stop at the first `offset + limit`
+    /// matches; sorted ones have to look at every file.
+    pub fn search<'a>(&self, files: &'a [FileMetadata], offset: usize, limit: usize) -> Vec<&'a FileMetadata> {
+        let matches = files.iter().filter(|file| self.matches(file));
+        if self.sort.is_none() {
+            return matches.skip(offset).take(limit).collect();
+        }
+        let mut results: Vec<&FileMetadata> = matches.collect();
+        self.sort_results(&mut results);
+        results.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// Like [`FileQuery::search`], but always counts every match so callers can page through them.
+    pub fn page<'a>(&self, files: &'a [FileMetadata], offset: usize, limit: usize) -> SearchPage<'a> {
+        let mut results: Vec<&FileMetadata> = files.iter().filter(|file| self.matches(file)).collect();
+        let total = results.len();
+        self.sort_results(&mut results);
+        SearchPage { total, results: results.into_iter().skip(offset).take(limit).collect() }
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchPage<'a> {
+    pub total: usize,
+    pub results: Vec<&'a FileMetadata>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn file(path: &str, size: u64, day: u32) -> FileMetadata {
+        FileMetadata {
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            size,
+            modified_time: Utc.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_query_filters_and_sort() {
+        let files = vec![
+            file("/src/Main.rs", 300, 3),
+            file("/src/lib.rs", 100, 1),
+            file("/docs/main.md", 200, 2),
+            file("/src/main/mod.RS", 50, 4),
+        ];
+
+        let names = |results: Vec<&FileMetadata>| results.into_iter().map(|file| file.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(FileQuery::new("MAIN").search(&files, 0, 10)), vec!["Main.rs", "main.md", "mod.RS"]);
+
+        let rust = AdvancedFilterOptions { file_type: Some("rs".to_string()), min_size: Some(60), max_size: None };
+        assert_eq!(names(FileQuery::new("").with_filters(rust).search(&files, 0, 10)), vec!["Main.rs", "lib.rs"]);
+
+        let by_size = FileQuery::new("").with_sort(SortKey::Size, true);
+        assert_eq!(names(by_size.search(&files, 1, 2)), vec!["main.md", "lib.rs"]);
+        let page = by_size.page(&files, 3, 2);
+        assert_eq!((page.total, names(page.results)), (4, vec!["mod.RS".to_string()]));
+        let by_name = FileQuery::new("rs").with_sort(SortKey::Name, false);
+        assert_eq!(names(by_name.search(&files, 0, 10)), vec!["lib.rs", "Main.rs", "mod.RS"]);
+
+        assert_eq!("Size".parse::<SortKey>(), Ok(SortKey::Size));
+        assert!("speed".parse::<SortKey>().is_err());
+    }
+}
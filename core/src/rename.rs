@@ -0,0 +1,604 @@
+//! Batch renaming of search results: rules turn each name into a new one, a plan shows the result and
+//! its conflicts before anything changes, and applied batches are journaled so they can be undone.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, Utc,
+};
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::{archive, fileops, indexer::{self, FileMetadata}};
+
+/// How many applied batches the journal remembers.
+const JOURNAL_LIMIT: usize = 20;
+
+#[derive(Debug, Error)]
+pub enum RenameError {
+    #[error("Invalid pattern: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("{0} conflicting renames; resolve them first")]
+    Conflicts(usize),
+    #[error("Failed to rename {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("{0} already exists")]
+    Exists(String),
+    #[error("Invalid date format '{0}'")]
+    DateFormat(String),
+    #[error("Numbering overflows at file {0}")]
+    NumberOverflow(usize),
+    #[error("Nothing to undo")]
+    NothingToUndo,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    #[default]
+    Start,
+    End,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseChange {
+    Lower,
+    Upper,
+    /// First letter of each word upper case, the rest lower case.
+    Title,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NumberRule {
+    pub start: u64,
+    pub step: u64,
+    /// Zero-padded to at least this many digits.
+    pub width: usize,
+    pub at: Position,
+    pub separator: String,
+}
+
+impl Default for NumberRule {
+    fn default() -> Self {
+        NumberRule { start: 1, step: 1, width: 0, at: Position::Start, separator: "_".to_string() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DateRule {
+    /// A chrono format string, applied to the modification time in local time.
+    pub format: String,
+    pub at: Position,
+    pub separator: String,
+}
+
+impl Default for DateRule {
+    fn default() -> Self {
+        DateRule { format: "%Y-%m-%d".to_string(), at: Position::Start, separator: "_".to_string() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum RenameRule {
+    /// Replaces every match of `pattern`; `$1` or `${name}` in `replacement` refer to its groups.
+    Replace {
+        pattern: String,
+        replacement: String,
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// Numbers the files in the order they were given.
+    Number(NumberRule),
+    Case { case: CaseChange },
+    Date(DateRule),
+}
+
+/// Key/value settings such as `width=3,at=end` after `number:` or `date:`.
+fn settings(spec: &str) -> Result<Vec<(&str, &str)>, String> {
+    spec.split(',')
+        .filter(|setting| !setting.is_empty())
+        .map(|setting| setting.split_once('=').ok_or_else(|| format!("Expected key=value, got '{}'", setting)))
+        .collect()
+}
+
+fn parse_position(value: &str) -> Result<Position, String> {
+    match value {
+        "start" => Ok(Position::Start),
+        "end" => Ok(Position::End),
+        other => Err(format!("Unknown position '{}' (expected start or end)", other)),
+    }
+}
+
+/// Parses the command line form of a rule: `s/PATTERN/REPLACEMENT/[i]` (any delimiter), `lower`, `upper`,
+/// `title`, `number[:start=1,step=1,width=3,at=end,sep=_]` or `date[:format=%Y%m%d,at=start,sep=_]`.
+impl FromStr for RenameRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, spec) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "lower" => return Ok(RenameRule::Case { case: CaseChange::Lower }),
+            "upper" => return Ok(RenameRule::Case { case: CaseChange::Upper }),
+            "title" => return Ok(RenameRule::Case { case: CaseChange::Title }),
+            "number" => {
+                let mut rule = NumberRule::default();
+                for (key, value) in settings(spec)? {
+                    let number = |value: &str| value.parse::<u64>().map_err(|_| format!("'{}' is not a number", value));
+                    match key {
+                        "start" => rule.start = number(value)?,
+                        "step" => rule.step = number(value)?,
+                        "width" => rule.width = number(value)? as usize,
+                        "at" => rule.at = parse_position(value)?,
+                        "sep" => rule.separator = value.to_string(),
+                        other => return Err(format!("Unknown number setting '{}'", other)),
+                    }
+                }
+                return Ok(RenameRule::Number(rule));
+            }
+            "date" => {
+                let mut rule = DateRule::default();
+                for (key, value) in settings(spec)? {
+                    match key {
+                        "format" => {
+                            date_format(value).map_err(|e| e.to_string())?;
+                            rule.format = value.to_string();
+                        }
+                        "at" => rule.at = parse_position(value)?,
+                        "sep" => rule.separator = value.to_string(),
+                        other => return Err(format!("Unknown date setting '{}'", other)),
+                    }
+                }
+                return Ok(RenameRule::Date(rule));
+            }
+            _ => {}
+        }
+
+        let mut chars = s.chars();
+        let (Some('s'), Some(delimiter)) = (chars.next(), chars.next()) else {
+            return Err(format!("Unknown rule '{}' (expected s/PATTERN/REPLACEMENT/, lower, upper, title, number or date)", s));
+        };
+        // Split on the delimiter, where `\` followed by the delimiter stands for the delimiter itself.
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.clone().next() == Some(delimiter) => {
+                    parts.last_mut().unwrap().push(delimiter);
+                    chars.next();
+                }
+                c if c == delimiter => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+        match parts.as_slice() {
+            [pattern, replacement, flags] if flags.is_empty() || flags == "i" => Ok(RenameRule::Replace {
+                pattern: pattern.clone(),
+                replacement: replacement.clone(),
+                ignore_case: flags == "i",
+            }),
+            _ => Err(format!("Expected s{0}PATTERN{0}REPLACEMENT{0} with an optional i flag, got '{1}'", delimiter, s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RenameOptions {
+    /// Applied in order.
+    pub rules: Vec<RenameRule>,
+    /// Let the rules see and change the extension too, rather than just the part before it.
+    pub include_extension: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Error)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RenameConflict {
+    /// Not in the index, or read-only there.
+    #[error("{message}")]
+    Unwritable { message: String },
+    #[error("'{name}' is not a valid file name")]
+    InvalidName { name: String },
+    #[error("{other} would get the same name")]
+    Duplicate { other: String },
+    #[error("a file with the new name already exists")]
+    Exists,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlannedRename {
+    pub from: String,
+    /// Same as `from` when the rules leave the name alone.
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<RenameConflict>,
+}
+
+/// The old → new mapping a set of rules would produce, computed without touching anything.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RenamePlan {
+    pub renames: Vec<PlannedRename>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+}
+
+enum CompiledRule<'a> {
+    Replace(Regex, &'a str),
+    Date(Vec<Item<'a>>, &'a DateRule),
+    Other(&'a RenameRule),
+}
+
+/// Parses a chrono format up front: formatting with an invalid one panics.
+fn date_format(format: &str) -> Result<Vec<Item<'_>>, RenameError> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(RenameError::DateFormat(format.to_string()));
+    }
+    Ok(items)
+}
+
+fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace() || matches!(c, '_' | '-' | '.');
+    }
+    out
+}
+
+fn insert(stem: &str, addition: &str, at: Position, separator: &str) -> String {
+    match at {
+        Position::Start => format!("{}{}{}", addition, separator, stem),
+        Position::End => format!("{}{}{}", stem, separator, addition),
+    }
+}
+
+fn new_name(file: &FileMetadata, index: usize, rules: &[CompiledRule], include_extension: bool) -> Result<String, RenameError> {
+    let (stem, extension) = match file.name.rsplit_once('.') {
+        Some((stem, extension)) if !include_extension && !stem.is_empty() => (stem, Some(extension)),
+        _ => (file.name.as_str(), None),
+    };
+    let mut stem = stem.to_string();
+    for rule in rules {
+        stem = match rule {
+            CompiledRule::Replace(regex, replacement) => regex.replace_all(&stem, *replacement).into_owned(),
+            CompiledRule::Other(RenameRule::Case { case: CaseChange::Lower }) => stem.to_lowercase(),
+            CompiledRule::Other(RenameRule::Case { case: CaseChange::Upper }) => stem.to_uppercase(),
+            CompiledRule::Other(RenameRule::Case { case: CaseChange::Title }) => title_case(&stem),
+            CompiledRule::Other(RenameRule::Number(rule)) => {
+                let number = rule.step.checked_mul(index as u64).and_then(|offset| offset.checked_add(rule.start)).ok_or(RenameError::NumberOverflow(index))?;
+                insert(&stem, &format!("{:0width$}", number, width = rule.width), rule.at, &rule.separator)
+            }
+            CompiledRule::Date(items, rule) => {
+                let mut date = String::new();
+                write!(date, "{}", file.modified_time.with_timezone(&Local).format_with_items(items.iter()))
+                    .map_err(|_| RenameError::DateFormat(rule.format.clone()))?;
+                insert(&stem, &date, rule.at, &rule.separator)
+            }
+            CompiledRule::Other(RenameRule::Replace { .. } | RenameRule::Date(_)) => stem,
+        };
+    }
+    Ok(match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem,
+    })
+}
+
+/// Works out what `options` would rename each of `paths` to, in order, and flags entries that can't be
+/// renamed: read-only or unknown files, invalid names, two files ending up with one name, and names
+/// already taken by a file that isn't itself being renamed away.
+pub fn plan(files: &[FileMetadata], paths: &[String], options: &RenameOptions) -> Result<RenamePlan, RenameError> {
+    let rules = options
+        .rules
+        .iter()
+        .map(|rule| match rule {
+            RenameRule::Replace { pattern, replacement, ignore_case } => {
+                Ok(CompiledRule::Replace(RegexBuilder::new(pattern).case_insensitive(*ignore_case).build()?, replacement.as_str()))
+            }
+            RenameRule::Date(rule) => Ok(CompiledRule::Date(date_format(&rule.format)?, rule)),
+            rule => Ok(CompiledRule::Other(rule)),
+        })
+        .collect::<Result<Vec<_>, RenameError>>()?;
+
+    let wanted: HashSet<&str> = paths.iter().map(String::as_str).collect();
+    let found: HashMap<&str, &FileMetadata> = files.iter().filter(|file| wanted.contains(file.path.as_str())).map(|file| (file.path.as_str(), file)).collect();
+
+    let mut renames = paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let mut planned = PlannedRename { from: path.clone(), to: path.clone(), conflict: None };
+            let writable = found
+                .get(path.as_str())
+                .ok_or_else(|| fileops::FileOpError::NotIndexed(path.clone()))
+                .and_then(|file| fileops::check_entry_writable(file).map(|()| *file));
+            match writable {
+                Ok(file) => {
+                    let name = new_name(file, index, &rules, options.include_extension)?;
+                    if fileops::is_valid_name(&name) {
+                        planned.to = Path::new(path).with_file_name(name).to_string_lossy().into_owned();
+                    } else {
+                        planned.conflict = Some(RenameConflict::InvalidName { name });
+                    }
+                }
+                Err(e) => planned.conflict = Some(RenameConflict::Unwritable { message: e.to_string() }),
+            }
+            Ok(planned)
+        })
+        .collect::<Result<Vec<_>, RenameError>>()?;
+
+    let mut taken: HashMap<String, String> = HashMap::new();
+    for planned in renames.iter_mut().filter(|planned| planned.conflict.is_none()) {
+        if let Some(other) = taken.get(&planned.to) {
+            planned.conflict = Some(RenameConflict::Duplicate { other: other.clone() });
+        } else {
+            taken.insert(planned.to.clone(), planned.from.clone());
+        }
+    }
+
+    // A name held by a file that's being renamed away in the same batch is free by the time it's needed.
+    let moving: HashSet<String> = renames.iter().filter(|planned| planned.conflict.is_none() && planned.to != planned.from).map(|planned| planned.from.clone()).collect();
+    for planned in renames.iter_mut().filter(|planned| planned.conflict.is_none() && planned.to != planned.from) {
+        if !moving.contains(&planned.to) && fileops::ensure_vacant(Path::new(&planned.from), Path::new(&planned.to)).is_err() {
+            planned.conflict = Some(RenameConflict::Exists);
+        }
+    }
+    Ok(RenamePlan { renames })
+}
+
+impl RenamePlan {
+    pub fn conflicts(&self) -> usize {
+        self.renames.iter().filter(|planned| planned.conflict.is_some()).count()
+    }
+
+    /// The entries whose name actually changes.
+    pub fn changes(&self) -> impl Iterator<Item = &PlannedRename> {
+        self.renames.iter().filter(|planned| planned.to != planned.from)
+    }
+}
+
+fn io_error(path: &Path, source: io::Error) -> RenameError {
+    RenameError::Io { path: path.display().to_string(), source }
+}
+
+/// Puts back whatever a failed [`rename_all`] had already moved: `done` files from their new names to
+/// their temporary ones, then every temporary name to the original.
+fn roll_back(renames: &[Renamed], temps: &[PathBuf], done: usize) {
+    for (renamed, temp) in renames.iter().zip(temps).take(done).rev() {
+        if let Err(e) = fs::rename(&renamed.to, temp) {
+            warn!("Could not roll back {} -> {}: {}", renamed.to, temp.display(), e);
+        }
+    }
+    for (renamed, temp) in renames.iter().zip(temps).rev() {
+        if let Err(e) = fs::rename(temp, &renamed.from) {
+            warn!("Could not roll back {} -> {}: {}", temp.display(), renamed.from, e);
+        }
+    }
+}
+
+/// Renames everything or nothing, as far as the file system allows: each file first moves to a temporary
+/// name in its folder, which lets swaps and chains (a → b, b → c) through, and only then to its new name.
+/// If any step fails, the steps already taken are undone.
+fn rename_all(renames: &[Renamed]) -> Result<(), RenameError> {
+    let mut temps: Vec<PathBuf> = Vec::with_capacity(renames.len());
+    for (index, renamed) in renames.iter().enumerate() {
+        let from = Path::new(&renamed.from);
+        let temp = from.with_file_name(format!(".ultrasearch-rename-{}-{}", std::process::id(), index));
+        let moved = if temp.symlink_metadata().is_ok() {
+            Err(RenameError::Exists(temp.display().to_string()))
+        } else {
+            fs::rename(from, &temp).map_err(|e| io_error(from, e))
+        };
+        if let Err(e) = moved {
+            roll_back(&renames[..index], &temps, 0);
+            return Err(e);
+        }
+        temps.push(temp);
+    }
+
+    for (index, (renamed, temp)) in renames.iter().zip(&temps).enumerate() {
+        let to = Path::new(&renamed.to);
+        // Something may have taken the name since the plan was made.
+        let moved = if to.symlink_metadata().is_ok() {
+            Err(RenameError::Exists(renamed.to.clone()))
+        } else {
+            fs::rename(temp, to).map_err(|e| io_error(Path::new(&renamed.from), e))
+        };
+        if let Err(e) = moved {
+            roll_back(renames, &temps, index);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Carries out a plan that has no conflicts and returns what was renamed.
+pub fn apply(plan: &RenamePlan) -> Result<Vec<Renamed>, RenameError> {
+    let conflicts = plan.conflicts();
+    if conflicts > 0 {
+        return Err(RenameError::Conflicts(conflicts));
+    }
+    let renamed: Vec<Renamed> = plan.changes().map(|planned| Renamed { from: planned.from.clone(), to: planned.to.clone() }).collect();
+    rename_all(&renamed)?;
+    Ok(renamed)
+}
+
+/// Points index entries (and the entries inside renamed archives) at their new paths, all at once so
+/// swapped names don't get mixed up.
+pub fn update_index(files: &mut [FileMetadata], renamed: &[Renamed]) {
+    let moves: HashMap<&str, &str> = renamed.iter().map(|renamed| (renamed.from.as_str(), renamed.to.as_str())).collect();
+    for file in files.iter_mut() {
+        if let Some(to) = moves.get(file.path.as_str()) {
+            file.name = Path::new(to).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            file.path = to.to_string();
+        } else if let Some(new_path) = archive::split_virtual_path(&file.path)
+            .and_then(|(archive_path, entry)| moves.get(archive_path).map(|to| archive::virtual_path(to, entry)))
+        {
+            file.path = new_path;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RenameBatch {
+    pub applied_at: DateTime<Utc>,
+    pub renames: Vec<Renamed>,
+}
+
+/// The last few applied batches, newest last, so they can be undone in turn.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RenameJournal {
+    pub batches: Vec<RenameBatch>,
+}
+
+impl RenameJournal {
+    pub fn path_for(index_path: &Path) -> PathBuf {
+        indexer::side_file_path(index_path, "renames")
+    }
+
+    /// An empty journal until the first batch is recorded.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        indexer::load_side_file(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        indexer::save_side_file(path, self)
+    }
+
+    pub fn record(&mut self, renames: Vec<Renamed>) {
+        if renames.is_empty() {
+            return;
+        }
+        self.batches.push(RenameBatch { applied_at: Utc::now(), renames });
+        if self.batches.len() > JOURNAL_LIMIT {
+            self.batches.remove(0);
+        }
+    }
+
+    /// Renames the files of the newest batch back and drops it from the journal; returns the renames made,
+    /// for [`update_index`]. The batch stays in the journal if they can't all be put back.
+    pub fn undo_last(&mut self) -> Result<Vec<Renamed>, RenameError> {
+        let batch = self.batches.last().ok_or(RenameError::NothingToUndo)?;
+        let reverse: Vec<Renamed> = batch.renames.iter().map(|renamed| Renamed { from: renamed.to.clone(), to: renamed.from.clone() }).collect();
+        rename_all(&reverse)?;
+        self.batches.pop();
+        Ok(reverse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn entry(dir: &Path, name: &str) -> FileMetadata {
+        FileMetadata {
+            path: dir.join(name).to_string_lossy().into_owned(),
+            name: name.to_string(),
+            modified_time: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn names(plan: &RenamePlan) -> Vec<String> {
+        plan.renames.iter().map(|planned| Path::new(&planned.to).file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_rules_and_conflicts() {
+        let dir = Path::new("/photos");
+        let files = vec![entry(dir, "IMG_0001.JPG"), entry(dir, "img_0002.jpg"), entry(dir, "notes")];
+        let paths: Vec<String> = files.iter().map(|file| file.path.clone()).collect();
+        let rules: Vec<RenameRule> = ["s/^img_(\\d+)/holiday $1/i", "title", "number:width=3,at=end,sep=-", "date:format=%Y"]
+            .iter()
+            .map(|spec| spec.parse().unwrap())
+            .collect();
+        let plan = plan(&files, &paths, &RenameOptions { rules, include_extension: false }).unwrap();
+        assert_eq!(names(&plan), vec!["2024_Holiday 0001-001.JPG", "2024_Holiday 0002-002.jpg", "2024_Notes-003"]);
+        assert_eq!(plan.conflicts(), 0);
+
+        let lower = RenameOptions { rules: vec!["lower".parse().unwrap()], include_extension: true };
+        let plan = super::plan(&files, &[paths[0].clone(), paths[1].clone(), "/photos/unknown.png".to_string()], &lower).unwrap();
+        assert_eq!(plan.renames[0].to, "/photos/img_0001.jpg");
+        assert_eq!(plan.renames[1].to, plan.renames[1].from);
+        assert!(matches!(plan.renames[2].conflict, Some(RenameConflict::Unwritable { .. })));
+
+        let clash = RenameOptions { rules: vec!["s/\\d+/x/".parse().unwrap()], include_extension: true };
+        let plan = super::plan(&files, &paths[..2], &clash).unwrap();
+        assert_eq!(plan.renames[1].conflict, None, "different case, different name");
+        let slash = RenameOptions { rules: vec!["s|_|/|".parse().unwrap()], include_extension: false };
+        assert!(matches!(super::plan(&files, &paths[..1], &slash).unwrap().renames[0].conflict, Some(RenameConflict::InvalidName { .. })));
+
+        assert!("s/a/b".parse::<RenameRule>().is_err());
+        assert!("number:width=three".parse::<RenameRule>().is_err());
+        assert!("date:format=%Q".parse::<RenameRule>().is_err());
+        let bad_date = RenameOptions { rules: vec![RenameRule::Date(DateRule { format: "%Q".to_string(), ..Default::default() })], include_extension: false };
+        assert!(matches!(super::plan(&files, &paths, &bad_date), Err(RenameError::DateFormat(_))));
+        let huge_step = RenameOptions { rules: vec![RenameRule::Number(NumberRule { step: u64::MAX, ..Default::default() })], include_extension: false };
+        assert!(matches!(super::plan(&files, &paths, &huge_step), Err(RenameError::NumberOverflow(1))));
+        assert!(matches!(super::plan(&files, &paths, &RenameOptions { rules: vec!["s/(/x/".parse().unwrap()], include_extension: false }), Err(RenameError::Regex(_))));
+    }
+
+    #[test]
+    fn test_apply_swap_and_undo() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        for (name, contents) in [("a.txt", "A"), ("b.txt", "B"), ("c.txt", "C"), ("taken.txt", "T")] {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let mut files = vec![entry(dir, "a.txt"), entry(dir, "b.txt"), entry(dir, "c.txt"), entry(dir, "taken.txt")];
+        let paths: Vec<String> = files[..3].iter().map(|file| file.path.clone()).collect();
+
+        // a and b swap names; c would land on a file outside the batch.
+        let swap = RenameOptions { rules: vec!["s/^a$/tmp/".parse().unwrap(), "s/^b$/a/".parse().unwrap(), "s/^tmp$/b/".parse().unwrap(), "s/^c$/taken/".parse().unwrap()], include_extension: false };
+        let planned = plan(&files, &paths, &swap).unwrap();
+        assert_eq!(planned.renames[2].conflict, Some(RenameConflict::Exists));
+        assert!(matches!(apply(&planned), Err(RenameError::Conflicts(1))));
+
+        let planned = plan(&files, &paths[..2], &swap).unwrap();
+        let renamed = apply(&planned).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "B");
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "A");
+        update_index(&mut files, &renamed);
+        assert_eq!(files[0].path, paths[1]);
+        assert_eq!(files[1].path, paths[0]);
+
+        let journal_path = RenameJournal::path_for(&dir.join("index.bin"));
+        let mut journal = RenameJournal::load(&journal_path).unwrap();
+        journal.record(renamed);
+        journal.save(&journal_path).unwrap();
+        let mut journal = RenameJournal::load(&journal_path).unwrap();
+        let undone = journal.undo_last().unwrap();
+        update_index(&mut files, &undone);
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "A");
+        assert_eq!(files[0].path, paths[0]);
+        assert!(matches!(journal.undo_last(), Err(RenameError::NothingToUndo)));
+
+        // A rename that can't finish leaves every file where it was.
+        let blocked = vec![Renamed { from: paths[0].clone(), to: dir.join("x.txt").to_string_lossy().into_owned() }, Renamed { from: paths[1].clone(), to: dir.join("missing/y.txt").to_string_lossy().into_owned() }];
+        assert!(matches!(rename_all(&blocked), Err(RenameError::Io { .. })));
+        assert!(!dir.join("x.txt").exists());
+        assert!(fs::read_dir(dir).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().starts_with(".ultrasearch-rename")));
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "A");
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "B");
+    }
+}
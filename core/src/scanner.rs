@@ -0,0 +1,231 @@
+//! Filesystem walker that produces `FileMetadata` for every file under a root.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use chrono::{DateTime, Utc};
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+use crate::{archive, indexer::FileMetadata};
+
+#[cfg(windows)]
+use std::os::windows::fs::MetadataExt;
+
+/// Directory names (or fragments) that are never descended into: system folders, caches and build output.
+const SKIP_DIRS: &[&str] = &[
+    "Windows", "Program Files", "Program Files (x86)", "$Recycle.Bin",
+    "System Volume Information", "Recovery", "Config.Msi", "swapfile",
+    "AppData", "Application Data", "Local Settings", "Library", "/.", "/dev", "/proc", "/sys",
+    "node_modules", "target", "vendor", "venv", ".git", ".hg", ".svn",
+    "__pycache__", ".pyc", ".pyo", ".class", ".jar", ".gradle", ".m2",
+    ".cache", "cache", "Cache", "Temp", "tmp", "Downloads",
+    ".vscode", ".vscode-server", ".idea", "Pods", ".npm", ".cargo", ".rustup",
+    ".vdi", ".vmdk", ".pvm"
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub index_archives: bool,
+}
+
+#[derive(Debug)]
+pub enum ScanEvent {
+    File(FileMetadata),
+    /// A directory that couldn't be listed for a reason other than permissions or a race with deletion.
+    Unreadable { path: PathBuf, error: io::Error },
+}
+
+fn is_skipped_dir(name: &str, meta: &fs::Metadata) -> bool {
+    #[cfg(windows)]
+    let is_hidden = meta.file_attributes() & 0x2 != 0 || name.starts_with('.');
+    #[cfg(not(windows))]
+    let is_hidden = {
+        let _ = meta;
+        name.starts_with('.')
+    };
+    is_hidden || SKIP_DIRS.iter().any(|&skip| name.contains(skip) || name.eq_ignore_ascii_case(skip))
+}
+
+/// Walks `root` breadth-first and reports every file (plus archive entries when enabled).
+/// Returns the number of files reported; the walk stops early once `on_event` returns false.
+pub fn scan(root: &Path, options: &ScanOptions, mut on_event: impl FnMut(ScanEvent) -> bool) -> usize {
+    let mut file_count = 0;
+    let mut dirs_to_visit: VecDeque<PathBuf> = VecDeque::new();
+    dirs_to_visit.push_back(root.to_path_buf());
+
+    while let Some(current_dir_path) = dirs_to_visit.pop_front() {
+        if !current_dir_path.is_dir() { continue; }
+        trace!("Processing directory: {}", current_dir_path.display());
+
+        let entries = match fs::read_dir(&current_dir_path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::PermissionDenied || e.kind() == io::ErrorKind::NotFound {
+                    trace!("Skipping inaccessible directory: {} ({})", current_dir_path.display(), e.kind());
+                } else {
+                    warn!("Could not read directory: {} ({})", current_dir_path.display(), e);
+                    if !on_event(ScanEvent::Unreadable { path: current_dir_path, error: e }) {
+                        return file_count;
+                    }
+                }
+                continue;
+            }
+        };
+
+        for entry_res in entries {
+            let entry = match entry_res { Ok(e) => e, Err(e) => { debug!("Error reading dir entry in {}: {}", current_dir_path.display(), e); continue; } };
+            let current_path = entry.path();
+            let Ok(meta) = entry.metadata() else { continue };
+
+            if meta.is_file() {
+                let Some(name_osstr) = current_path.file_name() else { continue };
+                let Ok(modified_sys_time) = meta.modified() else { continue };
+                let modified_time = DateTime::from(modified_sys_time);
+                let file_info = FileMetadata {
+                    path: current_path.to_string_lossy().to_string(),
+                    name: name_osstr.to_string_lossy().to_string(),
+                    size: meta.len(),
+                    modified_time,
+                    created_time: meta.created().ok().map(DateTime::from),
+                    attributes: file_attributes(&meta),
+                    source: None,
+                    volume: None,
+                };
+                if !on_event(ScanEvent::File(file_info)) {
+                    return file_count;
+                }
+                file_count += 1;
+
+                if options.index_archives && archive::archive_kind(&current_path).is_some() {
+                    match archive_entries(&current_path, modified_time) {
+                        Ok(files) => {
+                            for file_info in files {
+                                if !on_event(ScanEvent::File(file_info)) {
+                                    return file_count;
+                                }
+                                file_count += 1;
+                            }
+                        }
+                        Err(e) => debug!("Skipping unreadable archive {}: {}", current_path.display(), e),
+                    }
+                }
+            } else if meta.is_dir() {
+                let dir_name = current_path.file_name().unwrap_or_default().to_string_lossy();
+                if is_skipped_dir(&dir_name, &meta) {
+                    trace!("Skipping directory: {}", current_path.display());
+                    continue;
+                }
+                dirs_to_visit.push_back(current_path);
+            }
+        }
+    }
+
+    file_count
+}
+
+#[cfg(windows)]
+fn file_attributes(meta: &fs::Metadata) -> Option<u32> {
+    Some(meta.file_attributes())
+}
+
+#[cfg(not(windows))]
+fn file_attributes(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// One virtual `archive!/entry` record per file inside the archive.
+fn archive_entries(archive_path: &Path, archive_modified: DateTime<Utc>) -> Result<Vec<FileMetadata>, archive::ArchiveError> {
+    let archive_str = archive_path.to_string_lossy();
+    let files: Vec<FileMetadata> = archive::list_entries(archive_path)?
+        .into_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| FileMetadata {
+            path: archive::virtual_path(&archive_str, &entry.name),
+            name: entry.name.rsplit('/').next().unwrap_or(&entry.name).to_string(),
+            size: entry.size,
+            modified_time: entry.modified.unwrap_or(archive_modified),
+            ..Default::default()
+        })
+        .collect();
+    trace!("Indexed {} entries from archive {}", files.len(), archive_path.display());
+    Ok(files)
+}
+
+/// Roots a full scan covers: every accessible drive letter on Windows, `/` elsewhere.
+pub fn available_drives() -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let mut drives = Vec::new();
+    #[cfg(target_os = "windows")]
+    {
+        for letter_byte in b'A'..=b'Z' {
+            let letter = letter_byte as char;
+            let drive_path_str = format!("{}:\\", letter);
+            let path = Path::new(&drive_path_str);
+            match std::fs::metadata(path) {
+                Ok(meta) => {
+                    if meta.is_dir() {
+                        drives.push(drive_path_str);
+                    }
+                },
+                Err(_) => { /* Drive letter likely not valid or inaccessible */ }
+            }
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        drives.push("/".to_string());
+    }
+    if drives.is_empty() {
+        Err("No drives found or accessible.".into())
+    } else {
+        Ok(drives)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan_skips_and_archives() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/nested/deep.txt"), "deep").unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "skipped").unwrap();
+        fs::write(root.join(".hidden/secret.txt"), "skipped").unwrap();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(root.join("bundle.zip")).unwrap());
+        zip.start_file("docs/readme.md", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"# readme").unwrap();
+        zip.finish().unwrap();
+
+        let collect = |options: &ScanOptions| {
+            let mut files = Vec::new();
+            let count = scan(root, options, |event| {
+                if let ScanEvent::File(file) = event {
+                    files.push(file);
+                }
+                true
+            });
+            assert_eq!(count, files.len());
+            let mut names: Vec<String> = files.into_iter().map(|file| file.name).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(collect(&ScanOptions::default()), vec!["bundle.zip", "deep.txt", "main.rs"]);
+        assert_eq!(collect(&ScanOptions { index_archives: true }), vec!["bundle.zip", "deep.txt", "main.rs", "readme.md"]);
+
+        let mut seen = 0;
+        assert_eq!(scan(root, &ScanOptions::default(), |_| { seen += 1; seen < 2 }), 1);
+    }
+}
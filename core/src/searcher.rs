@@ -0,0 +1,532 @@
+//! Streaming, parallel content search (grep) over a list of files.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+use encoding_rs::Encoding;
+use memmap2::Mmap;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use crate::archive::{self, ArchiveError};
+use crate::extractor::{ExtractError, ExtractorRegistry};
+use crate::indexer::FileMetadata;
+use crate::query::SearchResult;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContentSearchOptions {
+    pub use_regex: bool,
+    pub case_sensitive: bool,
+    pub context_before: usize,
+    pub context_after: usize,
+    pub max_hits_per_file: usize,
+    pub max_hits: usize,
+    /// Encoding label forced for every file; `None` detects per file.
+    pub encoding: Option<String>,
+    /// Files larger than this are skipped without being opened.
+    pub max_file_size: u64,
+    /// Match binary files against their raw bytes instead of skipping them.
+    pub search_binary: bool,
+}
+
+impl Default for ContentSearchOptions {
+    fn default() -> Self {
+        ContentSearchOptions {
+            use_regex: false,
+            case_sensitive: false,
+            context_before: 0,
+            context_after: 0,
+            max_hits_per_file: 100,
+            max_hits: 1000,
+            encoding: None,
+            max_file_size: 64 * 1024 * 1024,
+            search_binary: false,
+        }
+    }
+}
+
+/// Offsets of one match, relative to the start of its line (or of the highlighted text).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MatchRange {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+impl MatchRange {
+    pub fn new(text: &str, byte_start: usize, byte_end: usize) -> Self {
+        Self::with_char_start(text, byte_start, byte_end, text[..byte_start].chars().count())
+    }
+
+    /// For callers walking the matches in order, which can count the chars before each one as they go
+    /// instead of from the start of `text` every time.
+    pub fn with_char_start(text: &str, byte_start: usize, byte_end: usize, char_start: usize) -> Self {
+        MatchRange { byte_start, byte_end, char_start, char_end: char_start + text[byte_start..byte_end].chars().count() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineHit {
+    pub line_number: usize,
+    pub line: String,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+    pub ranges: Vec<MatchRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileHits {
+    pub path: PathBuf,
+    pub hits: Vec<LineHit>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ContentSearchStats {
+    pub files_searched: usize,
+    pub skipped_binary: usize,
+    pub skipped_too_large: usize,
+    pub skipped_unreadable: usize,
+}
+
+impl ContentSearchStats {
+    fn record(&mut self, outcome: &FileOutcome) {
+        match outcome {
+            FileOutcome::Searched(_) => self.files_searched += 1,
+            FileOutcome::Binary => self.skipped_binary += 1,
+            FileOutcome::TooLarge => self.skipped_too_large += 1,
+            FileOutcome::Unreadable => self.skipped_unreadable += 1,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContentSearchReport {
+    pub files: Vec<FileHits>,
+    pub stats: ContentSearchStats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub hits: usize,
+    pub stats: ContentSearchStats,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Debug)]
+pub enum FileOutcome {
+    Searched(Vec<LineHit>),
+    Binary,
+    TooLarge,
+    Unreadable,
+}
+
+/// Bytes of surrounding context shown on each side of a binary match.
+const BINARY_CONTEXT: usize = 32;
+/// Files searched in parallel between two streamed batches.
+const STREAM_CHUNK: usize = 256;
+
+#[derive(Debug)]
+pub struct ContentSearcher {
+    pattern: Regex,
+    byte_pattern: Option<regex::bytes::Regex>,
+    encoding: Option<&'static Encoding>,
+    extractors: Option<Arc<ExtractorRegistry>>,
+    options: ContentSearchOptions,
+}
+
+impl ContentSearcher {
+    pub fn new(query: &str, options: ContentSearchOptions) -> Result<Self, String> {
+        let source = if options.use_regex { query.to_string() } else { regex::escape(query) };
+        let pattern = RegexBuilder::new(&source)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid search pattern: {}", e))?;
+        let byte_pattern = if options.search_binary {
+            Some(regex::bytes::RegexBuilder::new(&source)
+                .case_insensitive(!options.case_sensitive)
+                .unicode(false)
+                .build()
+                .map_err(|e| format!("Invalid search pattern: {}", e))?)
+        } else {
+            None
+        };
+        let encoding = options.encoding.as_deref().map(crate::encoding::resolve_label).transpose()?;
+        Ok(ContentSearcher { pattern, byte_pattern, encoding, extractors: None, options })
+    }
+
+    /// Searches documents (PDF, Office, ODF...) through their extracted text.
+    pub fn with_extractors(mut self, extractors: Arc<ExtractorRegistry>) -> Self {
+        self.extractors = Some(extractors);
+        self
+    }
+
+    /// Searches `paths` in parallel, keeping input order and stopping once `max_hits` is reached.
+    pub fn search_files(&self, paths: &[PathBuf]) -> ContentSearchReport {
+        let mut files = Vec::new();
+        let progress = self.search_files_streaming(paths, &AtomicBool::new(false), |batch, _| files.extend(batch));
+        ContentSearchReport { files, stats: progress.stats }
+    }
+
+    /// Searches `paths` in chunks, handing each chunk's hits and the running progress to `on_batch`.
+    /// Setting `cancel` stops the search before the next file is opened.
+    pub fn search_files_streaming<F>(&self, paths: &[PathBuf], cancel: &AtomicBool, mut on_batch: F) -> SearchProgress
+    where
+        F: FnMut(Vec<FileHits>, &SearchProgress),
+    {
+        let mut progress = SearchProgress { total: paths.len(), ..Default::default() };
+
+        for chunk in paths.chunks(STREAM_CHUNK) {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let outcomes: Vec<Option<FileOutcome>> = chunk
+                .par_iter()
+                .map(|path| (!cancel.load(Ordering::Relaxed)).then(|| self.search_file(path)))
+                .collect();
+
+            let mut files = Vec::new();
+            for (path, outcome) in chunk.iter().zip(outcomes) {
+                let Some(outcome) = outcome else { continue };
+                progress.processed += 1;
+                progress.stats.record(&outcome);
+                let remaining = self.options.max_hits - progress.hits;
+                if let FileOutcome::Searched(mut hits) = outcome {
+                    hits.truncate(remaining);
+                    if !hits.is_empty() {
+                        progress.hits += hits.len();
+                        files.push(FileHits { path: path.clone(), hits });
+                    }
+                }
+            }
+
+            progress.cancelled = cancel.load(Ordering::Relaxed);
+            progress.done = progress.hits >= self.options.max_hits || progress.processed == progress.total;
+            on_batch(files, &progress);
+            if progress.done || progress.cancelled {
+                return progress;
+            }
+        }
+
+        progress.cancelled = cancel.load(Ordering::Relaxed);
+        progress.done = true;
+        on_batch(Vec::new(), &progress);
+        progress
+    }
+
+    pub fn search_file(&self, path: &Path) -> FileOutcome {
+        let extractors = self.extractors.as_ref().filter(|registry| registry.supports(path));
+        // An archive entry is read out first; a document inside is then extracted from those bytes.
+        if let Some((archive_path, entry)) = path.to_str().and_then(archive::split_virtual_path) {
+            let limit = extractors.and_then(|registry| registry.size_limit(path)).unwrap_or(self.options.max_file_size);
+            let bytes = match archive::read_entry(Path::new(archive_path), entry, limit) {
+                Ok(bytes) => bytes,
+                Err(ArchiveError::TooLarge { .. }) => return FileOutcome::TooLarge,
+                Err(_) => return FileOutcome::Unreadable,
+            };
+            return match extractors {
+                Some(registry) => match registry.extract_bytes(path, &bytes) {
+                    Ok(Some(text)) => FileOutcome::Searched(self.search_text(&text)),
+                    Ok(None) => FileOutcome::Unreadable,
+                    Err(ExtractError::TooLarge { .. }) => FileOutcome::TooLarge,
+                    Err(_) => FileOutcome::Unreadable,
+                },
+                None => self.search_content(&bytes),
+            };
+        }
+        if let Some(extractors) = extractors {
+            // Documents have their own per-format size limits.
+            return match extractors.extract(path) {
+                Ok(Some(text)) => FileOutcome::Searched(self.search_text(&text)),
+                Ok(None) => FileOutcome::Unreadable,
+                Err(ExtractError::TooLarge { .. }) => FileOutcome::TooLarge,
+                Err(_) => FileOutcome::Unreadable,
+            };
+        }
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.len() > self.options.max_file_size => return FileOutcome::TooLarge,
+            Ok(meta) if meta.len() == 0 => return FileOutcome::Searched(Vec::new()),
+            Ok(_) => {}
+            Err(_) => return FileOutcome::Unreadable,
+        }
+        let Ok(file) = File::open(path) else { return FileOutcome::Unreadable };
+        let Ok(mmap) = (unsafe { Mmap::map(&file) }) else { return FileOutcome::Unreadable };
+        self.search_content(&mmap)
+    }
+
+    fn search_content(&self, bytes: &[u8]) -> FileOutcome {
+        if self.encoding.is_none() && crate::encoding::looks_binary(bytes) {
+            return match &self.byte_pattern {
+                Some(byte_pattern) => FileOutcome::Searched(self.search_bytes(byte_pattern, bytes)),
+                None => FileOutcome::Binary,
+            };
+        }
+        let decoded = crate::encoding::decode(bytes, self.encoding);
+        FileOutcome::Searched(self.search_text(&decoded.text))
+    }
+
+    /// Byte-level matches in binary content. Each hit's `line` is a window around the match with
+    /// non-printable bytes shown as `.`, so byte and char offsets coincide.
+    fn search_bytes(&self, byte_pattern: &regex::bytes::Regex, bytes: &[u8]) -> Vec<LineHit> {
+        let mut hits = Vec::new();
+        let mut line_number = 1;
+        let mut counted_to = 0;
+
+        for m in byte_pattern.find_iter(bytes).filter(|m| !m.is_empty()) {
+            if hits.len() >= self.options.max_hits_per_file {
+                break;
+            }
+            line_number += bytes[counted_to..m.start()].iter().filter(|&&b| b == b'\n').count();
+            counted_to = m.start();
+
+            let window_start = m.start().saturating_sub(BINARY_CONTEXT);
+            let window_end = (m.end() + BINARY_CONTEXT).min(bytes.len());
+            let line: String = bytes[window_start..window_end]
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            let start = m.start() - window_start;
+            let end = m.end() - window_start;
+            hits.push(LineHit {
+                line_number,
+                line,
+                context_before: Vec::new(),
+                context_after: Vec::new(),
+                ranges: vec![MatchRange { byte_start: start, byte_end: end, char_start: start, char_end: end }],
+            });
+        }
+
+        hits
+    }
+
+    pub fn search_text(&self, content: &str) -> Vec<LineHit> {
+        if !self.pattern.is_match(content) {
+            return Vec::new();
+        }
+        let lines: Vec<&str> = content.lines().collect();
+        let mut hits = Vec::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            if hits.len() >= self.options.max_hits_per_file {
+                break;
+            }
+            let (mut counted_to, mut chars) = (0, 0);
+            let ranges: Vec<MatchRange> = self
+                .pattern
+                .find_iter(line)
+                .filter(|m| !m.is_empty())
+                .map(|m| {
+                    chars += line[counted_to..m.start()].chars().count();
+                    counted_to = m.start();
+                    MatchRange::with_char_start(line, m.start(), m.end(), chars)
+                })
+                .collect();
+            if ranges.is_empty() {
+                continue;
+            }
+
+            let before_start = index.saturating_sub(self.options.context_before);
+            let after_end = (index + 1 + self.options.context_after).min(lines.len());
+            hits.push(LineHit {
+                line_number: index + 1,
+                line: line.to_string(),
+                context_before: lines[before_start..index].iter().map(|l| l.to_string()).collect(),
+                context_after: lines[index + 1..after_end].iter().map(|l| l.to_string()).collect(),
+                ranges,
+            });
+        }
+
+        hits
+    }
+}
+
+/// One result per line hit, for the files `by_path` knows about.
+pub fn line_hit_results(files: Vec<FileHits>, by_path: &HashMap<&Path, &FileMetadata>) -> Vec<SearchResult> {
+    let mut results = Vec::new();
+    for file in files {
+        let Some(metadata) = by_path.get(file.path.as_path()) else { continue };
+        for hit in file.hits {
+            let matches = hit.ranges.iter().map(|range| hit.line[range.byte_start..range.byte_end].to_string()).collect();
+            results.push(SearchResult {
+                file_path: metadata.path.clone(),
+                name: metadata.name.clone(),
+                size: metadata.size,
+                modified_time: metadata.modified_time,
+                line_number: Some(hit.line_number as i32),
+                content: Some(hit.line),
+                matches: Some(matches),
+                context_before: Some(hit.context_before),
+                context_after: Some(hit.context_after),
+                match_ranges: Some(hit.ranges),
+                source: metadata.source.clone(),
+                volume: metadata.volume.clone(),
+                offline: metadata.source.is_some(),
+            });
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_content_search() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+
+        let mut file = File::create(&file_path).unwrap();
+        file.write_all(b"Hello, world! This is a test file.").unwrap();
+
+        let searcher = ContentSearcher::new("test", ContentSearchOptions::default()).unwrap();
+        assert_eq!(searcher.search_files(std::slice::from_ref(&file_path)).files[0].path, file_path);
+        let searcher = ContentSearcher::new("nonexistent", ContentSearchOptions::default()).unwrap();
+        assert!(searcher.search_files(&[file_path]).files.is_empty());
+    }
+
+    #[test]
+    fn test_line_hits_with_context_and_ranges() {
+        let options = ContentSearchOptions { context_before: 1, context_after: 1, ..Default::default() };
+        let searcher = ContentSearcher::new("needle", options).unwrap();
+        let hits = searcher.search_text("first\n中文 Needle and needle\nlast\n");
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].line_number, 2);
+        assert_eq!(hits[0].context_before, vec!["first"]);
+        assert_eq!(hits[0].context_after, vec!["last"]);
+        assert_eq!(hits[0].ranges[0], MatchRange { byte_start: 7, byte_end: 13, char_start: 3, char_end: 9 });
+        assert_eq!(hits[0].ranges.len(), 2);
+    }
+
+    #[test]
+    fn test_hit_caps() {
+        let temp_dir = tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("{}.txt", i));
+                File::create(&path).unwrap().write_all(b"x\nx\nx\n").unwrap();
+                path
+            })
+            .collect();
+
+        let options = ContentSearchOptions { max_hits_per_file: 2, max_hits: 5, ..Default::default() };
+        let report = ContentSearcher::new("x", options).unwrap().search_files(&paths);
+        let counts: Vec<usize> = report.files.iter().map(|file| file.hits.len()).collect();
+        assert_eq!(counts, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn test_search_gbk_file() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("gbk.txt");
+        let (bytes, _, _) = encoding_rs::GBK.encode("第一行\n这是一个中文测试文件\n");
+        File::create(&file_path).unwrap().write_all(&bytes).unwrap();
+
+        let searcher = ContentSearcher::new("测试", ContentSearchOptions::default()).unwrap();
+        let FileOutcome::Searched(hits) = searcher.search_file(&file_path) else { panic!("file was skipped") };
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].line_number, 2);
+    }
+
+    #[test]
+    fn test_binary_and_size_skipping() {
+        let temp_dir = tempdir().unwrap();
+        let binary = temp_dir.path().join("blob.bin");
+        let large = temp_dir.path().join("large.txt");
+        File::create(&binary).unwrap().write_all(b"\x00\x01\x02 magic needle \x00\xff").unwrap();
+        File::create(&large).unwrap().write_all(&vec![b'a'; 2048]).unwrap();
+        let paths = vec![binary.clone(), large, temp_dir.path().join("missing.txt")];
+
+        let options = ContentSearchOptions { max_file_size: 1024, ..Default::default() };
+        let report = ContentSearcher::new("needle", options.clone()).unwrap().search_files(&paths);
+        assert!(report.files.is_empty());
+        assert_eq!(report.stats, ContentSearchStats {
+            files_searched: 0, skipped_binary: 1, skipped_too_large: 1, skipped_unreadable: 1,
+        });
+
+        let options = ContentSearchOptions { search_binary: true, ..options };
+        let report = ContentSearcher::new("needle", options).unwrap().search_files(&paths);
+        assert_eq!(report.files[0].path, binary);
+        assert_eq!(report.files[0].hits[0].line, "... magic needle ..");
+        assert_eq!(report.files[0].hits[0].ranges[0].byte_start, 10);
+    }
+
+    #[test]
+    fn test_search_archive_entry() {
+        let temp_dir = tempdir().unwrap();
+        let zip_path = temp_dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.start_file("notes/todo.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"buy milk\nfix archive search\n").unwrap();
+        writer.finish().unwrap();
+
+        let entry = PathBuf::from(archive::virtual_path(zip_path.to_str().unwrap(), "notes/todo.txt"));
+        let report = ContentSearcher::new("archive", ContentSearchOptions::default()).unwrap().search_files(&[entry]);
+        assert_eq!(report.files[0].hits[0].line_number, 2);
+    }
+
+    #[test]
+    fn test_search_extracted_document() {
+        let temp_dir = tempdir().unwrap();
+        let odt = temp_dir.path().join("notes.odt");
+        let mut writer = zip::ZipWriter::new(File::create(&odt).unwrap());
+        writer.start_file("content.xml", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"<office:document-content><text:p>intro</text:p><text:p>budget figures</text:p></office:document-content>").unwrap();
+        writer.finish().unwrap();
+
+        let searcher = ContentSearcher::new("budget", ContentSearchOptions::default()).unwrap();
+        assert!(searcher.search_files(std::slice::from_ref(&odt)).files.is_empty());
+
+        let searcher = searcher.with_extractors(Arc::new(ExtractorRegistry::with_defaults()));
+        let report = searcher.search_files(std::slice::from_ref(&odt));
+        assert_eq!(report.files[0].hits[0].line_number, 2);
+        assert_eq!(report.files[0].hits[0].line, "budget figures");
+
+        // The same document packed inside a zip.
+        let zip_path = temp_dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.start_file("docs/notes.odt", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(&std::fs::read(&odt).unwrap()).unwrap();
+        writer.finish().unwrap();
+        let entry = PathBuf::from(archive::virtual_path(zip_path.to_str().unwrap(), "docs/notes.odt"));
+        let report = searcher.search_files(&[entry]);
+        assert_eq!(report.files[0].hits[0].line, "budget figures");
+    }
+
+    #[test]
+    fn test_streaming_progress_and_cancel() {
+        let temp_dir = tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..STREAM_CHUNK + 10)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("{}.txt", i));
+                File::create(&path).unwrap().write_all(b"find me\n").unwrap();
+                path
+            })
+            .collect();
+        let searcher = ContentSearcher::new("find", ContentSearchOptions::default()).unwrap();
+
+        let mut batches = Vec::new();
+        let last = searcher.search_files_streaming(&paths, &AtomicBool::new(false), |files, progress| {
+            batches.push((files.len(), progress.processed));
+        });
+        assert_eq!(batches, vec![(STREAM_CHUNK, STREAM_CHUNK), (10, STREAM_CHUNK + 10)]);
+        assert!(last.done && !last.cancelled);
+        assert_eq!(last.hits, STREAM_CHUNK + 10);
+
+        let cancel = AtomicBool::new(false);
+        let last = searcher.search_files_streaming(&paths, &cancel, |_, _| cancel.store(true, Ordering::Relaxed));
+        assert!(last.cancelled);
+        assert_eq!(last.processed, STREAM_CHUNK);
+    }
+}
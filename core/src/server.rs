@@ -0,0 +1,385 @@
+//! Local HTTP/JSON API (`/search`, `/stats`, `/preview`, `/scan`) for scripts and other tools.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Read},
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    thread::JoinHandle,
+};
+use http::{header, Method, Request, Response, StatusCode};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use crate::indexer::{self, FileMetadata};
+use crate::preview::FilePreview;
+use crate::query::{AdvancedFilterOptions, FileQuery, SearchResult, SortKey};
+use crate::scanner::ScanOptions;
+
+pub const DEFAULT_PORT: u16 = 8721;
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+const MAX_REQUEST_BODY: u64 = 64 * 1024;
+const WORKERS: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerConfig {
+    /// 0 picks a free port.
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// When set, every request must send `Authorization: Bearer <token>` or `?token=<token>`.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { port: DEFAULT_PORT, token: None }
+    }
+}
+
+/// Body of `POST /scan`; no roots means a full scan.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ScanRequest {
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    #[serde(default, flatten)]
+    pub options: ScanOptions,
+}
+
+pub type ScanHandler = dyn Fn(ScanRequest) -> Result<(), String> + Send + Sync;
+
+/// The app state the API reads from; `on_scan` starts a scan in the background and returns right away.
+pub struct ServerContext {
+    pub files: Arc<Mutex<Vec<FileMetadata>>>,
+    pub preview: Arc<FilePreview>,
+    pub scanning: Arc<AtomicBool>,
+    pub on_scan: Box<ScanHandler>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    stats: indexer::IndexStats,
+    scanning: bool,
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response<Vec<u8>> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+    response
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response<Vec<u8>> {
+    json_response(status, &serde_json::json!({ "code": code, "message": message }))
+}
+
+/// Guards against DNS rebinding: a page on some other host name must not reach the API through the browser.
+fn is_local_host(host: Option<&str>) -> bool {
+    let Some(host) = host else { return true };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// Compares without an early exit, so response times don't leak how much of the token matched.
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Handles one API request. Kept free of any socket handling so it can be tested directly.
+pub fn handle(request: &Request<Vec<u8>>, ctx: &ServerContext, config: &ServerConfig) -> Response<Vec<u8>> {
+    let header_str = |name: header::HeaderName| request.headers().get(name).and_then(|value| value.to_str().ok());
+    if !is_local_host(header_str(header::HOST)) {
+        return error_response(StatusCode::FORBIDDEN, "forbidden", "unexpected Host header");
+    }
+    // Browsers send an Origin with cross-site requests, including ones that skip the CORS preflight;
+    // local tools don't, so no web page gets to use the API.
+    if request.headers().contains_key(header::ORIGIN) {
+        return error_response(StatusCode::FORBIDDEN, "forbidden", "requests from web pages are not allowed");
+    }
+    let query: HashMap<Cow<str>, Cow<str>> =
+        url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes()).collect();
+
+    if let Some(token) = config.token.as_deref() {
+        let given = header_str(header::AUTHORIZATION)
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| query.get("token").map(|token| token.as_ref()));
+        if !given.is_some_and(|given| token_matches(token, given)) {
+            return error_response(StatusCode::UNAUTHORIZED, "unauthorized", "missing or wrong token");
+        }
+    }
+
+    match (request.method(), request.uri().path().trim_end_matches('/')) {
+        (&Method::GET, "/search") => search(&query, ctx),
+        (&Method::GET, "/stats") => stats(ctx),
+        (&Method::GET, "/preview") => preview(&query, ctx),
+        (&Method::POST, "/scan") => scan(request, ctx),
+        (_, "/search" | "/stats" | "/preview" | "/scan") => {
+            error_response(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "wrong method for this endpoint")
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "not_found", "unknown endpoint"),
+    }
+}
+
+fn search(query: &HashMap<Cow<str>, Cow<str>>, ctx: &ServerContext) -> Response<Vec<u8>> {
+    let param = |name: &str| query.get(name).map(|value| value.as_ref()).filter(|value| !value.is_empty());
+    let number = |name: &str| param(name).map(|value| value.parse::<u64>().map_err(|_| name.to_string())).transpose();
+    let (min_size, max_size, offset, limit) = match (number("min_size"), number("max_size"), number("offset"), number("limit")) {
+        (Ok(min_size), Ok(max_size), Ok(offset), Ok(limit)) => (min_size, max_size, offset, limit),
+        (Err(name), ..) | (_, Err(name), ..) | (_, _, Err(name), _) | (.., Err(name)) => {
+            return error_response(StatusCode::BAD_REQUEST, "bad_request", &format!("{} must be a non-negative integer", name))
+        }
+    };
+    let offset = offset.unwrap_or(0) as usize;
+    let limit = limit.map_or(DEFAULT_PAGE_SIZE, |limit| (limit as usize).min(MAX_PAGE_SIZE));
+
+    let filters = AdvancedFilterOptions { file_type: param("ext").map(str::to_string), min_size, max_size };
+    let mut file_query = FileQuery::new(param("q").unwrap_or_default()).with_filters(filters);
+    if let Some(sort) = param("sort") {
+        let key = match sort.parse::<SortKey>() {
+            Ok(key) => key,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, "bad_request", &e),
+        };
+        file_query = file_query.with_sort(key, param("order").is_some_and(|order| order.eq_ignore_ascii_case("desc")));
+    }
+
+    let files = match ctx.files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+    let page = file_query.page(&files, offset, limit);
+    json_response(StatusCode::OK, &SearchResponse {
+        total: page.total,
+        offset,
+        limit,
+        results: page.results.into_iter().map(|file| SearchResult::from(file.clone())).collect(),
+    })
+}
+
+fn stats(ctx: &ServerContext) -> Response<Vec<u8>> {
+    let stats = {
+        let files = match ctx.files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+        indexer::index_stats(&files)
+    };
+    json_response(StatusCode::OK, &StatsResponse { stats, scanning: ctx.scanning.load(Ordering::Relaxed) })
+}
+
+fn preview(query: &HashMap<Cow<str>, Cow<str>>, ctx: &ServerContext) -> Response<Vec<u8>> {
+    let Some(path) = query.get("path") else {
+        return error_response(StatusCode::BAD_REQUEST, "bad_request", "missing path parameter");
+    };
+    // Only indexed files: the API must not become a way to read arbitrary files.
+    let indexed = match ctx.files.lock() {
+        Ok(files) => files.iter().any(|file| file.path == *path),
+        Err(p) => p.into_inner().iter().any(|file| file.path == *path),
+    };
+    if !indexed {
+        return error_response(StatusCode::FORBIDDEN, "forbidden", "path is not indexed");
+    }
+    match ctx.preview.preview_file(Path::new(path.as_ref()), query.get("encoding").map(|value| value.as_ref())) {
+        Ok(payload) => json_response(StatusCode::OK, &payload),
+        Err(e) => json_response(crate::protocol::preview_error_status(&e), &e),
+    }
+}
+
+/// A JSON body is required even when it's empty: a browser can't send that cross-site without a preflight.
+fn is_json(request: &Request<Vec<u8>>) -> bool {
+    let content_type = request.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    content_type.split(';').next().is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn scan(request: &Request<Vec<u8>>, ctx: &ServerContext) -> Response<Vec<u8>> {
+    if !is_json(request) {
+        return error_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", "send Content-Type: application/json");
+    }
+    let body = request.body();
+    let request = if body.iter().all(u8::is_ascii_whitespace) {
+        ScanRequest::default()
+    } else {
+        match serde_json::from_slice::<ScanRequest>(body) {
+            Ok(request) => request,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, "bad_request", &e.to_string()),
+        }
+    };
+    if ctx.scanning.load(Ordering::Relaxed) {
+        return error_response(StatusCode::CONFLICT, "scan_running", "a scan is already running");
+    }
+    match (ctx.on_scan)(request) {
+        Ok(()) => json_response(StatusCode::ACCEPTED, &serde_json::json!({ "status": "started" })),
+        Err(e) => error_response(StatusCode::CONFLICT, "scan_rejected", &e),
+    }
+}
+
+/// A running server on `127.0.0.1`; stopped on drop.
+pub struct HttpServer {
+    server: Arc<tiny_http::Server>,
+    addr: SocketAddr,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for HttpServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpServer").field("addr", &self.addr).finish()
+    }
+}
+
+impl HttpServer {
+    pub fn start(config: ServerConfig, ctx: Arc<ServerContext>) -> io::Result<Self> {
+        let server = tiny_http::Server::http((Ipv4Addr::LOCALHOST, config.port)).map_err(io::Error::other)?;
+        let addr = server.server_addr().to_ip().ok_or_else(|| io::Error::other("not a TCP listener"))?;
+        let server = Arc::new(server);
+        let config = Arc::new(config);
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let (server, ctx, config) = (server.clone(), ctx.clone(), config.clone());
+                std::thread::spawn(move || {
+                    while let Ok(request) = server.recv() {
+                        serve(request, &ctx, &config);
+                    }
+                })
+            })
+            .collect();
+        debug!("HTTP API listening on {}", addr);
+        Ok(HttpServer { server, addr, workers })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        // Each call wakes one worker blocked in `recv`.
+        for _ in &self.workers {
+            self.server.unblock();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        debug!("HTTP API on {} stopped", self.addr);
+    }
+}
+
+fn serve(mut raw: tiny_http::Request, ctx: &ServerContext, config: &ServerConfig) {
+    let mut body = Vec::new();
+    if let Err(e) = raw.as_reader().take(MAX_REQUEST_BODY).read_to_end(&mut body) {
+        debug!("Failed to read request body: {}", e);
+    }
+    let mut builder = Request::builder().method(raw.method().as_str()).uri(raw.url());
+    for field in raw.headers() {
+        builder = builder.header(field.field.as_str().as_str(), field.value.as_str());
+    }
+    let response = match builder.body(body) {
+        Ok(request) => handle(&request, ctx, config),
+        Err(e) => error_response(StatusCode::BAD_REQUEST, "bad_request", &e.to_string()),
+    };
+    if !response.status().is_success() {
+        debug!("{} {} -> {}", raw.method(), raw.url(), response.status());
+    }
+
+    let (parts, body) = response.into_parts();
+    let mut reply = tiny_http::Response::from_data(body).with_status_code(parts.status.as_u16());
+    for (name, value) in &parts.headers {
+        if let Ok(field) = tiny_http::Header::from_bytes(name.as_str().as_bytes(), value.as_bytes()) {
+            reply.add_header(field);
+        }
+    }
+    if let Err(e) = raw.respond(reply) {
+        warn!("Failed to send HTTP response: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn context(files: Vec<FileMetadata>) -> ServerContext {
+        ServerContext {
+            files: Arc::new(Mutex::new(files)),
+            preview: Arc::new(FilePreview::new()),
+            scanning: Arc::new(AtomicBool::new(false)),
+            on_scan: Box::new(|request| if request.roots.is_empty() { Err("full scans are disabled".to_string()) } else { Ok(()) }),
+        }
+    }
+
+    fn get(uri: &str) -> Request<Vec<u8>> {
+        Request::get(uri).header(header::HOST, "127.0.0.1:8721").body(Vec::new()).unwrap()
+    }
+
+    fn json(response: Response<Vec<u8>>) -> serde_json::Value {
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[test]
+    fn test_search_paging_and_auth() {
+        let files = (1..=5)
+            .map(|n| FileMetadata { path: format!("/data/file{}.txt", n), name: format!("file{}.txt", n), size: n * 10, modified_time: Utc::now(), ..Default::default() })
+            .collect();
+        let ctx = context(files);
+        let config = ServerConfig { port: 0, token: Some("secret".to_string()) };
+
+        assert_eq!(handle(&get("/search?q=file"), &ctx, &config).status(), StatusCode::UNAUTHORIZED);
+        let body = json(handle(&get("/search?q=FILE&sort=size&order=desc&offset=1&limit=2&token=secret"), &ctx, &config));
+        assert_eq!(body["total"], 5);
+        assert_eq!(body["results"][0]["name"], "file4.txt");
+        assert_eq!(body["results"].as_array().unwrap().len(), 2);
+
+        let config = ServerConfig::default();
+        assert_eq!(handle(&get("/search?limit=-1"), &ctx, &config).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(handle(&get("/search?sort=speed"), &ctx, &config).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json(handle(&get("/stats"), &ctx, &config))["file_count"], 5);
+        assert_eq!(handle(&get("/preview?path=/etc/passwd"), &ctx, &config).status(), StatusCode::FORBIDDEN);
+
+        let rebound = Request::get("/stats").header(header::HOST, "evil.example:8721").body(Vec::new()).unwrap();
+        assert_eq!(handle(&rebound, &ctx, &config).status(), StatusCode::FORBIDDEN);
+
+        let scan = |body: &str| Request::post("/scan").header(header::CONTENT_TYPE, "application/json").body(body.as_bytes().to_vec()).unwrap();
+        let no_cors = Request::post("/scan").header(header::CONTENT_TYPE, "text/plain").body(b"{}".to_vec()).unwrap();
+        assert_eq!(handle(&no_cors, &ctx, &config).status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let mut from_page = scan("{}");
+        from_page.headers_mut().insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert_eq!(handle(&from_page, &ctx, &config).status(), StatusCode::FORBIDDEN);
+        assert_eq!(handle(&scan(r#"{"roots": ["/data"], "index_archives": true}"#), &ctx, &config).status(), StatusCode::ACCEPTED);
+        assert_eq!(handle(&scan(""), &ctx, &config).status(), StatusCode::CONFLICT);
+        assert_eq!(handle(&get("/scan"), &ctx, &config).status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn test_server_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, "hello over http").unwrap();
+        let file = FileMetadata { path: path.to_string_lossy().to_string(), name: "hello.txt".to_string(), size: 15, modified_time: Utc::now(), ..Default::default() };
+
+        let server = HttpServer::start(ServerConfig { port: 0, token: None }, Arc::new(context(vec![file]))).unwrap();
+        let mut stream = std::net::TcpStream::connect(server.local_addr()).unwrap();
+        let target = url::form_urlencoded::byte_serialize(path.to_string_lossy().as_bytes()).collect::<String>();
+        let request = format!("GET /preview?path={} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", target);
+        std::io::Write::write_all(&mut stream, request.as_bytes()).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+
+        assert!(reply.starts_with("HTTP/1.1 200"));
+        assert!(reply.contains("hello over http"));
+        drop(server);
+    }
+}
@@ -0,0 +1,211 @@
+//! Syntax highlighting of source previews as inline-styled HTML.
+
+use std::path::Path;
+use serde::Serialize;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{HighlightState, Theme, ThemeSet},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::{ParseState, SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Highlighting is skipped for larger chunks; the plain escaped text is returned instead.
+pub const MAX_HIGHLIGHT_SIZE: usize = 256 * 1024;
+/// Minified files put everything on one line, which makes the highlighter crawl.
+const MAX_HIGHLIGHT_LINE: usize = 16 * 1024;
+const THEME: &str = "InspiredGitHub";
+/// How many lines apart [`ParseCheckpoints`] are taken.
+pub const CHECKPOINT_LINES: usize = 512;
+
+#[derive(Debug)]
+pub struct SyntaxHighlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+/// HTML for a chunk of source, with escaped text and inline-styled `<span>`s.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct HighlightedSource {
+    pub language: Option<String>,
+    pub html: String,
+    /// False when the chunk went through the size cap or no syntax matched.
+    pub highlighted: bool,
+}
+
+/// Where the highlighter stands at the start of a line: inside a block comment, a string...
+#[derive(Debug, Clone)]
+pub struct LineState {
+    highlight: HighlightState,
+    parse: ParseState,
+}
+
+/// The [`LineState`] at every [`CHECKPOINT_LINES`]th line of one file, filled in as far as it has been parsed,
+/// so a page from the middle is highlighted as it would be from the top.
+#[derive(Debug, Clone)]
+pub struct ParseCheckpoints {
+    language: String,
+    states: Vec<LineState>,
+}
+
+impl ParseCheckpoints {
+    /// The (zero-based) line of the last checkpoint taken so far.
+    pub fn last_line(&self) -> usize {
+        (self.states.len() - 1) * CHECKPOINT_LINES
+    }
+}
+
+impl Default for SyntaxHighlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyntaxHighlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = themes.themes.remove(THEME).unwrap_or_default();
+        SyntaxHighlighter { syntaxes: SyntaxSet::load_defaults_newlines(), theme }
+    }
+
+    /// Picks a syntax by extension, then by a shebang or mode line, then by well-known file names (Makefile...).
+    fn find_syntax(&self, path: &Path, first_line: &str) -> Option<&SyntaxReference> {
+        let by_token = |token: Option<&std::ffi::OsStr>| {
+            token.and_then(|os| os.to_str()).and_then(|token| self.syntaxes.find_syntax_by_extension(token))
+        };
+        by_token(path.extension())
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(first_line))
+            .or_else(|| by_token(path.file_name()))
+            .filter(|syntax| syntax.name != "Plain Text")
+    }
+
+    /// `first_line` is the file's first line, which may lie outside `content` for ranged previews.
+    pub fn highlight(&self, path: &Path, first_line: &str, content: &str) -> HighlightedSource {
+        self.highlight_with(path, first_line, content, |syntax| Some(HighlightLines::new(syntax, &self.theme)))
+    }
+
+    /// Highlights `content` starting from `state`, the state where its first line begins.
+    pub fn highlight_at(&self, path: &Path, first_line: &str, content: &str, state: LineState) -> HighlightedSource {
+        self.highlight_with(path, first_line, content, |_| Some(HighlightLines::from_state(&self.theme, state.highlight, state.parse)))
+    }
+
+    /// `content` escaped but not highlighted, for when the state it starts in is unknown.
+    pub fn plain(&self, path: &Path, first_line: &str, content: &str) -> HighlightedSource {
+        self.highlight_with(path, first_line, content, |_| None)
+    }
+
+    fn highlight_with<'t>(
+        &'t self,
+        path: &Path,
+        first_line: &str,
+        content: &str,
+        start: impl FnOnce(&SyntaxReference) -> Option<HighlightLines<'t>>,
+    ) -> HighlightedSource {
+        let syntax = self.find_syntax(path, first_line);
+        let language = syntax.map(|syntax| syntax.name.clone());
+        let plain = || HighlightedSource { language: language.clone(), html: escape_html(content), highlighted: false };
+
+        let Some(syntax) = syntax else { return plain() };
+        if content.len() > MAX_HIGHLIGHT_SIZE || content.lines().any(|line| line.len() > MAX_HIGHLIGHT_LINE) {
+            return plain();
+        }
+
+        let Some(mut highlighter) = start(syntax) else { return plain() };
+        let mut html = String::with_capacity(content.len() * 4);
+        for line in LinesWithEndings::from(content) {
+            let styled = highlighter
+                .highlight_line(line, &self.syntaxes)
+                .ok()
+                .and_then(|regions| styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok());
+            match styled {
+                Some(styled) => html.push_str(&styled),
+                None => return plain(),
+            }
+        }
+        HighlightedSource { language, html, highlighted: true }
+    }
+
+    /// Checkpoints for a file with no lines parsed yet, or `None` when no syntax matches it.
+    pub fn checkpoints(&self, path: &Path, first_line: &str) -> Option<ParseCheckpoints> {
+        let syntax = self.find_syntax(path, first_line)?;
+        let (highlight, parse) = HighlightLines::new(syntax, &self.theme).state();
+        Some(ParseCheckpoints { language: syntax.name.clone(), states: vec![LineState { highlight, parse }] })
+    }
+
+    /// Runs `text` through the highlighter from `state` and returns where it ends up.
+    fn advance(&self, state: &LineState, text: &str) -> Option<LineState> {
+        let mut highlighter = HighlightLines::from_state(&self.theme, state.highlight.clone(), state.parse.clone());
+        for line in LinesWithEndings::from(text) {
+            if line.len() > MAX_HIGHLIGHT_LINE {
+                return None;
+            }
+            highlighter.highlight_line(line, &self.syntaxes).ok()?;
+        }
+        let (highlight, parse) = highlighter.state();
+        Some(LineState { highlight, parse })
+    }
+
+    /// Parses `text`, the [`CHECKPOINT_LINES`] lines from the last checkpoint on, and takes the next checkpoint
+    /// after them. False when it can't: the text ends the file early or has a line too long to parse.
+    pub fn extend_checkpoints(&self, checkpoints: &mut ParseCheckpoints, text: &str) -> bool {
+        if LinesWithEndings::from(text).count() < CHECKPOINT_LINES {
+            return false;
+        }
+        let Some(last) = checkpoints.states.last() else { return false };
+        match self.advance(last, text) {
+            Some(state) => {
+                checkpoints.states.push(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The state at (zero-based) `line`, from the checkpoint before it and `skipped`, the lines between the two.
+    /// `None` when that checkpoint hasn't been taken, or `checkpoints` belong to another language than `path`.
+    pub fn state_at(&self, checkpoints: &ParseCheckpoints, path: &Path, first_line: &str, line: usize, skipped: &str) -> Option<LineState> {
+        if self.find_syntax(path, first_line).map(|syntax| syntax.name.as_str()) != Some(checkpoints.language.as_str()) {
+            return None;
+        }
+        let checkpoint = checkpoints.states.get(line / CHECKPOINT_LINES)?;
+        self.advance(checkpoint, skipped)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    crate::preview::push_escaped(&mut out, text);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_by_extension_and_shebang() {
+        let highlighter = SyntaxHighlighter::new();
+
+        let rust = highlighter.highlight(Path::new("main.rs"), "fn main() {}", "fn main() { let x = \"<b>\"; }\n");
+        assert_eq!(rust.language.as_deref(), Some("Rust"));
+        assert!(rust.highlighted);
+        assert!(rust.html.contains("<span style="));
+        assert!(rust.html.contains("&lt;b&gt;"));
+
+        let script = highlighter.highlight(Path::new("deploy"), "#!/usr/bin/env python3", "print('hi')\n");
+        assert_eq!(script.language.as_deref(), Some("Python"));
+
+        let notes = highlighter.highlight(Path::new("notes"), "just <text>", "just <text>");
+        assert!(!notes.highlighted);
+        assert_eq!(notes.html, "just &lt;text&gt;");
+    }
+
+    #[test]
+    fn test_highlight_size_cap() {
+        let highlighter = SyntaxHighlighter::new();
+        let minified = format!("var a = [{}];", "1,".repeat(MAX_HIGHLIGHT_LINE));
+        let result = highlighter.highlight(Path::new("bundle.min.js"), &minified, &minified);
+        assert_eq!(result.language.as_deref(), Some("JavaScript"));
+        assert!(!result.highlighted);
+    }
+}
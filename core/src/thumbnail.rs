@@ -0,0 +1,239 @@
+//! Image thumbnails with EXIF details, cached on disk.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use base64::Engine as _;
+use chrono::NaiveDateTime;
+use exif::{Exif, In, Tag, Value};
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::preview::PreviewError;
+
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+const MAX_THUMBNAIL_SIZE: u32 = 1024;
+/// Source images are read fully into memory to decode them, so very large ones are refused.
+const MAX_SOURCE_SIZE: u64 = 100 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExifSummary {
+    pub camera: Option<String>,
+    /// `DateTimeOriginal`, formatted as `YYYY-MM-DD HH:MM:SS`.
+    pub date_taken: Option<String>,
+    pub has_gps: bool,
+}
+
+/// Everything about a thumbnail except its pixels; stored next to the cached image.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThumbnailInfo {
+    /// Dimensions of the source image, after EXIF rotation.
+    pub width: u32,
+    pub height: u32,
+    pub thumb_width: u32,
+    pub thumb_height: u32,
+    pub mime: String,
+    pub exif: Option<ExifSummary>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Thumbnail {
+    #[serde(flatten)]
+    pub info: ThumbnailInfo,
+    /// Base64 image data.
+    pub data: String,
+}
+
+pub fn is_thumbnailable(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|os| os.to_str()) else { return false };
+    ["png", "jpg", "jpeg", "webp", "gif", "bmp"].iter().any(|known| extension.eq_ignore_ascii_case(known))
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+fn exif_summary(exif: &Exif) -> ExifSummary {
+    let make = ascii_field(exif, Tag::Make);
+    let model = ascii_field(exif, Tag::Model);
+    let camera = match (make, model) {
+        // Most cameras already repeat the make in the model ("Canon EOS 5D").
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    let date_taken = ascii_field(exif, Tag::DateTimeOriginal)
+        .or_else(|| ascii_field(exif, Tag::DateTime))
+        .map(|raw| match NaiveDateTime::parse_from_str(&raw, "%Y:%m:%d %H:%M:%S") {
+            Ok(parsed) => parsed.format("%Y-%m-%d %H:%M:%S").to_string(),
+            Err(_) => raw,
+        });
+    ExifSummary { camera, date_taken, has_gps: exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some() }
+}
+
+/// Generates downscaled thumbnails and caches them on disk, keyed by path, mtime and size.
+#[derive(Debug, Clone)]
+pub struct Thumbnailer {
+    cache_dir: PathBuf,
+}
+
+impl Thumbnailer {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Thumbnailer { cache_dir }
+    }
+
+    pub fn thumbnail(&self, path: &Path, max_size: u32) -> Result<Thumbnail, PreviewError> {
+        let (info, bytes) = self.thumbnail_bytes(path, max_size)?;
+        Ok(Thumbnail { info, data: base64::engine::general_purpose::STANDARD.encode(bytes) })
+    }
+
+    /// The encoded thumbnail, from the cache when the source hasn't changed since it was made.
+    pub fn thumbnail_bytes(&self, path: &Path, max_size: u32) -> Result<(ThumbnailInfo, Vec<u8>), PreviewError> {
+        if !is_thumbnailable(path) {
+            return Err(PreviewError::NotImage(path.display().to_string()));
+        }
+        let max_size = max_size.clamp(16, MAX_THUMBNAIL_SIZE);
+
+        // Images inside archives are keyed by the archive's own mtime and size.
+        let virtual_entry = path.to_str().and_then(crate::archive::split_virtual_path);
+        let source = virtual_entry.map(|(archive, _)| Path::new(archive)).unwrap_or(path);
+        let meta = fs::metadata(source).map_err(|e| PreviewError::from_io(e, source))?;
+        if meta.is_dir() {
+            return Err(PreviewError::IsDirectory(path.display().to_string()));
+        }
+
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        meta.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).hash(&mut hasher);
+        meta.len().hash(&mut hasher);
+        max_size.hash(&mut hasher);
+        let key = format!("{:016x}", hasher.finish());
+        let image_path = self.cache_dir.join(format!("{}.img", key));
+        let info_path = self.cache_dir.join(format!("{}.json", key));
+
+        if let (Ok(bytes), Ok(info)) = (fs::read(&image_path), fs::read(&info_path)) {
+            if let Ok(info) = serde_json::from_slice::<ThumbnailInfo>(&info) {
+                return Ok((info, bytes));
+            }
+        }
+
+        let data = match virtual_entry {
+            Some((archive, entry)) => crate::archive::read_entry(Path::new(archive), entry, MAX_SOURCE_SIZE)?,
+            None if meta.len() > MAX_SOURCE_SIZE => {
+                return Err(PreviewError::TooLarge { size: meta.len(), limit: MAX_SOURCE_SIZE })
+            }
+            None => fs::read(path).map_err(|e| PreviewError::from_io(e, path))?,
+        };
+        let (info, bytes) = render(&data, max_size)?;
+
+        if let Err(e) = self.store(&image_path, &bytes, &info_path, &info) {
+            warn!("Failed to cache thumbnail for {}: {}", path.display(), e);
+        }
+        Ok((info, bytes))
+    }
+
+    fn store(&self, image_path: &Path, bytes: &[u8], info_path: &Path, info: &ThumbnailInfo) -> std::io::Result<()> {
+        fs::create_dir_all(&self.cache_dir)?;
+        // The info file is written last, so a half-written image is never picked up.
+        let partial = image_path.with_extension("partial");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, image_path)?;
+        fs::write(&partial, serde_json::to_vec(info)?)?;
+        fs::rename(&partial, info_path)
+    }
+}
+
+fn render(data: &[u8], max_size: u32) -> Result<(ThumbnailInfo, Vec<u8>), PreviewError> {
+    let mut decoder = ImageReader::new(Cursor::new(data)).with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let thumb = image.thumbnail(max_size, max_size);
+    let mut bytes = Vec::new();
+    // JPEG keeps photo thumbnails small; anything with transparency stays PNG.
+    let mime = if thumb.color().has_alpha() {
+        thumb.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+        "image/png"
+    } else {
+        DynamicImage::ImageRgb8(thumb.to_rgb8()).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg)?;
+        "image/jpeg"
+    };
+
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(data)).ok().map(|exif| exif_summary(&exif));
+    let info = ThumbnailInfo {
+        width: image.width(),
+        height: image.height(),
+        thumb_width: thumb.width(),
+        thumb_height: thumb.height(),
+        mime: mime.to_string(),
+        exif,
+    };
+    Ok((info, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::Field;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_thumbnail_and_cache() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("wide.png");
+        DynamicImage::new_rgba8(800, 400).save(&source).unwrap();
+
+        let thumbnailer = Thumbnailer::new(temp_dir.path().join("cache"));
+        let thumbnail = thumbnailer.thumbnail(&source, 200).unwrap();
+        assert_eq!((thumbnail.info.width, thumbnail.info.height), (800, 400));
+        assert_eq!((thumbnail.info.thumb_width, thumbnail.info.thumb_height), (200, 100));
+        assert_eq!(thumbnail.info.mime, "image/png");
+        assert_eq!(fs::read_dir(temp_dir.path().join("cache")).unwrap().count(), 2);
+
+        let cached = thumbnailer.thumbnail(&source, 200).unwrap();
+        assert_eq!(cached.data, thumbnail.data);
+        assert!(matches!(
+            thumbnailer.thumbnail(&temp_dir.path().join("notes.txt"), 200),
+            Err(PreviewError::NotImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_exif_summary() {
+        let ascii = |tag, value: &[u8]| Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![value.to_vec()]) };
+        let fields = [
+            ascii(Tag::Make, b"Canon"),
+            ascii(Tag::Model, b"Canon EOS 5D"),
+            ascii(Tag::DateTimeOriginal, b"2021:06:30 18:45:00"),
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![(31, 1).into(), (14, 1).into(), (0, 1).into()]),
+            },
+        ];
+        let mut writer = exif::experimental::Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+
+        let exif = exif::Reader::new().read_raw(tiff.into_inner()).unwrap();
+        let summary = exif_summary(&exif);
+        assert_eq!(summary.camera.as_deref(), Some("Canon EOS 5D"));
+        assert_eq!(summary.date_taken.as_deref(), Some("2021-06-30 18:45:00"));
+        assert!(summary.has_gps);
+    }
+}
@@ -0,0 +1,363 @@
+//! Catalog of removable and external volumes, so their files stay searchable (marked offline) while unplugged.
+//!
+//! Files on a mounted volume are tagged with its id (`FileMetadata::volume`). When the volume goes away its
+//! entries become a read-only `volume:<id>` source, which rescans leave alone; when it comes back, only the
+//! difference between the catalog and a fresh scan of the volume is applied.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::indexer::{self, FileMetadata};
+use crate::scanner::{self, ScanEvent, ScanOptions};
+
+/// `FileMetadata::source` of the entries of an unplugged volume.
+pub const OFFLINE_SOURCE_PREFIX: &str = "volume:";
+/// Where removable and external drives get mounted; other mounts are part of the system, not volumes.
+const VOLUME_MOUNT_DIRS: &[&str] = &["/media", "/run/media", "/mnt", "/Volumes"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Volume {
+    /// Filesystem UUID, or `label:<label>` for filesystems without one.
+    pub id: String,
+    pub label: Option<String>,
+    pub mount_point: PathBuf,
+}
+
+pub fn offline_source(volume_id: &str) -> String {
+    format!("{}{}", OFFLINE_SOURCE_PREFIX, volume_id)
+}
+
+/// Undoes the `\040`-style octal escapes mountinfo and udev use for spaces and other bytes.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'\\')
+            .then(|| bytes.get(i + 1..i + 4))
+            .flatten()
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| match digits.strip_prefix('x') {
+                Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                None => u8::from_str_radix(digits, 8).ok(),
+            });
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Volumes in a `/proc/self/mountinfo` listing, given which device node each UUID and label links to.
+pub fn parse_mountinfo(mountinfo: &str, uuids: &HashMap<PathBuf, String>, labels: &HashMap<PathBuf, String>) -> Vec<Volume> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let mount_point = PathBuf::from(unescape(fields.get(4)?));
+            let separator = fields.iter().position(|field| *field == "-")?;
+            let device = PathBuf::from(unescape(fields.get(separator + 2)?));
+            if !VOLUME_MOUNT_DIRS.iter().any(|dir| mount_point.starts_with(dir) && mount_point != Path::new(dir)) {
+                return None;
+            }
+            let label = labels.get(&device).cloned();
+            let id = uuids.get(&device).cloned().or_else(|| label.as_ref().map(|label| format!("label:{}", label)))?;
+            Some(Volume { id, label, mount_point })
+        })
+        .collect()
+}
+
+/// Device node each entry of a `/dev/disk/by-*` folder points at, mapped to the (unescaped) entry name.
+fn disk_links(dir: &Path) -> HashMap<PathBuf, String> {
+    let Ok(entries) = fs::read_dir(dir) else { return HashMap::new() };
+    entries
+        .flatten()
+        .filter_map(|entry| Some((fs::canonicalize(entry.path()).ok()?, unescape(&entry.file_name().to_string_lossy()))))
+        .collect()
+}
+
+/// Removable and external volumes mounted right now. Linux reads mountinfo and udev's `/dev/disk`
+/// links; macOS lists `/Volumes`, identifying volumes by name. Elsewhere this is empty.
+pub fn mounted_volumes() -> Vec<Volume> {
+    if cfg!(target_os = "linux") {
+        let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") else { return Vec::new() };
+        return parse_mountinfo(&mountinfo, &disk_links(Path::new("/dev/disk/by-uuid")), &disk_links(Path::new("/dev/disk/by-label")));
+    }
+    if cfg!(target_os = "macos") {
+        let Ok(entries) = fs::read_dir("/Volumes") else { return Vec::new() };
+        return entries
+            .flatten()
+            // The boot volume shows up here as a symlink to `/`.
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .map(|entry| {
+                let label = entry.file_name().to_string_lossy().into_owned();
+                Volume { id: format!("label:{}", label), label: Some(label), mount_point: entry.path() }
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+/// Tags files that live on one of the `mounted` volumes with its id, and untags those that don't
+/// (after being moved off one, say). Offline entries keep theirs.
+pub fn tag(files: &mut [FileMetadata], mounted: &[Volume]) {
+    for file in files.iter_mut().filter(|file| file.source.is_none()) {
+        let path = Path::new(&file.path);
+        file.volume = mounted.iter().find(|volume| path.starts_with(&volume.mount_point)).map(|volume| volume.id.clone());
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CatalogVolume {
+    #[serde(flatten)]
+    pub volume: Volume,
+    pub online: bool,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VolumeDiff {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VolumeChange {
+    /// First time this volume was seen mounted.
+    Added { id: String },
+    /// Unplugged; its `files` entries are now offline.
+    Offline { id: String, files: usize },
+    /// Plugged back in and rescanned.
+    Resynced { id: String, diff: VolumeDiff },
+}
+
+/// The volumes seen so far; saved next to the index, whose entries hold the files themselves.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct VolumeCatalog {
+    pub volumes: Vec<CatalogVolume>,
+}
+
+fn rebase(path: &str, from: &Path, to: &Path) -> Option<String> {
+    let relative = Path::new(path).strip_prefix(from).ok()?;
+    Some(to.join(relative).to_string_lossy().into_owned())
+}
+
+/// Replaces the entries of volume `id` with `scanned` by changing only what differs, and brings them back online.
+fn apply_diff(files: &mut Vec<FileMetadata>, id: &str, scanned: Vec<FileMetadata>) -> VolumeDiff {
+    let mut diff = VolumeDiff::default();
+    let mut by_path: HashMap<String, usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, file)| file.volume.as_deref() == Some(id))
+        .map(|(index, file)| (file.path.clone(), index))
+        .collect();
+    let mut seen = HashSet::with_capacity(scanned.len());
+    for file in scanned {
+        seen.insert(file.path.clone());
+        match by_path.get(&file.path) {
+            Some(&index) => {
+                let existing = &mut files[index];
+                if existing.size != file.size || existing.modified_time != file.modified_time {
+                    *existing = file;
+                    diff.changed += 1;
+                } else {
+                    existing.source = None;
+                    diff.unchanged += 1;
+                }
+            }
+            None => {
+                by_path.insert(file.path.clone(), files.len());
+                files.push(file);
+                diff.added += 1;
+            }
+        }
+    }
+    let before = files.len();
+    files.retain(|file| file.volume.as_deref() != Some(id) || seen.contains(&file.path));
+    diff.removed = before - files.len();
+    diff
+}
+
+impl VolumeCatalog {
+    pub fn path_for(index_path: &Path) -> PathBuf {
+        indexer::side_file_path(index_path, "volumes")
+    }
+
+    /// Empty until the first volume is seen.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        indexer::load_side_file(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        indexer::save_side_file(path, self)
+    }
+
+    /// Brings the catalog and `files` in line with the `mounted` volumes: unplugged volumes go offline,
+    /// reattached ones are rescanned (at their new mount point, if it moved) and only the diff applied,
+    /// and new ones are added. Files of online volumes are tagged along the way. `files` is locked only
+    /// to apply each change, never while a volume is being rescanned.
+    pub fn refresh(&mut self, files: &Mutex<Vec<FileMetadata>>, mounted: &[Volume], options: &ScanOptions) -> Vec<VolumeChange> {
+        let lock = || match files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+        let mut changes = Vec::new();
+        let now = Utc::now();
+
+        {
+            let mut files = lock();
+            for known in self.volumes.iter_mut().filter(|known| known.online) {
+                if mounted.iter().any(|volume| volume.id == known.volume.id) {
+                    continue;
+                }
+                known.online = false;
+                let source = offline_source(&known.volume.id);
+                let mut count = 0;
+                for file in files.iter_mut().filter(|file| file.volume.as_deref() == Some(known.volume.id.as_str())) {
+                    file.source = Some(source.clone());
+                    count += 1;
+                }
+                changes.push(VolumeChange::Offline { id: known.volume.id.clone(), files: count });
+            }
+
+            for volume in mounted {
+                let Some(known) = self.volumes.iter().find(|known| known.volume.id == volume.id) else { continue };
+                if known.volume.mount_point != volume.mount_point {
+                    for file in files.iter_mut().filter(|file| file.volume.as_deref() == Some(volume.id.as_str())) {
+                        if let Some(path) = rebase(&file.path, &known.volume.mount_point, &volume.mount_point) {
+                            file.path = path;
+                        }
+                    }
+                }
+            }
+        }
+
+        for volume in mounted {
+            let Some(known) = self.volumes.iter_mut().find(|known| known.volume.id == volume.id) else {
+                self.volumes.push(CatalogVolume { volume: volume.clone(), online: true, last_seen: now });
+                changes.push(VolumeChange::Added { id: volume.id.clone() });
+                continue;
+            };
+            if !known.online {
+                let mut scanned = Vec::new();
+                scanner::scan(&volume.mount_point, options, |event| {
+                    if let ScanEvent::File(file) = event {
+                        scanned.push(file);
+                    }
+                    true
+                });
+                tag(&mut scanned, std::slice::from_ref(volume));
+                let mut files = lock();
+                // A rescan while the volume was unplugged may have picked up something else mounted there.
+                files.retain(|file| file.source.is_some() || file.volume.is_some() || !Path::new(&file.path).starts_with(&volume.mount_point));
+                let diff = apply_diff(&mut files, &volume.id, scanned);
+                changes.push(VolumeChange::Resynced { id: volume.id.clone(), diff });
+            }
+            known.volume = volume.clone();
+            known.online = true;
+            known.last_seen = now;
+        }
+
+        // Files indexed before their volume was known.
+        tag(&mut lock(), mounted);
+        changes
+    }
+
+    /// Drops a volume from the catalog along with every entry it had in `files`.
+    pub fn forget(&mut self, files: &mut Vec<FileMetadata>, id: &str) -> bool {
+        let before = self.volumes.len();
+        self.volumes.retain(|known| known.volume.id != id);
+        files.retain(|file| file.volume.as_deref() != Some(id));
+        self.volumes.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mountinfo = "\
+28 1 8:2 / / rw,relatime - ext4 /dev/sda2 rw
+97 28 8:17 / /media/me/My\\040Stick rw,nosuid - vfat /dev/sdb1 rw
+98 28 8:33 / /run/media/me/backup rw,nosuid - ext4 /dev/sdc1 rw
+99 28 0:50 / /mnt/share rw - nfs server:/share rw
+";
+        let uuids = HashMap::from([(PathBuf::from("/dev/sda2"), "root-uuid".to_string()), (PathBuf::from("/dev/sdb1"), "1A2B-3C4D".to_string())]);
+        let labels = HashMap::from([(PathBuf::from("/dev/sdb1"), "My Stick".to_string()), (PathBuf::from("/dev/sdc1"), "backup".to_string())]);
+
+        assert_eq!(
+            parse_mountinfo(mountinfo, &uuids, &labels),
+            vec![
+                Volume { id: "1A2B-3C4D".to_string(), label: Some("My Stick".to_string()), mount_point: PathBuf::from("/media/me/My Stick") },
+                Volume { id: "label:backup".to_string(), label: Some("backup".to_string()), mount_point: PathBuf::from("/run/media/me/backup") },
+            ]
+        );
+        assert_eq!(unescape("a\\x20b\\134c"), "a b\\c");
+    }
+
+    #[test]
+    fn test_offline_and_resync() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mount = temp_dir.path().join("usb");
+        fs::create_dir(&mount).unwrap();
+        for name in ["keep.txt", "edit.txt", "gone.txt"] {
+            fs::write(mount.join(name), name).unwrap();
+        }
+        let volume = Volume { id: "1A2B-3C4D".to_string(), label: Some("USB".to_string()), mount_point: mount.clone() };
+        let mut scanned: Vec<FileMetadata> = Vec::new();
+        scanner::scan(&mount, &ScanOptions::default(), |event| {
+            if let ScanEvent::File(file) = event {
+                scanned.push(file);
+            }
+            true
+        });
+        let files = Mutex::new(scanned);
+        let lock = || files.lock().unwrap();
+
+        let mut catalog = VolumeCatalog::default();
+        assert_eq!(catalog.refresh(&files, std::slice::from_ref(&volume), &ScanOptions::default()), vec![VolumeChange::Added { id: volume.id.clone() }]);
+        assert!(lock().iter().all(|file| file.volume.as_deref() == Some("1A2B-3C4D")));
+
+        assert_eq!(catalog.refresh(&files, &[], &ScanOptions::default()), vec![VolumeChange::Offline { id: volume.id.clone(), files: 3 }]);
+        assert!(lock().iter().all(|file| file.source.as_deref() == Some("volume:1A2B-3C4D")));
+        crate::indexer::replace_roots(&mut lock(), &[temp_dir.path().to_path_buf()], Vec::new());
+        assert_eq!(lock().len(), 3, "offline entries survive rescans");
+
+        // Reattached somewhere else, with one file added, one edited and one deleted meanwhile.
+        let remount = temp_dir.path().join("usb2");
+        fs::rename(&mount, &remount).unwrap();
+        fs::write(remount.join("new.txt"), "new").unwrap();
+        fs::write(remount.join("edit.txt"), "edited contents").unwrap();
+        fs::remove_file(remount.join("gone.txt")).unwrap();
+        let moved = Volume { mount_point: remount.clone(), ..volume.clone() };
+        let changes = catalog.refresh(&files, std::slice::from_ref(&moved), &ScanOptions::default());
+
+        assert_eq!(changes, vec![VolumeChange::Resynced { id: volume.id.clone(), diff: VolumeDiff { added: 1, removed: 1, changed: 1, unchanged: 1 } }]);
+        let mut files = files.into_inner().unwrap();
+        let mut names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["edit.txt", "keep.txt", "new.txt"]);
+        assert!(files.iter().all(|file| file.source.is_none() && Path::new(&file.path).starts_with(&remount)));
+        assert_eq!(catalog.volumes[0].volume.mount_point, remount);
+
+        let catalog_path = VolumeCatalog::path_for(&temp_dir.path().join("index.bin"));
+        catalog.save(&catalog_path).unwrap();
+        assert_eq!(VolumeCatalog::load(&catalog_path).unwrap(), catalog);
+        assert!(catalog.forget(&mut files, "1A2B-3C4D"));
+        assert!(files.is_empty());
+    }
+}
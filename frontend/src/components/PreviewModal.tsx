@@ -1,9 +1,95 @@
 import React, { useState, useEffect } from 'react';
-import { Modal, Typography, Spin } from 'antd';
+import { Modal, Typography, Spin, Table } from 'antd';
 import { invoke } from '@tauri-apps/api/tauri';
 
 const { Text } = Typography;
 
+interface PreviewPayload {
+    kind: 'text' | 'image' | 'binary' | 'archive' | 'directory' | 'unsupported';
+    content?: string;
+    data?: string;
+    mime?: string;
+    encoding?: string;
+    size: number;
+    truncated: boolean;
+    lossy: boolean;
+    archive?: {
+        entries: { name: string; size: number; compressed_size?: number; modified?: number; is_dir: boolean }[];
+        entry_count: number;
+        total_size: number;
+        total_compressed_size?: number;
+        truncated: boolean;
+    };
+    directory?: {
+        file_count: number;
+        dir_count: number;
+        total_size: number;
+        children: DirectoryChild[];
+        largest: DirectoryChild[];
+        truncated: boolean;
+    };
+}
+
+interface DirectoryChild {
+    name: string;
+    is_dir: boolean;
+    size: number;
+    file_count: number;
+    modified: number;
+}
+
+// Media is streamed through the backend's ultrasearch:// protocol instead of being sent over invoke.
+const protocolUrl = (endpoint: string, params: Record<string, string | number>) => {
+    const query = new URLSearchParams(Object.entries(params).map(([key, value]) => [key, String(value)]));
+    const base = navigator.userAgent.includes('Windows') ? 'http://ultrasearch.localhost' : 'ultrasearch://localhost';
+    return `${base}/${endpoint}?${query.toString()}`;
+};
+
+const VIDEO_EXTENSIONS = ['mp4', 'm4v', 'webm', 'mov', 'mkv'];
+const AUDIO_EXTENSIONS = ['mp3', 'm4a', 'aac', 'wav', 'ogg', 'flac'];
+
+const formatTime = (seconds?: number) => (seconds ? new Date(seconds * 1000).toLocaleString() : '');
+
+interface HexPreview {
+    file_type?: { mime: string; description: string };
+    size: number;
+    offset: number;
+    length: number;
+    rows: { offset: number; hex: string; ascii: string }[];
+    matches: { start: number; end: number }[];
+    has_more: boolean;
+}
+
+interface Thumbnail {
+    width: number;
+    height: number;
+    thumb_width: number;
+    thumb_height: number;
+    mime: string;
+    exif?: { camera?: string; date_taken?: string; has_gps: boolean };
+    data: string;
+}
+
+interface SourcePreview {
+    // Escaped, inline-styled HTML from the backend highlighter.
+    html: string;
+    language?: string;
+    highlighted: boolean;
+    start_line?: number;
+    total_lines?: number;
+    has_more: boolean;
+}
+
+const HEX_PAGE = 4096;
+const SOURCE_PAGE_LINES = 2000;
+const THUMBNAIL_SIZE = 512;
+
+interface Highlight {
+    // Already HTML-escaped by the backend; only <mark> tags are added.
+    html: string;
+    ranges: { byte_start: number; byte_end: number; char_start: number; char_end: number }[];
+}
+
 interface PreviewModalProps {
     visible: boolean;
     filePath: string;
@@ -20,19 +106,64 @@ const PreviewModal: React.FC<PreviewModalProps> = ({
     const [content, setContent] = useState<string>('');
     const [loading, setLoading] = useState(false);
     const [highlightedContent, setHighlightedContent] = useState<string>('');
+    const [hex, setHex] = useState<HexPreview | null>(null);
+    const [thumbnail, setThumbnail] = useState<Thumbnail | null>(null);
+    const [source, setSource] = useState<SourcePreview | null>(null);
+    const [payload, setPayload] = useState<PreviewPayload | null>(null);
+
+    const loadSource = (startLine: number) => {
+        invoke<SourcePreview>('preview_source', {
+            path: filePath,
+            range: { start_line: startLine, line_count: SOURCE_PAGE_LINES },
+        })
+            .then(setSource)
+            .catch((error) => console.error('Error loading source preview:', error));
+    };
+
+    const loadHex = (offset: number) => {
+        invoke<HexPreview>('preview_hex', {
+            path: filePath,
+            offset,
+            length: HEX_PAGE,
+            pattern: searchQuery ?? null,
+        })
+            .then(setHex)
+            .catch((error) => console.error('Error loading hex preview:', error));
+    };
+
+    const isMatched = (offset: number) =>
+        hex?.matches.some((m) => offset >= m.start && offset < m.end) ?? false;
 
     useEffect(() => {
         if (visible && filePath) {
             setLoading(true);
-            invoke('preview_file', { path: filePath })
-                .then((result: string) => {
-                    setContent(result);
-                    if (searchQuery) {
-                        invoke('highlight_content', {
-                            content: result,
+            setHighlightedContent('');
+            setHex(null);
+            setThumbnail(null);
+            setSource(null);
+            setPayload(null);
+            invoke<PreviewPayload>('preview_file', { path: filePath })
+                .then((result) => {
+                    const text = result.content ?? '';
+                    setContent(text);
+                    setPayload(result);
+                    if (result.kind === 'binary') {
+                        loadHex(0);
+                    }
+                    if (result.kind === 'image') {
+                        invoke<Thumbnail>('thumbnail', { path: filePath, maxSize: THUMBNAIL_SIZE })
+                            .then(setThumbnail)
+                            .catch((error) => console.error('Error loading thumbnail:', error));
+                    }
+                    if (!searchQuery && result.kind === 'text') {
+                        loadSource(1);
+                    }
+                    if (searchQuery && result.kind === 'text') {
+                        invoke<Highlight>('highlight_content', {
+                            content: text,
                             query: searchQuery,
-                        }).then((highlighted: string) => {
-                            setHighlightedContent(highlighted);
+                        }).then((highlighted) => {
+                            setHighlightedContent(highlighted.html);
                         });
                     }
                 })
@@ -60,7 +191,112 @@ const PreviewModal: React.FC<PreviewModalProps> = ({
                     </div>
                 ) : (
                     <div className="whitespace-pre-wrap font-mono text-sm">
-                        {searchQuery && highlightedContent ? (
+                        {payload?.kind === 'unsupported' &&
+                        VIDEO_EXTENSIONS.includes(filePath.split('.').pop()?.toLowerCase() ?? '') ? (
+                            <video controls width="100%" src={protocolUrl('file', { path: filePath })} />
+                        ) : payload?.kind === 'unsupported' &&
+                          AUDIO_EXTENSIONS.includes(filePath.split('.').pop()?.toLowerCase() ?? '') ? (
+                            <audio controls src={protocolUrl('file', { path: filePath })} />
+                        ) : payload?.archive ? (
+                            <div>
+                                <Text type="secondary">
+                                    {`${payload.archive.entry_count} 个条目 · 解压后 ${payload.archive.total_size} 字节`}
+                                    {payload.archive.total_compressed_size !== undefined &&
+                                        ` · 压缩后 ${payload.archive.total_compressed_size} 字节`}
+                                </Text>
+                                <Table
+                                    size="small"
+                                    rowKey="name"
+                                    pagination={{ pageSize: 50 }}
+                                    dataSource={payload.archive.entries}
+                                    columns={[
+                                        { title: '名称', dataIndex: 'name' },
+                                        { title: '大小', dataIndex: 'size' },
+                                        { title: '压缩后', dataIndex: 'compressed_size' },
+                                        { title: '修改时间', dataIndex: 'modified', render: formatTime },
+                                    ]}
+                                />
+                            </div>
+                        ) : payload?.directory ? (
+                            <div>
+                                <Text type="secondary">
+                                    {`${payload.directory.file_count} 个文件 · ${payload.directory.dir_count} 个子文件夹 · ${payload.directory.total_size} 字节`}
+                                </Text>
+                                <div>
+                                    <Text strong>最大的项目：</Text>
+                                    {payload.directory.largest.map((child) => `${child.name} (${child.size})`).join('，')}
+                                </div>
+                                <Table
+                                    size="small"
+                                    rowKey="name"
+                                    pagination={{ pageSize: 50 }}
+                                    dataSource={payload.directory.children}
+                                    columns={[
+                                        { title: '名称', dataIndex: 'name', render: (name: string, child: DirectoryChild) => (child.is_dir ? `${name}/` : name) },
+                                        { title: '大小', dataIndex: 'size' },
+                                        { title: '文件数', dataIndex: 'file_count' },
+                                        { title: '修改时间', dataIndex: 'modified', render: formatTime },
+                                    ]}
+                                />
+                            </div>
+                        ) : source ? (
+                            <div>
+                                {source.language && <Text type="secondary">{source.language}</Text>}
+                                <div dangerouslySetInnerHTML={{ __html: source.html }} />
+                                <div className="flex gap-4">
+                                    {(source.start_line ?? 1) > 1 && (
+                                        <a onClick={() => loadSource(Math.max(1, (source.start_line ?? 1) - SOURCE_PAGE_LINES))}>
+                                            上一页
+                                        </a>
+                                    )}
+                                    {source.has_more && (
+                                        <a onClick={() => loadSource((source.start_line ?? 1) + SOURCE_PAGE_LINES)}>下一页</a>
+                                    )}
+                                </div>
+                            </div>
+                        ) : thumbnail ? (
+                            <div>
+                                <img
+                                    src={`data:${thumbnail.mime};base64,${thumbnail.data}`}
+                                    width={thumbnail.thumb_width}
+                                    height={thumbnail.thumb_height}
+                                />
+                                <Text type="secondary">
+                                    {`${thumbnail.width} × ${thumbnail.height}`}
+                                    {thumbnail.exif?.camera && ` · ${thumbnail.exif.camera}`}
+                                    {thumbnail.exif?.date_taken && ` · 拍摄于 ${thumbnail.exif.date_taken}`}
+                                    {thumbnail.exif?.has_gps && ' · 含 GPS 位置'}
+                                </Text>
+                            </div>
+                        ) : hex ? (
+                            <div>
+                                <Text type="secondary">
+                                    {hex.file_type ? `${hex.file_type.description} (${hex.file_type.mime})` : '未知类型'}
+                                    {` · ${hex.size} 字节`}
+                                </Text>
+                                {hex.rows.map((row) => (
+                                    <div key={row.offset}>
+                                        <span>{row.offset.toString(16).padStart(8, '0')}  </span>
+                                        {row.hex.split(' ').map((byte, i) =>
+                                            byte === '' ? (
+                                                <span key={i}> </span>
+                                            ) : (
+                                                <span key={i}>
+                                                    {isMatched(row.offset + (i > 8 ? i - 1 : i)) ? <mark>{byte}</mark> : byte}{' '}
+                                                </span>
+                                            )
+                                        )}
+                                        <span> |{row.ascii}|</span>
+                                    </div>
+                                ))}
+                                <div className="flex gap-4">
+                                    {hex.offset > 0 && (
+                                        <a onClick={() => loadHex(Math.max(0, hex.offset - HEX_PAGE))}>上一页</a>
+                                    )}
+                                    {hex.has_more && <a onClick={() => loadHex(hex.offset + hex.length)}>下一页</a>}
+                                </div>
+                            </div>
+                        ) : searchQuery && highlightedContent ? (
                             <div
                                 dangerouslySetInnerHTML={{
                                     __html: highlightedContent,
@@ -1,55 +1,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod preview;
-
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    error::Error,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex, RwLock},
     time::{Duration, Instant},
 };
 use tauri::{self, Emitter, State, Manager as _, AppHandle};
-use preview::FilePreview;
+use ultrasearch_core::{content_index, indexer, protocol, scanner};
+use ultrasearch_core::indexer::FileMetadata;
+use ultrasearch_core::query::{AdvancedFilterOptions, FileQuery, SearchResult, SortKey};
+use ultrasearch_core::efu;
+use ultrasearch_core::export::{self, ExportFormat, ExportOptions};
+use ultrasearch_core::scanner::{ScanEvent, ScanOptions};
+use ultrasearch_core::server::{HttpServer, ScanRequest, ServerConfig, ServerContext};
+use ultrasearch_core::volumes::{self, CatalogVolume, Volume, VolumeCatalog};
+use ultrasearch_core::desktop;
+use ultrasearch_core::fileops::{self, FileOpResult};
+use ultrasearch_core::rename::{self, RenameJournal, RenameOptions, RenamePlan, Renamed};
+#[cfg(unix)]
+use ultrasearch_core::ipc::{self, IpcContext, IpcServer, OpenAction};
+use ultrasearch_core::preview::{FilePreview, HexPreview, Highlight, HighlightOptions, IndexedFile, PreviewError, PreviewPayload, PreviewRange, RangePreview, SourcePreview};
+use ultrasearch_core::content_index::{ContentIndex, ContentIndexConfig};
+use ultrasearch_core::extractor::ExtractorRegistry;
+use ultrasearch_core::thumbnail::{Thumbnail, Thumbnailer, DEFAULT_THUMBNAIL_SIZE};
+use ultrasearch_core::searcher::{line_hit_results, ContentSearchOptions, ContentSearchStats, ContentSearcher, SearchProgress};
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
-use chrono;
 use log::{info, warn, error, debug, trace};
 use tokio::sync::mpsc;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct FileMetadata {
-    path: String,
-    name: String,
-    size: u64,
-    #[serde(with = "chrono::serde::ts_seconds")]
-    modified_time: chrono::DateTime<chrono::Utc>,
+pub struct ContentSearchResponse {
+    results: Vec<SearchResult>,
+    stats: ContentSearchStats,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SearchResult {
-    file_path: String,
-    name: String,
-    size: u64,
-    #[serde(with = "chrono::serde::ts_seconds")]
-    modified_time: chrono::DateTime<chrono::Utc>,
-    line_number: Option<i32>,
-    content: Option<String>,
-    matches: Option<Vec<String>>,
-}
-
-impl From<FileMetadata> for SearchResult {
-    fn from(metadata: FileMetadata) -> Self {
-        SearchResult {
-            file_path: metadata.path.clone(),
-            name: metadata.name.clone(),
-            size: metadata.size,
-            modified_time: metadata.modified_time,
-            line_number: None,
-            content: None,
-            matches: None,
-        }
-    }
+pub struct ContentSearchHits {
+    search_id: u64,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContentSearchProgress {
+    search_id: u64,
+    #[serde(flatten)]
+    progress: SearchProgress,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -69,29 +66,102 @@ pub struct ScanProgress {
     files_collected_so_far: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AdvancedFilterOptions {
-    file_type: Option<String>,
-    min_size: Option<u64>,
-    max_size: Option<u64>,
-}
-
 #[derive(Debug)]
 struct AppState {
     files: Arc<Mutex<Vec<FileMetadata>>>,
+    indexed_paths: Arc<IndexedPaths>,
     file_preview: Arc<FilePreview>,
+    extractors: Arc<ExtractorRegistry>,
+    content_index: Arc<Mutex<Option<ContentIndex>>>,
+    content_watcher: Mutex<Option<notify::RecommendedWatcher>>,
+    next_search_id: AtomicU64,
+    active_content_search: Mutex<Option<(u64, Arc<AtomicBool>)>>,
+    /// Canonical folders the `ultrasearch://` protocol may serve from, in addition to indexed files.
+    allowed_roots: Mutex<Vec<PathBuf>>,
+    scan_running: Arc<AtomicBool>,
+    /// The running HTTP API and the config it was started with, to bring it back if a restart fails.
+    http_server: Mutex<Option<(HttpServer, ServerConfig)>>,
+    volume_catalog: Arc<Mutex<VolumeCatalog>>,
+    /// Held by a volume re-sync and by scans, so a scan waits for a re-sync to finish instead of failing.
+    volume_sync: Arc<tokio::sync::Mutex<()>>,
 }
 
-#[tauri::command]
-async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
-    let files_arc = state.files.clone();
-    let handle = app_handle;
-    let scan_start_time = Instant::now();
+/// The paths in the file list, kept beside it so checking whether a path is indexed doesn't walk the whole list.
+#[derive(Debug, Default)]
+struct IndexedPaths(RwLock<HashSet<String>>);
+
+impl IndexedPaths {
+    /// Call after every change to the file list.
+    fn rebuild(&self, files: &[FileMetadata]) {
+        let paths = files.iter().map(|file| file.path.clone()).collect();
+        *match self.0.write() { Ok(guard) => guard, Err(p) => p.into_inner() } = paths;
+    }
 
+    fn contains(&self, path: &str) -> bool {
+        match self.0.read() {
+            Ok(paths) => paths.contains(path),
+            Err(p) => p.into_inner().contains(path),
+        }
+    }
+}
+
+/// Holds `scan_running` for a change to the file list that a scan must not overwrite, releasing it when dropped.
+struct ScanClaim(Arc<AtomicBool>);
+
+impl ScanClaim {
+    /// `None` while a scan, or another change claimed this way, is running.
+    fn claim(scan_running: &Arc<AtomicBool>) -> Option<Self> {
+        (!scan_running.swap(true, Ordering::SeqCst)).then(|| ScanClaim(scan_running.clone()))
+    }
+}
+
+impl Drop for ScanClaim {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// How often the mounted volumes are checked for drives that were plugged in or removed.
+const VOLUME_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Indexed files (including archive entries) and anything under an allowed root may be served.
+fn is_servable(state: &AppState, path: &Path) -> bool {
+    if path.to_str().is_some_and(|path| state.indexed_paths.contains(path)) {
+        return true;
+    }
+    // Canonicalizing resolves `..` and symlinks, so neither can be used to escape a root.
+    let Ok(canonical) = path.canonicalize() else { return false };
+    match state.allowed_roots.lock() {
+        Ok(roots) => roots.iter().any(|root| canonical.starts_with(root)),
+        Err(_) => false,
+    }
+}
+
+#[tauri::command]
+async fn scan_directory(app_handle: AppHandle, options: Option<ScanOptions>, state: State<'_, AppState>) -> Result<(), String> {
     info!("Scan directory command received. Spawning parallel background tasks.");
+    start_scan(app_handle, state.files.clone(), state.indexed_paths.clone(), state.scan_running.clone(), state.volume_sync.clone(), options.unwrap_or_default(), Vec::new())
+}
 
-    tokio::spawn(async move {
-        {
+/// Scans `roots` in the background, or every drive when `roots` is empty. A full scan replaces the
+/// whole file list; a rooted one only what was indexed under those roots.
+fn start_scan(
+    handle: AppHandle,
+    files_arc: Arc<Mutex<Vec<FileMetadata>>>,
+    indexed_paths: Arc<IndexedPaths>,
+    scan_running: Arc<AtomicBool>,
+    volume_sync: Arc<tokio::sync::Mutex<()>>,
+    options: ScanOptions,
+    roots: Vec<PathBuf>,
+) -> Result<(), String> {
+    if scan_running.swap(true, Ordering::SeqCst) {
+        return Err("A scan is already running".to_string());
+    }
+    let scan_start_time = Instant::now();
+
+    tauri::async_runtime::spawn(async move {
+        let _volume_sync = volume_sync.lock().await;
+        if roots.is_empty() {
             let mut files_guard = match files_arc.lock() {
                 Ok(guard) => guard,
                 Err(poisoned) => {
@@ -99,8 +169,10 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
                     poisoned.into_inner()
                 }
             };
-            files_guard.clear();
+            // Imported file lists aren't rescanned, so they stay searchable throughout.
+            files_guard.retain(|file| file.source.is_some());
             files_guard.shrink_to_fit();
+            indexed_paths.rebuild(&files_guard);
             drop(files_guard);
         }
 
@@ -114,12 +186,18 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
 
         let (tx, mut rx) = mpsc::channel::<FileMetadata>(2048);
 
-        let available_drives = match get_available_drives() {
+        let drives = if roots.is_empty() {
+            scanner::available_drives()
+        } else {
+            Ok(roots.iter().map(|root| root.display().to_string()).collect())
+        };
+        let available_drives = match drives {
             Ok(drives) => drives,
             Err(e) => {
                 error!("Error getting available drives: {}", e);
                 let _ = handle.emit("scan_log", format!("获取可用驱动器列表时出错: {}", e));
                 let _ = handle.emit("scan_status", "idle");
+                scan_running.store(false, Ordering::SeqCst);
                 return;
             }
         };
@@ -133,6 +211,7 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
             let tx_clone = tx.clone();
             let handle_clone = handle.clone();
             let drive_label = drive.clone();
+            let options_clone = options.clone();
 
             info!("Spawning scan task for drive: {}", drive_label);
             let _ = handle_clone.emit("scan_progress", ScanProgress {
@@ -140,7 +219,7 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
                 message: "任务已启动".to_string(), files_found_on_drive: None, files_collected_so_far: Some(0)
             });
 
-            let join_handle = tokio::spawn(async move {
+            let join_handle = tokio::task::spawn_blocking(move || {
                 let drive_scan_start = Instant::now();
                 info!("Task started for drive: {}", drive_label);
                 let _ = handle_clone.emit("scan_log", format!("开始扫描驱动器: {}", drive_label));
@@ -149,10 +228,20 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
                     message: "正在扫描...".to_string(), files_found_on_drive: None, files_collected_so_far: Some(0)
                 });
 
-                let mut files_on_this_drive: usize = 0;
-                if let Err(e) = scan_directory_iterative_chan(&handle_clone, tx_clone, drive_path, &mut files_on_this_drive).await {
-                    error!("Task error scanning drive {}: {}", drive_label, e);
-                    let _ = handle_clone.emit("scan_log", format!("[{}] 扫描出错: {}", drive_label, e));
+                let mut channel_closed = false;
+                let files_on_this_drive = scanner::scan(&drive_path, &options_clone, |event| match event {
+                    ScanEvent::File(file_info) => {
+                        channel_closed = tx_clone.blocking_send(file_info).is_err();
+                        !channel_closed
+                    }
+                    ScanEvent::Unreadable { path, error } => {
+                        let _ = handle_clone.emit("scan_log", format!("警告 [{}]: 无法读取 {}", path.display(), error));
+                        true
+                    }
+                });
+                if channel_closed {
+                    error!("Task error scanning drive {}: channel closed", drive_label);
+                    let _ = handle_clone.emit("scan_log", format!("[{}] 扫描出错: {}", drive_label, "Channel closed"));
                 } else {
                     let duration = drive_scan_start.elapsed();
                     info!("Task finished for drive {}. Found {} files in {:?}", drive_label, files_on_this_drive, duration);
@@ -194,6 +283,20 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
             message: "结果收集完成".to_string(), files_found_on_drive: None, files_collected_so_far: Some(collected_files.len())
         });
 
+        volumes::tag(&mut collected_files, &volumes::mounted_volumes());
+
+        // A full scan is saved before it's published, so searches aren't blocked while it's written.
+        // File lists can't be imported or removed while a scan runs, so the imported entries can't change meanwhile.
+        let collected_files = if roots.is_empty() {
+            {
+                let files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+                collected_files.extend(files_guard.iter().filter(|file| file.source.is_some()).cloned());
+            }
+            persist_index(collected_files).await
+        } else {
+            collected_files
+        };
+
         {
             let write_start_time = Instant::now();
             info!("Updating shared file list...");
@@ -204,135 +307,59 @@ async fn scan_directory(app_handle: AppHandle, state: State<'_, AppState>) -> Re
                     poisoned.into_inner()
                 }
             };
-            files_guard.extend(collected_files);
+            if roots.is_empty() {
+                *files_guard = collected_files;
+            } else {
+                indexer::replace_roots(&mut files_guard, &roots, collected_files);
+            }
             files_guard.shrink_to_fit();
+            indexed_paths.rebuild(&files_guard);
             let write_duration = write_start_time.elapsed();
             info!("Shared file list updated in {:?}. Final count: {}", write_duration, files_guard.len());
             let _ = handle.emit("scan_log", format!("共享列表更新完毕 ({:.2?}). 最终文件数: {}", write_duration, files_guard.len()));
         }
+        if !roots.is_empty() {
+            persist_shared_index(files_arc.clone()).await;
+        }
 
         let total_scan_duration = scan_start_time.elapsed();
         info!("Background scan coordination finished in {:?}.", total_scan_duration);
         let _ = handle.emit("scan_log", format!("所有扫描任务协调完毕 ({:.2?})", total_scan_duration));
         let _ = handle.emit("scan_status", "idle");
+        scan_running.store(false, Ordering::SeqCst);
     });
 
     Ok(())
 }
 
-async fn scan_directory_iterative_chan(
-    handle: &AppHandle,
-    tx: mpsc::Sender<FileMetadata>,
-    start_path: PathBuf,
-    file_count: &mut usize,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut dirs_to_visit: VecDeque<PathBuf> = VecDeque::new();
-    dirs_to_visit.push_back(start_path);
-
-    while let Some(current_dir_path) = dirs_to_visit.pop_front() {
-        if !current_dir_path.is_dir() { continue; }
-        trace!("Processing directory: {}", current_dir_path.display());
-
-        let mut entries = match tokio::fs::read_dir(&current_dir_path).await {
-            Ok(entries) => entries,
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::PermissionDenied || e.kind() == std::io::ErrorKind::NotFound {
-                    trace!("Skipping inaccessible directory: {} ({})", current_dir_path.display(), e.kind());
-                } else {
-                    warn!("Could not read directory: {} ({})", current_dir_path.display(), e);
-                    let _ = handle.emit("scan_log", format!("警告 [{}]: 无法读取 {}", current_dir_path.display(), e));
-                }
-                continue;
-            }
-        };
-
-        while let Some(entry_res) = entries.next_entry().await.transpose() {
-            let entry = match entry_res { Ok(e) => e, Err(e) => { error!("Error reading dir entry in {}: {}", current_dir_path.display(), e); continue; } };
-            let current_path = entry.path();
-            let metadata = entry.metadata().await.ok();
-
-            if let Some(meta) = metadata {
-                if meta.is_file() {
-                    if let Some(name_osstr) = current_path.file_name() {
-                        let name = name_osstr.to_string_lossy().to_string();
-                        if let Ok(modified_sys_time) = meta.modified() {
-                            let modified_time = chrono::DateTime::from(modified_sys_time);
-                            let file_info = FileMetadata {
-                                path: current_path.to_string_lossy().to_string(),
-                                name,
-                                size: meta.len(),
-                                modified_time,
-                            };
-                            if tx.send(file_info).await.is_err() {
-                                error!("Channel closed while sending file info from {}", current_dir_path.display());
-                                return Err("Channel closed".into());
-                            }
-                            *file_count += 1;
-                        }
-                    }
-                } else if meta.is_dir() {
-                    let dir_name = current_path.file_name().unwrap_or_default();
-                    let dir_name_str = dir_name.to_string_lossy();
-
-                    #[cfg(windows)]
-                    let is_hidden = meta.file_attributes() & 0x2 != 0 || dir_name_str.starts_with('.');
-                    #[cfg(not(windows))]
-                    let is_hidden = dir_name_str.starts_with('.');
-
-                    let skip_dirs = [
-                        "Windows", "Program Files", "Program Files (x86)", "$Recycle.Bin",
-                        "System Volume Information", "Recovery", "Config.Msi", "swapfile",
-                        "AppData", "Application Data", "Local Settings", "Library", "/.", "/dev", "/proc", "/sys",
-                        "node_modules", "target", "vendor", "venv", ".git", ".hg", ".svn",
-                        "__pycache__", ".pyc", ".pyo", ".class", ".jar", ".gradle", ".m2",
-                        ".cache", "cache", "Cache", "Temp", "tmp", "Downloads",
-                        ".vscode", ".vscode-server", ".idea", "Pods", ".npm", ".cargo", ".rustup",
-                        ".vdi", ".vmdk", ".pvm"
-                    ];
-                    let is_skipped = skip_dirs.iter().any(|&skip| dir_name_str.contains(skip) || dir_name_str.eq_ignore_ascii_case(skip));
-
-                    if is_hidden || is_skipped {
-                        trace!("Skipping directory: {}", current_path.display());
-                        continue;
-                    }
-                    dirs_to_visit.push_back(current_path);
-                }
-            }
-        }
+/// Saves a fresh scan where the `ultrasearch` CLI (and the next launch) can find it.
+async fn persist_index(files: Vec<FileMetadata>) -> Vec<FileMetadata> {
+    let Some(index_path) = indexer::default_index_path() else {
+        warn!("No data directory available; the file index is not persisted");
+        return files;
+    };
+    let files = Arc::new(files);
+    let task_files = files.clone();
+    let save_start = Instant::now();
+    match tokio::task::spawn_blocking(move || indexer::save_index(&index_path, &task_files).map(|()| index_path)).await {
+        Ok(Ok(index_path)) => debug!("Saved {} files to {} in {:?}", files.len(), index_path.display(), save_start.elapsed()),
+        Ok(Err(e)) => warn!("Failed to save the file index: {}", e),
+        Err(e) => error!("Index save task failed: {}", e),
     }
-
-    Ok(())
+    Arc::try_unwrap(files).unwrap_or_else(|files| (*files).clone())
 }
 
-#[cfg(windows)]
-use std::os::windows::fs::MetadataExt;
-
-fn get_available_drives() -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
-    let mut drives = Vec::new();
-    #[cfg(target_os = "windows")]
-    {
-        for letter_byte in b'A'..=b'Z' {
-            let letter = letter_byte as char;
-            let drive_path_str = format!("{}:\\", letter);
-            let path = Path::new(&drive_path_str);
-            match std::fs::metadata(path) {
-                Ok(meta) => {
-                    if meta.is_dir() {
-                        drives.push(drive_path_str);
-                    }
-                },
-                Err(_) => { /* Drive letter likely not valid or inaccessible */ }
-            }
-        }
-    }
-    #[cfg(not(target_os = "windows"))]
-    {
-        drives.push("/".to_string());
-    }
-    if drives.is_empty() {
-        Err("No drives found or accessible.".into())
-    } else {
-        Ok(drives)
+/// Saves the shared file list as it is. Only the copy is made under its lock, not the write.
+async fn persist_shared_index(files_arc: Arc<Mutex<Vec<FileMetadata>>>) {
+    let Some(index_path) = indexer::default_index_path() else { return };
+    let saved = tokio::task::spawn_blocking(move || {
+        let snapshot = match files_arc.lock() { Ok(guard) => guard.clone(), Err(p) => p.into_inner().clone() };
+        indexer::save_index(&index_path, &snapshot)
+    }).await;
+    match saved {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to save the file index: {}", e),
+        Err(e) => error!("Index save task failed: {}", e),
     }
 }
 
@@ -340,7 +367,6 @@ fn get_available_drives() -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
 async fn basic_search(query: String, state: State<'_, AppState>) -> Result<Vec<SearchResult>, String> {
     let search_start = Instant::now();
     let files_arc = state.files.clone();
-    let query_lower = query.to_lowercase();
 
     let files_guard = match files_arc.lock() {
         Ok(guard) => guard,
@@ -350,24 +376,10 @@ async fn basic_search(query: String, state: State<'_, AppState>) -> Result<Vec<S
         }
     };
 
-    let results: Vec<SearchResult> = files_guard
-        .iter()
-        .filter(|file| {
-            file.name.to_lowercase().contains(&query_lower) ||
-            file.path.to_lowercase().contains(&query_lower)
-        })
-        .take(500)
-        .map(|file| {
-            SearchResult {
-                file_path: file.path.clone(),
-                name: file.name.clone(),
-                size: file.size,
-                modified_time: file.modified_time,
-                line_number: None,
-                content: None,
-                matches: None,
-            }
-        })
+    let results: Vec<SearchResult> = FileQuery::new(&query)
+        .search(&files_guard, 0, 500)
+        .into_iter()
+        .map(|file| SearchResult::from(file.clone()))
         .collect();
 
     let search_duration = search_start.elapsed();
@@ -384,93 +396,929 @@ async fn advanced_search(
 ) -> Result<Vec<SearchResult>, String> {
     let search_start = Instant::now();
     let files_arc = state.files.clone();
-    let query_lower = query.to_lowercase();
 
     debug!("Advanced search started for query: '{}', filters: {:?}", query, filters);
 
     let files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
 
-    let results: Vec<SearchResult> = files_guard
-        .iter()
-        .filter(|file| {
-            query_lower.is_empty() ||
-            file.name.to_lowercase().contains(&query_lower) ||
-            file.path.to_lowercase().contains(&query_lower)
-        })
-        .filter(|file| {
-            if let Some(ref ext_filter) = filters.file_type {
-                if !ext_filter.is_empty() {
-                    if let Some(file_ext) = Path::new(&file.path).extension().and_then(|os| os.to_str()) {
-                        if !file_ext.eq_ignore_ascii_case(ext_filter) {
-                            return false;
-                        }
-                    } else {
-                        return false;
-                    }
+    let results: Vec<SearchResult> = FileQuery::new(&query)
+        .with_filters(filters)
+        .search(&files_guard, 0, 500)
+        .into_iter()
+        .map(|file| SearchResult::from(file.clone()))
+        .collect();
+
+    let search_duration = search_start.elapsed();
+    debug!("Advanced search completed in {:?}, found {} results (limited)", search_duration, results.len());
+
+    Ok(results)
+}
+
+/// Writes every match of a name search, not just the 500 the list shows, to `path`; returns how many were written.
+/// The format comes from `options`, or else from the file extension.
+#[tauri::command]
+async fn export_results(
+    query: String,
+    filters: Option<AdvancedFilterOptions>,
+    sort: Option<SortKey>,
+    descending: Option<bool>,
+    path: String,
+    options: Option<ExportOptions>,
+    state: State<'_, AppState>
+) -> Result<usize, String> {
+    let export_path = PathBuf::from(path);
+    let options = options.unwrap_or_else(|| ExportOptions {
+        format: ExportFormat::from_path(&export_path).unwrap_or_default(),
+        columns: Vec::new(),
+    });
+    let mut file_query = FileQuery::new(&query).with_filters(filters.unwrap_or_default());
+    if let Some(key) = sort {
+        file_query = file_query.with_sort(key, descending.unwrap_or(false));
+    }
+
+    let files_arc = state.files.clone();
+    let export_start = Instant::now();
+    let count = tauri::async_runtime::spawn_blocking(move || {
+        // Only the matches are copied under the lock; writing them out doesn't block searches.
+        let results: Vec<FileMetadata> = {
+            let files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+            file_query.search(&files_guard, 0, usize::MAX).into_iter().cloned().collect()
+        };
+        export::export_to_file(&export_path, &results, &options)
+            .map_err(|e| format!("Failed to export to {}: {}", export_path.display(), e))
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))??;
+
+    info!("Exported {} results for '{}' in {:?}", count, query, export_start.elapsed());
+    Ok(count)
+}
+
+/// Adds an Everything file list (.efu) as a read-only source, searchable alongside scanned files;
+/// importing the same file again replaces its entries. Returns how many entries it added.
+#[tauri::command]
+async fn import_file_list(path: String, state: State<'_, AppState>) -> Result<usize, String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; import the file list once it finishes".to_string());
+    };
+    let list_path = PathBuf::from(path);
+    let (source, imported) = tauri::async_runtime::spawn_blocking(move || {
+        efu::import_efu(&list_path).map_err(|e| format!("Failed to import {}: {}", list_path.display(), e))
+    })
+    .await
+    .map_err(|e| format!("Import task failed: {}", e))??;
+
+    let count = imported.len();
+    {
+        let mut files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        indexer::replace_source(&mut files_guard, &source, imported);
+        state.indexed_paths.rebuild(&files_guard);
+    }
+    info!("Imported {} entries from {}", count, source);
+    persist_shared_index(state.files.clone()).await;
+    Ok(count)
+}
+
+#[tauri::command]
+async fn remove_file_list(source: String, state: State<'_, AppState>) -> Result<(), String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; remove the file list once it finishes".to_string());
+    };
+    {
+        let mut files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        indexer::replace_source(&mut files_guard, &source, Vec::new());
+        state.indexed_paths.rebuild(&files_guard);
+    }
+    persist_shared_index(state.files.clone()).await;
+    Ok(())
+}
+
+/// Imported file lists and how many entries each holds.
+#[tauri::command]
+async fn list_file_lists(state: State<'_, AppState>) -> Result<Vec<(String, usize)>, String> {
+    let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    let mut sources = indexer::sources(&files_guard);
+    sources.retain(|(source, _)| !source.starts_with(volumes::OFFLINE_SOURCE_PREFIX));
+    Ok(sources)
+}
+
+/// Opens an indexed file with its default application.
+#[tauri::command]
+async fn open_file(path: String, state: State<'_, AppState>) -> Result<(), String> {
+    let path = PathBuf::from(path);
+    if !is_servable(&state, &path) {
+        return Err(format!("{} is not in the index", path.display()));
+    }
+    desktop::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Shows an indexed file selected in the file manager.
+#[tauri::command]
+async fn reveal_file(path: String, state: State<'_, AppState>) -> Result<(), String> {
+    let path = PathBuf::from(path);
+    if !is_servable(&state, &path) {
+        return Err(format!("{} is not in the index", path.display()));
+    }
+    desktop::reveal(&path).map_err(|e| format!("Failed to reveal {}: {}", path.display(), e))
+}
+
+/// Puts the paths on the clipboard, one per line.
+#[tauri::command]
+async fn copy_paths(paths: Vec<String>) -> Result<(), String> {
+    desktop::copy_to_clipboard(&paths.join("\n")).map_err(|e| format!("Failed to copy to the clipboard: {}", e))
+}
+
+/// Runs a file operation over `paths` in the background and saves the index if anything changed.
+/// A scan would publish the file list it started with, undoing the changes, so none may start until it's done.
+async fn run_file_ops(
+    state: &AppState,
+    paths: Vec<String>,
+    op: impl FnOnce(&Mutex<Vec<FileMetadata>>, &[String]) -> Vec<FileOpResult> + Send + 'static,
+) -> Result<Vec<FileOpResult>, String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; try again once it finishes".to_string());
+    };
+    let (files_arc, indexed_paths) = (state.files.clone(), state.indexed_paths.clone());
+    let results = tauri::async_runtime::spawn_blocking(move || {
+        let results = op(&files_arc, &paths);
+        if results.iter().any(|result| result.error.is_none()) {
+            indexed_paths.rebuild(&match files_arc.lock() { Ok(guard) => guard, Err(p) => p.into_inner() });
+        }
+        results
+    })
+        .await
+        .map_err(|e| format!("File operation task failed: {}", e))?;
+    let failed = results.iter().filter(|result| result.error.is_some()).count();
+    if failed > 0 {
+        warn!("{} of {} file operations failed", failed, results.len());
+    }
+    if failed < results.len() {
+        persist_shared_index(state.files.clone()).await;
+    }
+    Ok(results)
+}
+
+/// Renames an indexed file within its folder and returns its new path.
+#[tauri::command]
+async fn rename_file(path: String, new_name: String, state: State<'_, AppState>) -> Result<String, String> {
+    let results = run_file_ops(&state, vec![path], move |files, paths| {
+        fileops::apply_batch(files, paths, |path| fileops::rename(path, &new_name).map(Some))
+    })
+    .await?;
+    match results.into_iter().next() {
+        Some(FileOpResult { new_path: Some(new_path), .. }) => Ok(new_path),
+        Some(FileOpResult { error: Some(error), .. }) => Err(error),
+        _ => Err("Rename produced no result".to_string()),
+    }
+}
+
+/// Moves indexed files into `destination`, reporting the outcome for each.
+#[tauri::command]
+async fn move_files(paths: Vec<String>, destination: String, state: State<'_, AppState>) -> Result<Vec<FileOpResult>, String> {
+    let results = run_file_ops(&state, paths, move |files, paths| {
+        let results = fileops::move_all(files, paths, Path::new(&destination));
+        // Moving onto or off a removable drive changes which volume a file belongs to.
+        let mounted = volumes::mounted_volumes();
+        volumes::tag(&mut match files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() }, &mounted);
+        results
+    })
+    .await?;
+    Ok(results)
+}
+
+/// Sends indexed files to the trash, reporting the outcome for each.
+#[tauri::command]
+async fn trash_files(paths: Vec<String>, state: State<'_, AppState>) -> Result<Vec<FileOpResult>, String> {
+    run_file_ops(&state, paths, fileops::trash_all).await
+}
+
+fn rename_journal_path() -> Result<PathBuf, String> {
+    indexer::default_index_path()
+        .map(|index_path| RenameJournal::path_for(&index_path))
+        .ok_or_else(|| "No data directory for the rename journal".to_string())
+}
+
+/// What `options` would rename each of `paths` to, with any conflicts; nothing is changed.
+#[tauri::command]
+async fn preview_rename(paths: Vec<String>, options: RenameOptions, state: State<'_, AppState>) -> Result<RenamePlan, String> {
+    let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    rename::plan(&files_guard, &paths, &options).map_err(|e| e.to_string())
+}
+
+/// Renames `paths` as `preview_rename` showed, all or none, and records the batch so it can be undone.
+#[tauri::command]
+async fn apply_rename(paths: Vec<String>, options: RenameOptions, state: State<'_, AppState>) -> Result<Vec<Renamed>, String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; rename once it finishes".to_string());
+    };
+    let journal_path = rename_journal_path()?;
+    let plan = {
+        let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        rename::plan(&files_guard, &paths, &options).map_err(|e| e.to_string())?
+    };
+    let (files_arc, indexed_paths) = (state.files.clone(), state.indexed_paths.clone());
+    let renamed = tauri::async_runtime::spawn_blocking(move || {
+        let renamed = rename::apply(&plan).map_err(|e| e.to_string())?;
+        {
+            let mut files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+            rename::update_index(&mut files_guard, &renamed);
+            indexed_paths.rebuild(&files_guard);
+        }
+        let mut journal = RenameJournal::load(&journal_path).unwrap_or_else(|e| {
+            warn!("Starting a new rename journal; {} can't be read: {}", journal_path.display(), e);
+            RenameJournal::default()
+        });
+        journal.record(renamed.clone());
+        if let Err(e) = journal.save(&journal_path) {
+            warn!("Failed to save the rename journal {}: {}", journal_path.display(), e);
+        }
+        Ok::<_, String>(renamed)
+    })
+    .await
+    .map_err(|e| format!("Rename task failed: {}", e))??;
+
+    info!("Renamed {} files", renamed.len());
+    persist_shared_index(state.files.clone()).await;
+    Ok(renamed)
+}
+
+/// Puts back the names the last applied batch rename changed.
+#[tauri::command]
+async fn undo_rename(state: State<'_, AppState>) -> Result<Vec<Renamed>, String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; undo the rename once it finishes".to_string());
+    };
+    let journal_path = rename_journal_path()?;
+    let (files_arc, indexed_paths) = (state.files.clone(), state.indexed_paths.clone());
+    let undone = tauri::async_runtime::spawn_blocking(move || {
+        let mut journal = RenameJournal::load(&journal_path).map_err(|e| format!("Failed to read {}: {}", journal_path.display(), e))?;
+        let undone = journal.undo_last().map_err(|e| e.to_string())?;
+        {
+            let mut files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+            rename::update_index(&mut files_guard, &undone);
+            indexed_paths.rebuild(&files_guard);
+        }
+        if let Err(e) = journal.save(&journal_path) {
+            warn!("Failed to save the rename journal {}: {}", journal_path.display(), e);
+        }
+        Ok::<_, String>(undone)
+    })
+    .await
+    .map_err(|e| format!("Undo task failed: {}", e))??;
+
+    persist_shared_index(state.files.clone()).await;
+    Ok(undone)
+}
+
+/// Volumes seen so far, online or not, with how many indexed files each holds.
+#[tauri::command]
+async fn list_volumes(state: State<'_, AppState>) -> Result<Vec<(CatalogVolume, usize)>, String> {
+    let catalog = match state.volume_catalog.lock() { Ok(guard) => guard.clone(), Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for volume in files_guard.iter().filter_map(|file| file.volume.as_deref()) {
+        *counts.entry(volume).or_default() += 1;
+    }
+    Ok(catalog.volumes.into_iter().map(|known| {
+        let count = counts.get(known.volume.id.as_str()).copied().unwrap_or(0);
+        (known, count)
+    }).collect())
+}
+
+/// Drops a volume from the catalog together with its files.
+#[tauri::command]
+async fn forget_volume(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let Some(_claim) = ScanClaim::claim(&state.scan_running) else {
+        return Err("A scan is running; forget the volume once it finishes".to_string());
+    };
+    let _volume_sync = state.volume_sync.lock().await;
+    let forgotten = {
+        let mut catalog = match state.volume_catalog.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        let mut files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        let forgotten = catalog.forget(&mut files_guard, &id);
+        state.indexed_paths.rebuild(&files_guard);
+        forgotten
+    };
+    if !forgotten {
+        return Err(format!("No volume {} in the catalog", id));
+    }
+    save_volume_catalog(&state.volume_catalog);
+    persist_shared_index(state.files.clone()).await;
+    Ok(())
+}
+
+/// Writes the whole index, or everything under `root`, as an Everything file list.
+#[tauri::command]
+async fn export_file_list(path: String, root: Option<String>, state: State<'_, AppState>) -> Result<usize, String> {
+    let export_path = PathBuf::from(path);
+    let root = root.map(PathBuf::from);
+    let files_arc = state.files.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let entries: Vec<FileMetadata> = {
+            let files_guard = match files_arc.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+            efu::under_root(&files_guard, root.as_deref()).cloned().collect()
+        };
+        let options = ExportOptions { format: ExportFormat::Efu, columns: Vec::new() };
+        export::export_to_file(&export_path, &entries, &options)
+            .map_err(|e| format!("Failed to export to {}: {}", export_path.display(), e))
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn content_search(
+    query: String,
+    filters: AdvancedFilterOptions,
+    options: Option<ContentSearchOptions>,
+    state: State<'_, AppState>
+) -> Result<ContentSearchResponse, String> {
+    let search_start = Instant::now();
+    let searcher = ContentSearcher::new(&query, options.unwrap_or_default())?.with_extractors(state.extractors.clone());
+
+    let candidates: Vec<FileMetadata> = {
+        let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        files_guard.iter().filter(|file| filters.matches(file)).cloned().collect()
+    };
+    debug!("Content search for '{}' over {} candidate files", query, candidates.len());
+
+    let response = tokio::task::spawn_blocking(move || {
+        let paths: Vec<PathBuf> = candidates.iter().map(|file| PathBuf::from(&file.path)).collect();
+        let by_path: HashMap<&Path, &FileMetadata> = candidates.iter().map(|file| (Path::new(&file.path), file)).collect();
+        let report = searcher.search_files(&paths);
+        let results = line_hit_results(report.files, &by_path);
+        ContentSearchResponse { results, stats: report.stats }
+    }).await.map_err(|e| format!("Content search task failed: {}", e))?;
+
+    debug!("Content search completed in {:?}, {} line hits, {:?}", search_start.elapsed(), response.results.len(), response.stats);
+    Ok(response)
+}
+
+/// Starts a streaming content search and returns its id right away. Hits arrive as
+/// `content_search_hit` events and progress as `content_search_progress`; starting a new
+/// search cancels the previous one.
+#[tauri::command]
+async fn start_content_search(
+    app_handle: AppHandle,
+    query: String,
+    filters: AdvancedFilterOptions,
+    options: Option<ContentSearchOptions>,
+    state: State<'_, AppState>
+) -> Result<u64, String> {
+    let searcher = ContentSearcher::new(&query, options.unwrap_or_default())?.with_extractors(state.extractors.clone());
+    let search_id = state.next_search_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    {
+        let mut active_guard = match state.active_content_search.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        if let Some((previous_id, previous_cancel)) = active_guard.replace((search_id, cancel.clone())) {
+            debug!("Content search {} superseded by {}", previous_id, search_id);
+            previous_cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let candidates: Vec<FileMetadata> = {
+        let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        files_guard.iter().filter(|file| filters.matches(file)).cloned().collect()
+    };
+    info!("Content search {} for '{}' started over {} candidate files", search_id, query, candidates.len());
+
+    tokio::task::spawn_blocking(move || {
+        let search_start = Instant::now();
+        let paths: Vec<PathBuf> = candidates.iter().map(|file| PathBuf::from(&file.path)).collect();
+        let by_path: HashMap<&Path, &FileMetadata> = candidates.iter().map(|file| (Path::new(&file.path), file)).collect();
+
+        let progress = searcher.search_files_streaming(&paths, &cancel, |files, progress| {
+            if !files.is_empty() {
+                let results = line_hit_results(files, &by_path);
+                if let Err(e) = app_handle.emit("content_search_hit", ContentSearchHits { search_id, results }) {
+                    error!("Failed to emit content_search_hit: {}", e);
                 }
             }
+            let _ = app_handle.emit("content_search_progress", ContentSearchProgress { search_id, progress: progress.clone() });
+        });
 
-            if let Some(min_size) = filters.min_size {
-                if file.size < min_size {
-                    return false;
-                }
+        info!("Content search {} finished in {:?}: {} hits, cancelled: {}", search_id, search_start.elapsed(), progress.hits, progress.cancelled);
+    });
+
+    Ok(search_id)
+}
+
+#[tauri::command]
+async fn cancel_content_search(search_id: u64, state: State<'_, AppState>) -> Result<(), String> {
+    let mut active_guard = match state.active_content_search.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    if active_guard.as_ref().is_some_and(|(active_id, _)| *active_id == search_id) {
+        if let Some((_, cancel)) = active_guard.take() {
+            cancel.store(true, Ordering::Relaxed);
+            debug!("Content search {} cancelled", search_id);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn build_content_index(
+    app_handle: AppHandle,
+    config: Option<ContentIndexConfig>,
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    let config = config.unwrap_or_default();
+    let candidates: Vec<PathBuf> = {
+        let files_guard = match state.files.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        files_guard
+            .iter()
+            .filter(|file| config.accepts(Path::new(&file.path), file.size))
+            .map(|file| PathBuf::from(&file.path))
+            .collect()
+    };
+
+    info!("Building content index over {} candidate files", candidates.len());
+    let _ = app_handle.emit("content_index_status", "building");
+    let build_start = Instant::now();
+
+    let index = tokio::task::spawn_blocking(move || {
+        let mut index = ContentIndex::new(config);
+        index.build(candidates);
+        index
+    }).await.map_err(|e| format!("Content index task failed: {}", e))?;
+
+    let duration = build_start.elapsed();
+    info!("Content index built in {:?}: {} documents", duration, index.len());
+    let _ = app_handle.emit("scan_log", format!("内容索引构建完成 ({:.2?}). 已索引 {} 个文件.", duration, index.len()));
+
+    let watch_roots = !index.is_empty();
+    {
+        let mut index_guard = match state.content_index.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+        *index_guard = Some(index);
+    }
+
+    let mut watcher_guard = match state.content_watcher.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    *watcher_guard = None;
+    if watch_roots {
+        match content_index::watch(state.content_index.clone()) {
+            Ok(watcher) => *watcher_guard = Some(watcher),
+            Err(e) => {
+                warn!("Failed to watch content index roots: {}", e);
+                let _ = app_handle.emit("scan_log", format!("警告: 无法监视内容索引目录 {}", e));
             }
+        }
+    }
+
+    let _ = app_handle.emit("content_index_status", "ready");
+    Ok(())
+}
 
-            if let Some(max_size) = filters.max_size {
-                 if file.size > max_size {
-                     return false;
-                 }
+/// Starts (or restarts) the local HTTP API and returns the address it listens on.
+#[tauri::command]
+async fn start_http_server(app_handle: AppHandle, config: Option<ServerConfig>, state: State<'_, AppState>) -> Result<String, String> {
+    let (files, indexed_paths, scan_running, volume_sync) =
+        (state.files.clone(), state.indexed_paths.clone(), state.scan_running.clone(), state.volume_sync.clone());
+    let on_scan = move |request: ScanRequest| {
+        let roots = request
+            .roots
+            .iter()
+            .map(|root| root.canonicalize().map_err(|e| format!("Cannot scan {}: {}", root.display(), e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        start_scan(app_handle.clone(), files.clone(), indexed_paths.clone(), scan_running.clone(), volume_sync.clone(), request.options, roots)
+    };
+    let ctx = Arc::new(ServerContext {
+        files: state.files.clone(),
+        preview: state.file_preview.clone(),
+        scanning: state.scan_running.clone(),
+        on_scan: Box::new(on_scan),
+    });
+    let config = config.unwrap_or_default();
+    let port = config.port;
+
+    // A running server keeps serving until its replacement is listening.
+    let mut server_guard = match state.http_server.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    let started = match server_guard.take() {
+        // The port is still held by the running server, so it has to stop first; if the new one still
+        // can't listen, the old one is started again.
+        Some((previous, previous_config)) if port != 0 && previous.local_addr().port() == port => {
+            info!("Restarting HTTP API on {}", previous.local_addr());
+            drop(previous);
+            HttpServer::start(config.clone(), ctx.clone()).inspect_err(|_| {
+                match HttpServer::start(previous_config.clone(), ctx.clone()) {
+                    Ok(restored) => *server_guard = Some((restored, previous_config)),
+                    Err(e) => warn!("Failed to restore the HTTP API on port {}: {}", port, e),
+                }
+            })
+        }
+        previous => match HttpServer::start(config.clone(), ctx.clone()) {
+            Ok(server) => {
+                if let Some((previous, _)) = previous {
+                    info!("Moving HTTP API from {}", previous.local_addr());
+                }
+                Ok(server)
+            }
+            Err(e) => {
+                *server_guard = previous;
+                Err(e)
             }
+        },
+    };
+    let server = started.map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+    let address = server.local_addr().to_string();
+    info!("HTTP API listening on {}", address);
+    *server_guard = Some((server, config));
+    Ok(address)
+}
+
+#[tauri::command]
+async fn stop_http_server(state: State<'_, AppState>) -> Result<(), String> {
+    let mut server_guard = match state.http_server.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    if let Some((server, _)) = server_guard.take() {
+        info!("Stopping HTTP API on {}", server.local_addr());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_allowed_roots(roots: Vec<String>, state: State<'_, AppState>) -> Result<(), String> {
+    let mut canonical_roots = Vec::with_capacity(roots.len());
+    for root in roots {
+        match Path::new(&root).canonicalize() {
+            Ok(root) => canonical_roots.push(root),
+            Err(e) => return Err(format!("Invalid root {}: {}", root, e)),
+        }
+    }
+    let mut roots_guard = match state.allowed_roots.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    info!("Protocol allowed roots set to {:?}", canonical_roots);
+    *roots_guard = canonical_roots;
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_content_index(state: State<'_, AppState>) -> Result<(), String> {
+    if let Ok(mut watcher_guard) = state.content_watcher.lock() {
+        *watcher_guard = None;
+    }
+    let mut index_guard = match state.content_index.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    *index_guard = None;
+    info!("Content index cleared");
+    Ok(())
+}
 
-            true
+#[tauri::command]
+async fn search_content_index(
+    query: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>
+) -> Result<Vec<SearchResult>, String> {
+    let search_start = Instant::now();
+    let index_guard = match state.content_index.lock() { Ok(guard) => guard, Err(p) => return Err(format!("Mutex poisoned: {}", p)) };
+    let index = index_guard.as_ref().ok_or_else(|| "Content index is not enabled".to_string())?;
+
+    let results: Vec<SearchResult> = index
+        .search(&query, limit.unwrap_or(500))?
+        .into_iter()
+        .map(|hit| SearchResult {
+            file_path: hit.path.to_string_lossy().to_string(),
+            name: hit.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            size: hit.size,
+            modified_time: chrono::DateTime::from(hit.modified),
+            line_number: (hit.line_number > 0).then_some(hit.line_number as i32),
+            content: (!hit.snippet.is_empty()).then_some(hit.snippet),
+            matches: (!hit.matched.is_empty()).then_some(hit.matched),
+            context_before: None,
+            context_after: None,
+            match_ranges: None,
+            source: None,
+            volume: None,
+            offline: false,
         })
-        .take(500)
-        .map(|file| SearchResult {
-             file_path: file.path.clone(),
-             name: file.name.clone(),
-             size: file.size,
-             modified_time: file.modified_time,
-             line_number: None, content: None, matches: None,
-         })
         .collect();
 
-    let search_duration = search_start.elapsed();
-    debug!("Advanced search completed in {:?}, found {} results (limited)", search_duration, results.len());
-
+    debug!("Content index search for '{}' completed in {:?}, {} hits", query, search_start.elapsed(), results.len());
     Ok(results)
 }
 
 #[tauri::command]
-async fn preview_file(state: State<'_, AppState>, path: String) -> Result<String, String> {
-    warn!("Preview file not fully implemented yet.");
-    Ok(format!("Preview for {} not available.", path))
+async fn preview_file(
+    path: String,
+    encoding: Option<String>,
+    state: State<'_, AppState>
+) -> Result<PreviewPayload, PreviewError> {
+    let preview_start = Instant::now();
+    let file_preview = state.file_preview.clone();
+    let preview_path = PathBuf::from(&path);
+    let files = state.files.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        if preview_path.is_dir() {
+            let files = match files.lock() {
+                Ok(guard) => guard,
+                Err(p) => return Err(PreviewError::Io(std::io::Error::other(format!("Mutex poisoned: {}", p)))),
+            };
+            let indexed = files.iter().map(|file| IndexedFile { path: &file.path, size: file.size, modified: file.modified_time });
+            return Ok(file_preview.preview_directory(&preview_path, indexed));
+        }
+        file_preview.preview_file(&preview_path, encoding.as_deref())
+    })
+    .await
+    .map_err(|e| PreviewError::Io(std::io::Error::other(e)))?;
+
+    match &result {
+        Ok(payload) => debug!("Preview of {} ({:?}, {} bytes) built in {:?}", path, payload.kind, payload.size, preview_start.elapsed()),
+        Err(e) => warn!("Preview of {} failed: {}", path, e),
+    }
+    result
+}
+
+#[tauri::command]
+async fn preview_range(
+    path: String,
+    range: PreviewRange,
+    encoding: Option<String>,
+    state: State<'_, AppState>
+) -> Result<RangePreview, PreviewError> {
+    let preview_start = Instant::now();
+    let file_preview = state.file_preview.clone();
+    let preview_path = PathBuf::from(&path);
+    let result = tokio::task::spawn_blocking(move || file_preview.preview_range(&preview_path, range, encoding.as_deref()))
+        .await
+        .map_err(|e| PreviewError::Io(std::io::Error::other(e)))?;
+
+    match &result {
+        Ok(preview) => debug!("Range {:?} of {} ({} bytes at {}) read in {:?}", range, path, preview.length, preview.offset, preview_start.elapsed()),
+        Err(e) => warn!("Range preview of {} failed: {}", path, e),
+    }
+    result
+}
+
+#[tauri::command]
+async fn preview_source(
+    path: String,
+    range: PreviewRange,
+    encoding: Option<String>,
+    state: State<'_, AppState>
+) -> Result<SourcePreview, PreviewError> {
+    let preview_start = Instant::now();
+    let file_preview = state.file_preview.clone();
+    let preview_path = PathBuf::from(&path);
+    let result = tokio::task::spawn_blocking(move || file_preview.preview_source(&preview_path, range, encoding.as_deref()))
+        .await
+        .map_err(|e| PreviewError::Io(std::io::Error::other(e)))?;
+
+    match &result {
+        Ok(preview) => debug!("Source preview of {} ({:?}) built in {:?}", path, preview.source.language, preview_start.elapsed()),
+        Err(e) => warn!("Source preview of {} failed: {}", path, e),
+    }
+    result
+}
+
+#[tauri::command]
+async fn preview_hex(
+    path: String,
+    offset: u64,
+    length: Option<u64>,
+    pattern: Option<String>,
+    state: State<'_, AppState>
+) -> Result<HexPreview, PreviewError> {
+    let file_preview = state.file_preview.clone();
+    let preview_path = PathBuf::from(&path);
+    let result = tokio::task::spawn_blocking(move || file_preview.preview_hex(&preview_path, offset, length, pattern.as_deref()))
+        .await
+        .map_err(|e| PreviewError::Io(std::io::Error::other(e)))?;
+
+    if let Err(e) = &result {
+        warn!("Hex preview of {} failed: {}", path, e);
+    }
+    result
+}
+
+#[tauri::command]
+async fn thumbnail(
+    path: String,
+    max_size: Option<u32>,
+    thumbnailer: State<'_, Thumbnailer>
+) -> Result<Thumbnail, PreviewError> {
+    let thumbnail_start = Instant::now();
+    let thumbnailer = thumbnailer.inner().clone();
+    let source = PathBuf::from(&path);
+    let result = tokio::task::spawn_blocking(move || thumbnailer.thumbnail(&source, max_size.unwrap_or(DEFAULT_THUMBNAIL_SIZE)))
+        .await
+        .map_err(|e| PreviewError::Io(std::io::Error::other(e)))?;
+
+    match &result {
+        Ok(thumbnail) => debug!("Thumbnail of {} ({}x{}) ready in {:?}", path, thumbnail.info.width, thumbnail.info.height, thumbnail_start.elapsed()),
+        Err(e) => warn!("Thumbnail of {} failed: {}", path, e),
+    }
+    result
 }
 
 #[tauri::command]
-async fn highlight_content(state: State<'_, AppState>, content: String, query: String) -> Result<String, String> {
-    warn!("Highlight content not fully implemented yet.");
-    Ok(content)
+async fn highlight_content(
+    content: String,
+    query: String,
+    options: Option<HighlightOptions>,
+    state: State<'_, AppState>
+) -> Result<Highlight, String> {
+    state.file_preview.highlight_content(&content, &query, options.unwrap_or_default())
+}
+
+/// Fills the file list from the last saved scan, unless a new scan has already produced one.
+fn load_persisted_index(files: Arc<Mutex<Vec<FileMetadata>>>, indexed_paths: Arc<IndexedPaths>) -> Option<tauri::async_runtime::JoinHandle<()>> {
+    let index_path = indexer::default_index_path()?;
+    Some(tauri::async_runtime::spawn_blocking(move || {
+        let load_start = Instant::now();
+        let loaded = match indexer::load_index(&index_path) {
+            Ok(loaded) => loaded,
+            Err(indexer::IndexError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Ignoring saved index {}: {}", index_path.display(), e);
+                return;
+            }
+        };
+        let mut files_guard = match files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+        if files_guard.is_empty() {
+            info!("Loaded {} files from {} in {:?}", loaded.len(), index_path.display(), load_start.elapsed());
+            *files_guard = loaded;
+            indexed_paths.rebuild(&files_guard);
+        }
+    }))
+}
+
+fn load_volume_catalog() -> VolumeCatalog {
+    let Some(index_path) = indexer::default_index_path() else { return VolumeCatalog::default() };
+    let catalog_path = VolumeCatalog::path_for(&index_path);
+    VolumeCatalog::load(&catalog_path).unwrap_or_else(|e| {
+        warn!("Ignoring volume catalog {}: {}", catalog_path.display(), e);
+        VolumeCatalog::default()
+    })
+}
+
+fn save_volume_catalog(catalog: &Mutex<VolumeCatalog>) {
+    let Some(index_path) = indexer::default_index_path() else { return };
+    let catalog_path = VolumeCatalog::path_for(&index_path);
+    let catalog_guard = match catalog.lock() { Ok(guard) => guard, Err(p) => p.into_inner() };
+    if let Err(e) = catalog_guard.save(&catalog_path) {
+        warn!("Failed to save the volume catalog {}: {}", catalog_path.display(), e);
+    }
+}
+
+/// Polls the mounted volumes once the saved index is loaded. Whenever they change, unplugged volumes go
+/// offline and reattached ones are re-synced, then `volumes_changed` is emitted with what happened.
+fn watch_volumes(
+    handle: AppHandle,
+    files_arc: Arc<Mutex<Vec<FileMetadata>>>,
+    indexed_paths: Arc<IndexedPaths>,
+    catalog: Arc<Mutex<VolumeCatalog>>,
+    volume_sync: Arc<tokio::sync::Mutex<()>>,
+    loaded: Option<tauri::async_runtime::JoinHandle<()>>,
+) {
+    tauri::async_runtime::spawn(async move {
+        if let Some(loaded) = loaded {
+            let _ = loaded.await;
+        }
+        let mut last_mounted: Option<Vec<Volume>> = None;
+        loop {
+            let mounted = tauri::async_runtime::spawn_blocking(volumes::mounted_volumes).await.unwrap_or_default();
+            // A running scan rewrites the file list; try again on the next poll once it's done.
+            let sync_guard = if last_mounted.as_ref() != Some(&mounted) { volume_sync.try_lock().ok() } else { None };
+            if let Some(_sync_guard) = sync_guard {
+                let (task_files, task_paths, task_catalog, task_mounted) = (files_arc.clone(), indexed_paths.clone(), catalog.clone(), mounted.clone());
+                let changes = tauri::async_runtime::spawn_blocking(move || {
+                    // Re-syncing rescans whole volumes, so neither the file list nor the catalog stays locked meanwhile.
+                    let mut updated = match task_catalog.lock() { Ok(guard) => guard.clone(), Err(p) => p.into_inner().clone() };
+                    let changes = updated.refresh(&task_files, &task_mounted, &ScanOptions::default());
+                    *match task_catalog.lock() { Ok(guard) => guard, Err(p) => p.into_inner() } = updated;
+                    task_paths.rebuild(&match task_files.lock() { Ok(guard) => guard, Err(p) => p.into_inner() });
+                    changes
+                })
+                .await
+                .unwrap_or_default();
+                save_volume_catalog(&catalog);
+                persist_shared_index(files_arc.clone()).await;
+                if !changes.is_empty() {
+                    info!("Volumes changed: {:?}", changes);
+                    let _ = handle.emit("volumes_changed", &changes);
+                }
+                last_mounted = Some(mounted);
+            }
+            tokio::time::sleep(VOLUME_POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// Listens on the IPC socket; a second instance just runs without one.
+#[cfg(unix)]
+fn start_ipc_server(files: Arc<Mutex<Vec<FileMetadata>>>) -> Option<IpcServer> {
+    let ctx = IpcContext {
+        files,
+        on_open: Box::new(|path, action| {
+            match action {
+                OpenAction::Open => desktop::open(path),
+                OpenAction::Reveal => desktop::reveal(path),
+            }
+            .map_err(|e| e.to_string())
+        }),
+    };
+    let socket = ipc::default_socket_path();
+    match IpcServer::start(&socket, Arc::new(ctx)) {
+        Ok(server) => {
+            info!("IPC listening on {}", socket.display());
+            Some(server)
+        }
+        Err(e) => {
+            warn!("IPC disabled, cannot listen on {}: {}", socket.display(), e);
+            None
+        }
+    }
 }
 
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
         .init();
 
+    let extractors = Arc::new(ExtractorRegistry::with_defaults());
+    let files = Arc::new(Mutex::new(Vec::with_capacity(1_000_000)));
+    let indexed_paths = Arc::new(IndexedPaths::default());
+    let index_loaded = load_persisted_index(files.clone(), indexed_paths.clone());
+    let volume_catalog = Arc::new(Mutex::new(load_volume_catalog()));
+    // Launchers and other local tools query the running app through this; it stays open until `run` returns.
+    #[cfg(unix)]
+    let _ipc_server = start_ipc_server(files.clone());
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(AppState {
-            files: Arc::new(Mutex::new(Vec::with_capacity(1_000_000))),
-            file_preview: Arc::new(FilePreview::new()),
+            files,
+            indexed_paths,
+            file_preview: Arc::new(FilePreview::new().with_extractors(extractors.clone())),
+            extractors,
+            content_index: Arc::new(Mutex::new(None)),
+            content_watcher: Mutex::new(None),
+            next_search_id: AtomicU64::new(0),
+            active_content_search: Mutex::new(None),
+            allowed_roots: Mutex::new(Vec::new()),
+            scan_running: Arc::new(AtomicBool::new(false)),
+            http_server: Mutex::new(None),
+            volume_catalog,
+            volume_sync: Arc::new(tokio::sync::Mutex::new(())),
+        })
+        .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let state = app.state::<AppState>();
+                let thumbnailer = app.state::<Thumbnailer>();
+                let is_allowed = |path: &Path| is_servable(&state, path);
+                let protocol_ctx = protocol::ProtocolContext {
+                    preview: &state.file_preview,
+                    thumbnailer: &thumbnailer,
+                    is_allowed: &is_allowed,
+                };
+                let response = protocol::respond(&request, &protocol_ctx);
+                if !response.status().is_success() {
+                    debug!("{} request {} -> {}", protocol::SCHEME, request.uri(), response.status());
+                }
+                responder.respond(response);
+            });
         })
         .invoke_handler(tauri::generate_handler![
             preview_file,
+            preview_range,
+            preview_source,
+            preview_hex,
+            thumbnail,
             highlight_content,
             basic_search,
             advanced_search,
+            export_results,
+            import_file_list,
+            remove_file_list,
+            list_file_lists,
+            export_file_list,
+            list_volumes,
+            forget_volume,
+            open_file,
+            reveal_file,
+            copy_paths,
+            rename_file,
+            move_files,
+            trash_files,
+            preview_rename,
+            apply_rename,
+            undo_rename,
+            content_search,
+            start_content_search,
+            cancel_content_search,
             scan_directory,
+            set_allowed_roots,
+            start_http_server,
+            stop_http_server,
+            build_content_index,
+            clear_content_index,
+            search_content_index,
         ])
         .setup(|app| {
+            let thumbnail_dir = app.path().app_cache_dir()?.join("thumbnails");
+            app.manage(Thumbnailer::new(thumbnail_dir));
+
+            let state = app.state::<AppState>();
+            watch_volumes(app.handle().clone(), state.files.clone(), state.indexed_paths.clone(), state.volume_catalog.clone(), state.volume_sync.clone(), index_loaded);
+
             let main_window = app.get_webview_window("main").ok_or("Main window not found")?;
             #[cfg(debug_assertions)]
             main_window.open_devtools();